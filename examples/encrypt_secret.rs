@@ -0,0 +1,21 @@
+//! Encrypt a secret for a `<NAME>_ENC` variable, e.g. `PFX_PASSWORD_ENC`.
+//! The key comes from `ENC_KEY` or `ENC_KEY_FILE`, as at startup:
+//!
+//! ```sh
+//! ENC_KEY=$(openssl rand -base64 32) cargo run --example encrypt_secret < password.txt
+//! ```
+
+use plexo_back::services::secrets;
+use std::io::Read;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let key = secrets::encryption_key()?;
+
+    let mut plaintext = String::new();
+    std::io::stdin().read_to_string(&mut plaintext)?;
+    // Files and shells usually leave a trailing newline that is not part of the secret
+    let plaintext = plaintext.trim_end_matches(['\r', '\n']);
+
+    println!("{}", secrets::encrypt_secret(plaintext, &key)?);
+    Ok(())
+}
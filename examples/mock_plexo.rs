@@ -0,0 +1,25 @@
+//! Run the mock Plexo gateway standalone:
+//!
+//! ```sh
+//! cargo run --example mock_plexo --features mock-plexo
+//! PLEXO_BASE_URL=http://127.0.0.1:9090 cargo run
+//! ```
+
+use plexo_back::services::mock_plexo;
+
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
+
+    let addr = std::env::var("MOCK_PLEXO_ADDR").unwrap_or_else(|_| "127.0.0.1:9090".to_string());
+    let addr = addr
+        .parse()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+
+    let (bound, handle) = mock_plexo::start(addr)?;
+    log::info!("Mock Plexo listening on http://{}", bound);
+
+    tokio::signal::ctrl_c().await?;
+    handle.stop(true).await;
+    Ok(())
+}
@@ -0,0 +1,134 @@
+use crate::models::requests::RotateCertRequest;
+use crate::models::responses::{ApiResponse, CertInfo};
+use crate::services::crypto;
+use crate::services::middleware::{RateLimitUsage, ServiceRateLimit};
+use crate::services::recent_requests::{self, RecentRequest};
+use actix_web::{web, HttpRequest, HttpResponse, Result as ActixResult};
+use lazy_static::lazy_static;
+use log::{info, warn};
+use subtle::ConstantTimeEq;
+
+pub const ADMIN_KEY_HEADER: &str = "x-admin-key";
+
+lazy_static! {
+    // ADMIN_KEY guards /api/admin; the admin API is disabled while it is unset
+    static ref ADMIN_KEY: Option<String> = std::env::var("ADMIN_KEY")
+        .ok()
+        .filter(|key| !key.is_empty());
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/rotate-cert",
+    tag = "admin",
+    description = "Replace the default signing certificate without a restart",
+    request_body = RotateCertRequest,
+    params(("x-admin-key" = String, Header, description = "Admin key, distinct from the service key")),
+    responses(
+        (status = 200, body = ApiResponse<CertInfo>),
+        (status = 400, description = "The PFX could not be loaded or is not currently valid"),
+        (status = 401, description = "Missing or wrong admin key"),
+        (status = 403, description = "Admin API disabled, ADMIN_KEY is not set"),
+    )
+)]
+pub async fn rotate_cert(
+    http_request: HttpRequest,
+    request: web::Json<RotateCertRequest>,
+) -> ActixResult<HttpResponse> {
+    if let Err(response) = authorize_admin(&http_request) {
+        return Ok(response);
+    }
+
+    match crypto::rotate_certificate(&request.pfx_base64, &request.pfx_password) {
+        Ok(cert_info) => {
+            info!("Signing certificate rotated through the admin API");
+            Ok(HttpResponse::Ok().json(ApiResponse::ok(cert_info)))
+        }
+        Err(e) => {
+            warn!("Certificate rotation rejected: {}", e);
+            Ok(admin_error(
+                HttpResponse::BadRequest(),
+                &format!("Certificate rotation failed: {}", e),
+            ))
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/recent",
+    tag = "admin",
+    description = "The last signed payloads, newest first, with card, instrument and personal fields redacted",
+    params(("x-admin-key" = String, Header, description = "Admin key, distinct from the service key")),
+    responses(
+        (status = 200, body = ApiResponse<Vec<RecentRequest>>),
+        (status = 401, description = "Missing or wrong admin key"),
+        (status = 403, description = "Admin API disabled, ADMIN_KEY is not set"),
+        (status = 404, description = "Recording is disabled, RECENT_REQUESTS_CAPACITY is not set"),
+    )
+)]
+pub async fn recent(http_request: HttpRequest) -> ActixResult<HttpResponse> {
+    if let Err(response) = authorize_admin(&http_request) {
+        return Ok(response);
+    }
+
+    let Some(recent) = recent_requests::recent_requests() else {
+        return Ok(admin_error(
+            HttpResponse::NotFound(),
+            "Recent request recording is disabled",
+        ));
+    };
+
+    Ok(HttpResponse::Ok().json(ApiResponse::ok(recent.snapshot())))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/ratelimit",
+    tag = "admin",
+    description = "Requests each caller has made within the current rate-limit window, busiest first",
+    params(("x-admin-key" = String, Header, description = "Admin key, distinct from the service key")),
+    responses(
+        (status = 200, body = ApiResponse<Vec<RateLimitUsage>>),
+        (status = 401, description = "Missing or wrong admin key"),
+        (status = 403, description = "Admin API disabled, ADMIN_KEY is not set"),
+    )
+)]
+pub async fn rate_limit(
+    http_request: HttpRequest,
+    rate_limit: web::Data<ServiceRateLimit>,
+) -> ActixResult<HttpResponse> {
+    if let Err(response) = authorize_admin(&http_request) {
+        return Ok(response);
+    }
+
+    Ok(HttpResponse::Ok().json(ApiResponse::ok(rate_limit.snapshot())))
+}
+
+fn authorize_admin(http_request: &HttpRequest) -> Result<(), HttpResponse> {
+    let Some(expected) = ADMIN_KEY.as_deref() else {
+        return Err(admin_error(
+            HttpResponse::Forbidden(),
+            "Admin API is disabled",
+        ));
+    };
+
+    let provided = http_request
+        .headers()
+        .get(ADMIN_KEY_HEADER)
+        .map(|value| value.as_bytes())
+        .unwrap_or_default();
+    if provided.ct_eq(expected.as_bytes()).unwrap_u8() == 1 {
+        Ok(())
+    } else {
+        warn!("Rejected admin request with a missing or wrong admin key");
+        Err(admin_error(
+            HttpResponse::Unauthorized(),
+            "Invalid admin key",
+        ))
+    }
+}
+
+fn admin_error(mut builder: actix_web::HttpResponseBuilder, message: &str) -> HttpResponse {
+    builder.json(ApiResponse::<()>::error(message))
+}
@@ -0,0 +1,203 @@
+use crate::api::admin_controller::{rate_limit, recent, rotate_cert, ADMIN_KEY_HEADER};
+use crate::api::cert_controller::cert_info;
+use crate::api::health_controller::ready;
+use crate::api::metrics_controller::metrics;
+use crate::api::openapi::ApiDoc;
+use crate::api::plexo_controller::{
+    authorize, commerce_info, delete_instrument, purchase, purchase_async, purchase_async_status,
+    register_instrument, status, status_batch_stream, transaction_history, validate,
+};
+use crate::models::responses::ApiResponse;
+use crate::services::middleware::{ServiceAuthConfig, ServiceAuthMiddleware};
+use crate::services::plexo_service::PlexoClient;
+use crate::services::response_signing;
+use actix_cors::Cors;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceFactory, ServiceRequest, ServiceResponse};
+use actix_web::error::{InternalError, JsonPayloadError};
+use actix_web::http::header::HeaderName;
+use actix_web::http::StatusCode;
+use actix_web::{middleware, web, App, Error, HttpResponse, ResponseError};
+use log::warn;
+use std::sync::Arc;
+use utoipa::OpenApi;
+use utoipa_swagger_ui::SwaggerUi;
+
+// Plexo payloads are a few KB, anything near this is a mistake or an attack
+const DEFAULT_MAX_JSON_BODY_BYTES: usize = 256 * 1024;
+
+/// Everything the HTTP app is built from. The server fills it from the
+/// environment; tests can pass a client pointed at a mock and their own keys.
+#[derive(Clone)]
+pub struct AppConfig {
+    plexo_client: Arc<PlexoClient>,
+    auth: ServiceAuthConfig,
+    allowed_origins: Vec<String>,
+    max_body_bytes: usize,
+}
+
+impl AppConfig {
+    /// No cross-origin access and the default body limit
+    pub fn new(plexo_client: Arc<PlexoClient>, auth: ServiceAuthConfig) -> Self {
+        Self {
+            plexo_client,
+            auth,
+            allowed_origins: Vec::new(),
+            max_body_bytes: DEFAULT_MAX_JSON_BODY_BYTES,
+        }
+    }
+
+    /// Origins allowed by CORS; a single `*` allows any
+    pub fn with_allowed_origins(mut self, allowed_origins: Vec<String>) -> Self {
+        self.allowed_origins = allowed_origins;
+        self
+    }
+
+    /// Largest JSON body accepted, larger ones get 413
+    pub fn with_max_body_bytes(mut self, max_body_bytes: usize) -> Self {
+        self.max_body_bytes = max_body_bytes;
+        self
+    }
+}
+
+/// The full application: every route with auth, logging, CORS and body
+/// limits, as served by the binary. Call once per worker, or hand it to
+/// `actix_web::test::init_service` to exercise the real request path.
+pub fn build_app(
+    config: AppConfig,
+) -> App<
+    impl ServiceFactory<
+        ServiceRequest,
+        Config = (),
+        Response = ServiceResponse<impl MessageBody>,
+        Error = Error,
+        InitError = (),
+    >,
+> {
+    let rate_limit_state = web::Data::new(config.auth.rate_limit().clone());
+    let cors = build_cors(&config.allowed_origins, config.auth.header_name());
+
+    App::new()
+        .app_data(web::Data::from(config.plexo_client))
+        .app_data(rate_limit_state)
+        .wrap(ServiceAuthMiddleware::new(config.auth))
+        .wrap(middleware::Logger::default())
+        .wrap(cors)
+        // Register API routes
+        .service(
+            web::scope("/api")
+                .app_data(json_config(config.max_body_bytes))
+                .wrap(middleware::Condition::new(
+                    response_signing::enabled(),
+                    middleware::from_fn(response_signing::sign_response),
+                ))
+                .route("/authorize", web::post().to(authorize))
+                .route("/purchase", web::post().to(purchase))
+                .route("/purchase/async", web::post().to(purchase_async))
+                .route(
+                    "/purchase/async/{job_id}",
+                    web::get().to(purchase_async_status),
+                )
+                .route("/status", web::post().to(status))
+                .route("/status/batch/stream", web::post().to(status_batch_stream))
+                .route("/commerce/{id}", web::get().to(commerce_info))
+                .route("/transactions", web::get().to(transaction_history))
+                .route("/instrument", web::post().to(register_instrument))
+                .route("/instrument", web::delete().to(delete_instrument))
+                .route("/validate", web::post().to(validate))
+                .route("/cert-info", web::get().to(cert_info))
+                .route("/admin/rotate-cert", web::post().to(rotate_cert))
+                .route("/admin/recent", web::get().to(recent))
+                .route("/admin/ratelimit", web::get().to(rate_limit)),
+        )
+        // API documentation
+        .service(SwaggerUi::new("/docs/{_:.*}").url("/openapi.json", ApiDoc::openapi()))
+        // Prometheus scrape endpoint
+        .route("/metrics", web::get().to(metrics))
+        // Add a health check endpoint
+        .route(
+            "/health",
+            web::get().to(|| async { HttpResponse::Ok().body("Service is running") }),
+        )
+        // Readiness probe for orchestrators
+        .route("/ready", web::get().to(ready))
+}
+
+/// Reject oversized bodies with 413 before they are deserialized, and
+/// report bodies that fail to deserialize in the `ApiResponse` envelope
+fn json_config(limit: usize) -> web::JsonConfig {
+    web::JsonConfig::default()
+        .limit(limit)
+        // Bodies without a JSON Content-Type are refused rather than parsed anyway
+        .content_type_required(true)
+        .error_handler(move |err, _req| match err {
+            JsonPayloadError::OverflowKnownLength { .. } | JsonPayloadError::Overflow { .. } => {
+                let response = HttpResponse::PayloadTooLarge().json(ApiResponse::<()>::error(
+                    format!("Request body exceeds {} bytes", limit),
+                ));
+                InternalError::from_response(err, response).into()
+            }
+            // actix answers 400 for these; a form or text body is the wrong media type
+            JsonPayloadError::ContentType => {
+                let response = HttpResponse::UnsupportedMediaType().json(ApiResponse::<()> {
+                    error_code: Some("UNSUPPORTED_MEDIA_TYPE".to_string()),
+                    ..ApiResponse::error("Content-Type must be application/json")
+                });
+                InternalError::from_response(err, response).into()
+            }
+            err => {
+                // Keep actix's status but answer in the envelope; serde names
+                // the missing or unknown field and the line and column it gave up at
+                let status = err.status_code();
+                let detail = match &err {
+                    JsonPayloadError::Deserialize(e) => format!("Invalid request body: {}", e),
+                    err => err.to_string(),
+                };
+                let response = HttpResponse::build(status).json(ApiResponse::<()> {
+                    error_code: (status == StatusCode::BAD_REQUEST)
+                        .then(|| "BAD_REQUEST".to_string()),
+                    ..ApiResponse::error(detail)
+                });
+                InternalError::from_response(err, response).into()
+            }
+        })
+}
+
+/// Build the CORS layer. An empty list denies every cross-origin request and
+/// a single `*` entry allows any origin (intended for local development only).
+fn build_cors(allowed_origins: &[String], service_header: &HeaderName) -> Cors {
+    let cors = Cors::default()
+        .allowed_methods(vec!["GET", "POST", "PUT", "PATCH", "DELETE"])
+        .allowed_headers(vec![
+            actix_web::http::header::AUTHORIZATION,
+            actix_web::http::header::ACCEPT,
+            actix_web::http::header::CONTENT_TYPE,
+            actix_web::http::header::CACHE_CONTROL,
+        ])
+        .allowed_header("x-timeout-ms")
+        .allowed_header("x-allow-duplicate")
+        .allowed_header("x-signature")
+        .allowed_header("x-timestamp")
+        .allowed_header("x-correlation-id")
+        .allowed_header("x-include-raw")
+        .allowed_header("idempotency-key")
+        .allowed_header("x-void-authorization")
+        .allowed_header(ADMIN_KEY_HEADER)
+        // Preflight requests must be allowed to announce the service key header
+        .allowed_header(service_header.clone())
+        .expose_headers(vec![
+            "x-correlation-id",
+            "idempotent-replayed",
+            response_signing::RESPONSE_SIGNATURE_HEADER,
+        ])
+        .max_age(3600);
+
+    if allowed_origins.iter().any(|origin| origin == "*") {
+        warn!("CORS is configured to allow any origin");
+        return cors.allow_any_origin();
+    }
+
+    allowed_origins
+        .iter()
+        .fold(cors, |cors, origin| cors.allowed_origin(origin))
+}
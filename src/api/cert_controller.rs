@@ -0,0 +1,25 @@
+use crate::models::responses::{ApiResponse, CertInfo};
+use crate::services::crypto::get_crypto_service;
+use actix_web::{HttpResponse, Result as ActixResult};
+use log::error;
+
+#[utoipa::path(
+    get,
+    path = "/api/cert-info",
+    tag = "operations",
+    description = "Details of the certificate used to sign Plexo requests",
+    responses(
+        (status = 200, body = ApiResponse<CertInfo>),
+        (status = 503, description = "Crypto service not initialized"),
+    ),
+    security(("service_key" = []))
+)]
+pub async fn cert_info() -> ActixResult<HttpResponse> {
+    match get_crypto_service() {
+        Ok(service) => Ok(HttpResponse::Ok().json(ApiResponse::ok(service.cert_info().clone()))),
+        Err(e) => {
+            error!("Error reading certificate info: {}", e);
+            Ok(HttpResponse::ServiceUnavailable().json(ApiResponse::<()>::error(e.to_string())))
+        }
+    }
+}
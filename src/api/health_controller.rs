@@ -0,0 +1,40 @@
+use crate::models::responses::{ApiResponse, Readiness};
+use crate::services::crypto::get_crypto_service;
+use crate::services::plexo_service;
+use actix_web::{HttpResponse, Result as ActixResult};
+use log::warn;
+
+#[utoipa::path(
+    get,
+    path = "/ready",
+    tag = "operations",
+    description = "Readiness probe, 503 until signing and the Plexo client are usable and while the signing certificate is expired",
+    responses(
+        (status = 200, body = ApiResponse<Readiness>),
+        (status = 503, body = ApiResponse<Readiness>),
+    )
+)]
+pub async fn ready() -> ActixResult<HttpResponse> {
+    // Read from the CertInfo captured when the certificate was loaded
+    let crypto_service = get_crypto_service().ok();
+    let not_after = crypto_service
+        .as_ref()
+        .map(|service| service.cert_info().not_after);
+    let readiness = Readiness {
+        crypto_service: crypto_service.is_some(),
+        plexo_client: plexo_service::default_client().is_ok(),
+        certificate_valid: not_after.is_some_and(|not_after| not_after > chrono::Utc::now()),
+        certificate_not_after: not_after,
+    };
+
+    if readiness.crypto_service && readiness.plexo_client && readiness.certificate_valid {
+        return Ok(HttpResponse::Ok().json(ApiResponse::ok(readiness)));
+    }
+
+    warn!("Readiness check failed: {:?}", readiness);
+    Ok(HttpResponse::ServiceUnavailable().json(ApiResponse {
+        data: Some(readiness),
+        error: Some("Service is not ready".to_string()),
+        ..Default::default()
+    }))
+}
@@ -0,0 +1,15 @@
+use crate::services::metrics;
+use actix_web::{HttpResponse, Result as ActixResult};
+use log::error;
+
+pub async fn metrics() -> ActixResult<HttpResponse> {
+    match metrics::render() {
+        Ok(body) => Ok(HttpResponse::Ok()
+            .content_type("text/plain; version=0.0.4")
+            .body(body)),
+        Err(e) => {
+            error!("Failed to render metrics: {}", e);
+            Ok(HttpResponse::InternalServerError().finish())
+        }
+    }
+}
@@ -1 +1,7 @@
+pub mod admin_controller;
+pub mod app;
+pub mod cert_controller;
+pub mod health_controller;
+pub mod metrics_controller;
+pub mod openapi;
 pub mod plexo_controller;
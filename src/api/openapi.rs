@@ -0,0 +1,43 @@
+use crate::api::{admin_controller, cert_controller, health_controller, plexo_controller};
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+/// OpenAPI document for the public API, served at `/openapi.json`
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Plexo payments API"),
+    paths(
+        plexo_controller::authorize,
+        plexo_controller::purchase,
+        plexo_controller::purchase_async,
+        plexo_controller::purchase_async_status,
+        plexo_controller::status,
+        plexo_controller::status_batch_stream,
+        plexo_controller::commerce_info,
+        plexo_controller::transaction_history,
+        plexo_controller::register_instrument,
+        plexo_controller::delete_instrument,
+        plexo_controller::validate,
+        cert_controller::cert_info,
+        health_controller::ready,
+        admin_controller::rotate_cert,
+        admin_controller::recent,
+        admin_controller::rate_limit,
+    ),
+    modifiers(&ServiceKeyScheme)
+)]
+pub struct ApiDoc;
+
+/// Documents the service key header. The header name is configurable, so the
+/// default is shown here.
+struct ServiceKeyScheme;
+
+impl Modify for ServiceKeyScheme {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "service_key",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("x-service-key"))),
+        );
+    }
+}
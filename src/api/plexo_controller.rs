@@ -1,110 +1,1315 @@
-use crate::models::requests::{AuthorizationRequest, PaymentRequest, StatusRequest};
-use crate::models::responses::ApiResponse;
-use crate::services::plexo_service::{self, PlexoServiceError};
-use actix_web::{web, HttpResponse, Result as ActixResult};
-use log::{error, info};
+use crate::models::requests::{
+    AuthorizationRequest, BatchStatusRequest, CommerceInfoRequest, CommerceInfoRequestData,
+    DeleteInstrumentRequest, PaymentRequest, RegisterInstrumentRequest, StatusQuery, StatusRequest,
+    TransactionHistoryRequest, TransactionHistoryRequestData,
+};
+use crate::models::responses::{
+    ApiResponse, AuthorizationVoid, BatchStatusLine, CanonicalPreview, CommerceInfo,
+    DeleteInstrumentResponse, OperationResult, Outcome, RawResponse, RegisterInstrumentResponse,
+    ThreeDSChallenge, TransactionHistory,
+};
+use crate::services::audit::{self, AuditRecord};
+use crate::services::crypto::CryptoError;
+use crate::services::idempotency::{self, Reservation, StoredResponse};
+use crate::services::middleware::ServiceIdentity;
+use crate::services::plexo_service::{
+    self, AuthorizationVoidResult, Operation, PlexoClient, PlexoServiceError, RequestOptions,
+};
+use crate::services::projection;
+use crate::services::purchase_queue::{
+    self, JobState, JobStatus, PurchaseJob, PurchaseQueue, QueueError,
+};
+use crate::services::redaction;
+use crate::services::validation;
+use crate::services::webhook::{self, ResultNotification};
+use actix_web::{
+    body::{BoxBody, MessageBody},
+    http::header::{self, ContentType, HeaderName, HeaderValue},
+    http::StatusCode,
+    web, HttpRequest, HttpResponse, Result as ActixResult,
+};
+use futures_util::stream::{self, StreamExt};
+use lazy_static::lazy_static;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+use utoipa::IntoParams;
+use uuid::Uuid;
+
+const TIMEOUT_HEADER: &str = "x-timeout-ms";
+const ALLOW_DUPLICATE_HEADER: &str = "x-allow-duplicate";
+const CORRELATION_ID_HEADER: &str = "x-correlation-id";
+const INCLUDE_RAW_HEADER: &str = "x-include-raw";
+const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+const IDEMPOTENT_REPLAY_HEADER: &str = "idempotent-replayed";
+const VOID_AUTHORIZATION_HEADER: &str = "x-void-authorization";
+const THREE_DS_REQUIRED: &str = "3DS_REQUIRED";
+
+lazy_static! {
+    // Echoing Plexo's raw response is a debugging aid, off unless ENABLE_RAW_RESPONSE is set
+    static ref RAW_RESPONSE_ENABLED: bool = std::env::var("ENABLE_RAW_RESPONSE")
+        .map(|value| value.eq_ignore_ascii_case("true") || value == "1")
+        .unwrap_or(false);
+
+    // Version served when the client doesn't ask for one, from DEFAULT_API_VERSION.
+    // Defaults to the latest; pin to 1 while clients migrate.
+    static ref DEFAULT_API_VERSION: ApiVersion = std::env::var("DEFAULT_API_VERSION")
+        .ok()
+        .and_then(|value| ApiVersion::parse(&value))
+        .unwrap_or(ApiVersion::V2);
+    // Lookups accepted in one streamed batch, from MAX_BATCH_STATUS_SIZE
+    static ref MAX_BATCH_STATUS_SIZE: usize = env_usize("MAX_BATCH_STATUS_SIZE", 10_000);
+    // Lookups of one batch sent to Plexo at once, from BATCH_STATUS_CONCURRENCY
+    static ref BATCH_STATUS_CONCURRENCY: usize = env_usize("BATCH_STATUS_CONCURRENCY", 8);
+    // Dry runs expose signed payloads, so they must be enabled explicitly
+    static ref DRY_RUN_ENABLED: bool = std::env::var("ENABLE_DRY_RUN")
+        .map(|value| value.eq_ignore_ascii_case("true") || value == "1")
+        .unwrap_or(false);
+}
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct OperationQuery {
+    /// Return the signed envelope instead of calling Plexo (requires ENABLE_DRY_RUN)
+    #[serde(rename = "dryRun", default)]
+    pub dry_run: bool,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/authorize",
+    tag = "plexo",
+    description = "Create a Plexo authorization session",
+    request_body = AuthorizationRequest,
+    params(OperationQuery),
+    responses(
+        (status = 200, body = ApiResponse<serde_json::Value>),
+        (status = 400, description = "Invalid request"),
+        (status = 502, description = "Plexo could not be reached"),
+        (status = 504, description = "Plexo did not answer in time"),
+    ),
+    security(("service_key" = []))
+)]
+pub async fn authorize(
+    http_request: HttpRequest,
+    client: web::Data<PlexoClient>,
+    request: web::Json<AuthorizationRequest>,
+    query: web::Query<OperationQuery>,
+) -> ActixResult<HttpResponse> {
+    if query.dry_run {
+        return Ok(dry_run_response(&request.into_inner()));
+    }
 
-pub async fn authorize(request: web::Json<AuthorizationRequest>) -> ActixResult<HttpResponse> {
     info!("Received authorization request");
+    let context = OperationContext::new(
+        &http_request,
+        Operation::Authorize,
+        Some(request.Request.MetaReference.clone()),
+    );
 
-    match plexo_service::send_authorization_request(request.into_inner()).await {
+    match client
+        .authorize(
+            request.into_inner(),
+            request_options(&http_request).with_correlation_id(&context.correlation_id),
+        )
+        .await
+    {
         Ok(response) => {
             info!("Successfully processed authorization request");
-            Ok(HttpResponse::Ok().json(ApiResponse {
-                success: true,
-                data: Some(response),
-                error: None,
-            }))
+            Ok(success_response(&context, response))
         }
         Err(e) => {
-            error!("Error processing authorization request: {:?}", e);
+            log_operation_error("authorization", &e);
+            Ok(failure_response(&context, e))
+        }
+    }
+}
 
-            let status_code = match e {
-                PlexoServiceError::Timeout => actix_web::http::StatusCode::GATEWAY_TIMEOUT,
-                PlexoServiceError::HttpRequestError(_) => actix_web::http::StatusCode::BAD_GATEWAY,
-                PlexoServiceError::SerializationError(_) => {
-                    actix_web::http::StatusCode::BAD_REQUEST
+#[utoipa::path(
+    post,
+    path = "/api/purchase",
+    tag = "plexo",
+    description = "Submit a purchase to Plexo",
+    request_body = PaymentRequest,
+    params(
+        OperationQuery,
+        ("x-void-authorization" = Option<String>, Header, description = "MetaReference of the authorization to cancel if Plexo declines the purchase, when a cancel contract is configured; what became of it is reported in authorization_void"),
+    ),
+    responses(
+        (status = 200, body = ApiResponse<serde_json::Value>),
+        (status = 202, body = ApiResponse<ThreeDSChallenge>, description = "3-D Secure challenge required, error_code is 3DS_REQUIRED"),
+        (status = 400, description = "Invalid request"),
+        (status = 409, description = "A request with the same Idempotency-Key is still running"),
+        (status = 422, description = "The Idempotency-Key was already used with a different body"),
+        (status = 502, description = "Plexo could not be reached"),
+        (status = 504, description = "Plexo did not answer in time"),
+    ),
+    security(("service_key" = []))
+)]
+pub async fn purchase(
+    http_request: HttpRequest,
+    client: web::Data<PlexoClient>,
+    identity: ServiceIdentity,
+    request: web::Json<PaymentRequest>,
+    query: web::Query<OperationQuery>,
+) -> ActixResult<HttpResponse> {
+    if query.dry_run {
+        return Ok(dry_run_response(&request.into_inner()));
+    }
+
+    info!("Received payment request");
+    let reservation = match idempotency_key(&http_request) {
+        Some(key) => {
+            let key = idempotency::scoped_key(identity.name(), &key);
+            let body = serde_json::to_vec(&*request)
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+            let fingerprint = idempotency::fingerprint(&body);
+            match idempotency::store()
+                .reserve(&key, &fingerprint, idempotency::in_flight_ttl())
+                .await
+            {
+                Reservation::Reserved => Some((key, fingerprint)),
+                Reservation::Completed(stored) => {
+                    info!("Replaying stored response for idempotency key");
+                    return Ok(replay_response(stored));
+                }
+                Reservation::InFlight => {
+                    return Ok(idempotency_conflict(
+                        StatusCode::CONFLICT,
+                        "IDEMPOTENCY_IN_FLIGHT",
+                        "A request with this idempotency key is still being processed",
+                    ))
+                }
+                Reservation::Mismatch => {
+                    return Ok(idempotency_conflict(
+                        StatusCode::UNPROCESSABLE_ENTITY,
+                        "IDEMPOTENCY_KEY_REUSED",
+                        "This idempotency key was used with a different request body",
+                    ))
                 }
-                PlexoServiceError::SigningError(_) => {
-                    actix_web::http::StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+        None => None,
+    };
+
+    let context = OperationContext::new(
+        &http_request,
+        Operation::Purchase,
+        Some(request.Request.ClientReferenceId.clone()),
+    );
+
+    let options = request_options(&http_request).with_correlation_id(&context.correlation_id);
+    let (result, authorization_void) = match void_authorization(&http_request) {
+        Some(authorization) => {
+            let reference = authorization.reference().to_string();
+            let compensated = client
+                .purchase_or_void(request.into_inner(), authorization, options)
+                .await;
+            let authorization_void = match compensated.authorization {
+                AuthorizationVoidResult::NotNeeded => None,
+                AuthorizationVoidResult::Attempted(result) => {
+                    Some(voided_authorization(&context, reference, result))
                 }
+                AuthorizationVoidResult::LeftOpen => Some(AuthorizationVoid::left_open(reference)),
             };
+            (compensated.purchase, authorization_void)
+        }
+        None => (client.purchase(request.into_inner(), options).await, None),
+    };
+    let reached_plexo = match &result {
+        Ok(_) => true,
+        Err(e) => plexo_service::may_have_reached_plexo(e),
+    };
+    let response = purchase_response(&context, result, authorization_void);
 
-            Ok(HttpResponse::build(status_code).json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                error: Some(e.to_string()),
-            }))
+    match reservation {
+        Some((key, fingerprint)) => {
+            remember_response(&key, fingerprint, response, reached_plexo).await
         }
+        None => Ok(response),
     }
 }
 
-pub async fn purchase(request: web::Json<PaymentRequest>) -> ActixResult<HttpResponse> {
-    info!("Received payment request");
+// The authorization a declined purchase should cancel, named by its MetaReference
+fn void_authorization(http_request: &HttpRequest) -> Option<StatusQuery> {
+    http_request
+        .headers()
+        .get(VOID_AUTHORIZATION_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|reference| !reference.is_empty())
+        .map(|reference| StatusQuery::ByClientReference(reference.to_string()))
+}
 
-    match plexo_service::send_payment_request(request.into_inner()).await {
+// The cancel is audited as its own operation under the purchase's correlation id
+fn voided_authorization(
+    context: &OperationContext,
+    reference: String,
+    result: Result<Value, PlexoServiceError>,
+) -> AuthorizationVoid {
+    let cancel_context = OperationContext {
+        correlation_id: context.correlation_id.clone(),
+        operation: Operation::Cancel,
+        client_reference: Some(reference.clone()),
+        include_raw: false,
+        api_version: context.api_version,
+    };
+    match &result {
+        Ok(response) => cancel_context.audit(
+            StatusCode::OK,
+            response.get("ResultCode").and_then(Value::as_i64),
+        ),
+        Err(e) => {
+            log_operation_error("cancel", e);
+            cancel_context.audit(error_status(e), None);
+        }
+    }
+    AuthorizationVoid::attempted(reference, &result)
+}
+
+// Shared by synchronous and queued purchases
+fn purchase_response(
+    context: &OperationContext,
+    result: Result<Value, PlexoServiceError>,
+    authorization_void: Option<AuthorizationVoid>,
+) -> HttpResponse {
+    match result {
         Ok(response) => {
-            info!("Successfully processed payment request");
-            Ok(HttpResponse::Ok().json(ApiResponse {
-                success: true,
-                data: Some(response),
-                error: None,
-            }))
+            if let Some(challenge) = ThreeDSChallenge::from_response(&response) {
+                info!("Payment requires a 3-D Secure challenge");
+                challenge_response(context, &response, challenge)
+            } else {
+                info!("Successfully processed payment request");
+                success_response_with_void(context, response, authorization_void)
+            }
         }
         Err(e) => {
-            error!("Error processing payment request: {}", e);
+            log_operation_error("payment", &e);
+            failure_response_with_void(context, e, authorization_void)
+        }
+    }
+}
 
-            let status_code = match e {
-                PlexoServiceError::Timeout => actix_web::http::StatusCode::GATEWAY_TIMEOUT,
-                PlexoServiceError::HttpRequestError(_) => actix_web::http::StatusCode::BAD_GATEWAY,
-                PlexoServiceError::SerializationError(_) => {
-                    actix_web::http::StatusCode::BAD_REQUEST
-                }
-                PlexoServiceError::SigningError(_) => {
-                    actix_web::http::StatusCode::INTERNAL_SERVER_ERROR
-                }
-            };
+#[utoipa::path(
+    post,
+    path = "/api/purchase/async",
+    tag = "plexo",
+    description = "Validate and queue a purchase, answering at once with a job id. The result is delivered to the result webhook and can be polled.",
+    request_body = PaymentRequest,
+    responses(
+        (status = 202, body = ApiResponse<JobStatus>, description = "Queued; poll the Location header"),
+        (status = 400, description = "Invalid request"),
+        (status = 503, description = "The queue is full, retry later"),
+    ),
+    security(("service_key" = []))
+)]
+pub async fn purchase_async(
+    http_request: HttpRequest,
+    request: web::Json<PaymentRequest>,
+) -> ActixResult<HttpResponse> {
+    info!("Received asynchronous payment request");
+    let request = request.into_inner();
+    let context = OperationContext::new(
+        &http_request,
+        Operation::Purchase,
+        Some(request.Request.ClientReferenceId.clone()),
+    );
 
-            Ok(HttpResponse::build(status_code).json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                error: Some(e.to_string()),
-            }))
+    // Rejected now rather than after the caller has gone away
+    if let Err(e) = validation::validate_payment_request(&request) {
+        return Ok(failure_response(&context, e.into()));
+    }
+
+    let job = PurchaseJob {
+        id: Uuid::new_v4().to_string(),
+        correlation_id: context.correlation_id.clone(),
+        request,
+        options: request_options(&http_request).with_correlation_id(&context.correlation_id),
+    };
+    let status = JobStatus::queued(&job);
+
+    if let Err(e) = purchase_queue::queue().enqueue(job).await {
+        warn!("Could not queue payment request: {}", e);
+        let full = matches!(e, QueueError::Full);
+        let mut response = HttpResponse::ServiceUnavailable();
+        if full {
+            response.insert_header((header::RETRY_AFTER, "1"));
         }
+        return Ok(response
+            .insert_header((CORRELATION_ID_HEADER, context.correlation_id.as_str()))
+            .json(ApiResponse::<()> {
+                outcome: Some(Outcome::Error),
+                error_code: full.then(|| "QUEUE_FULL".to_string()),
+                ..ApiResponse::error(e.to_string())
+            }));
+    }
+
+    info!("Queued payment request as job {}", status.job_id);
+    Ok(HttpResponse::Accepted()
+        .insert_header((CORRELATION_ID_HEADER, context.correlation_id.as_str()))
+        .insert_header((
+            header::LOCATION,
+            format!("/api/purchase/async/{}", status.job_id),
+        ))
+        .json(ApiResponse {
+            outcome: Some(Outcome::Pending),
+            ..ApiResponse::ok(status)
+        }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/purchase/async/{job_id}",
+    tag = "plexo",
+    description = "Progress of a queued purchase; once completed it carries the response POST /api/purchase would have given",
+    params(("job_id" = String, Path, description = "Id returned when the purchase was queued")),
+    responses(
+        (status = 200, body = ApiResponse<JobStatus>),
+        (status = 404, description = "Unknown or expired job"),
+    ),
+    security(("service_key" = []))
+)]
+pub async fn purchase_async_status(job_id: web::Path<String>) -> ActixResult<HttpResponse> {
+    match purchase_queue::queue().status(&job_id).await {
+        Some(status) => Ok(HttpResponse::Ok().json(ApiResponse::ok(status))),
+        None => {
+            Ok(HttpResponse::NotFound().json(ApiResponse::<()>::error("Unknown or expired job")))
+        }
+    }
+}
+
+/// Start the workers that send queued purchases through `client`, at most
+/// `ASYNC_PURCHASE_WORKERS` at a time. Results are audited and forwarded to
+/// the result webhook like synchronous ones, rendered in the default API
+/// version since the caller's Accept header is long gone.
+pub fn start_purchase_workers(client: Arc<PlexoClient>) {
+    for _ in 0..purchase_queue::workers() {
+        let client = client.clone();
+        tokio::spawn(async move {
+            let queue = purchase_queue::queue();
+            while let Some(job) = queue.next().await {
+                process_queued_purchase(&client, queue.as_ref(), job).await;
+            }
+        });
     }
 }
 
-pub async fn status(request: web::Json<StatusRequest>) -> ActixResult<HttpResponse> {
+// Every purchase response is a buffered JSON body. Kept out of the async
+// worker since an HttpResponse must not be held across an await.
+fn response_parts(response: HttpResponse) -> (u16, Option<Value>) {
+    let status = response.status().as_u16();
+    let body = response
+        .into_body()
+        .try_into_bytes()
+        .ok()
+        .and_then(|body| serde_json::from_slice(&body).ok());
+    (status, body)
+}
+
+async fn process_queued_purchase(
+    client: &PlexoClient,
+    queue: &dyn PurchaseQueue,
+    job: PurchaseJob,
+) {
+    let PurchaseJob {
+        id,
+        correlation_id,
+        request,
+        options,
+    } = job;
+    let context = OperationContext {
+        correlation_id,
+        operation: Operation::Purchase,
+        client_reference: Some(request.Request.ClientReferenceId.clone()),
+        include_raw: false,
+        api_version: *DEFAULT_API_VERSION,
+    };
+
+    let mut status = JobStatus {
+        job_id: id,
+        state: JobState::Processing,
+        correlation_id: context.correlation_id.clone(),
+        http_status: None,
+        response: None,
+    };
+    queue.update(status.clone()).await;
+
+    let result = client.purchase(request, options).await;
+    let (http_status, response) = response_parts(purchase_response(&context, result, None));
+    status.http_status = Some(http_status);
+    status.response = response;
+    status.state = JobState::Completed;
+
+    info!(
+        "Job {} completed with HTTP {}",
+        status.job_id,
+        status.http_status.unwrap_or_default()
+    );
+    queue.update(status).await;
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/status",
+    tag = "plexo",
+    description = "Look up a transaction by reference. Final results are cached for STATUS_CACHE_TTL_MS (2 s by default); send `Cache-Control: no-cache` to ask Plexo again",
+    request_body = StatusRequest,
+    params(OperationQuery),
+    responses(
+        (status = 200, body = ApiResponse<serde_json::Value>),
+        (status = 400, description = "Invalid request"),
+        (status = 502, description = "Plexo could not be reached"),
+        (status = 504, description = "Plexo did not answer in time"),
+    ),
+    security(("service_key" = []))
+)]
+pub async fn status(
+    http_request: HttpRequest,
+    client: web::Data<PlexoClient>,
+    request: web::Json<StatusRequest>,
+    query: web::Query<OperationQuery>,
+) -> ActixResult<HttpResponse> {
+    if query.dry_run {
+        return Ok(dry_run_response(&request.into_inner()));
+    }
+
     info!("Received payment request");
+    let context = OperationContext::new(
+        &http_request,
+        Operation::Status,
+        Some(request.request.reference().to_string()),
+    );
 
-    match plexo_service::send_status_request(request.into_inner()).await {
+    match client
+        .status(
+            request.into_inner(),
+            request_options(&http_request).with_correlation_id(&context.correlation_id),
+        )
+        .await
+    {
         Ok(response) => {
             info!("Successfully processed payment request");
-            Ok(HttpResponse::Ok().json(ApiResponse {
-                success: true,
-                data: Some(response),
-                error: None,
-            }))
+            Ok(success_response(&context, response))
         }
         Err(e) => {
-            error!("Error processing status request: {}", e);
+            log_operation_error("status", &e);
+            Ok(failure_response(&context, e))
+        }
+    }
+}
 
-            let status_code = match e {
-                PlexoServiceError::Timeout => actix_web::http::StatusCode::GATEWAY_TIMEOUT,
-                PlexoServiceError::HttpRequestError(_) => actix_web::http::StatusCode::BAD_GATEWAY,
-                PlexoServiceError::SerializationError(_) => {
-                    actix_web::http::StatusCode::BAD_REQUEST
-                }
-                PlexoServiceError::SigningError(_) => {
-                    actix_web::http::StatusCode::INTERNAL_SERVER_ERROR
-                }
-            };
+#[utoipa::path(
+    post,
+    path = "/api/status/batch/stream",
+    tag = "plexo",
+    description = "Look up many transactions, streaming one newline-delimited JSON BatchStatusLine per lookup as it completes",
+    request_body = BatchStatusRequest,
+    responses(
+        (status = 200, content_type = "application/x-ndjson", body = BatchStatusLine),
+        (status = 400, description = "Too many lookups in one batch"),
+    ),
+    security(("service_key" = []))
+)]
+pub async fn status_batch_stream(
+    http_request: HttpRequest,
+    client: web::Data<PlexoClient>,
+    request: web::Json<BatchStatusRequest>,
+) -> ActixResult<HttpResponse> {
+    let BatchStatusRequest {
+        client: plexo_client,
+        requests,
+    } = request.into_inner();
+
+    if requests.len() > *MAX_BATCH_STATUS_SIZE {
+        return Ok(
+            HttpResponse::BadRequest().json(ApiResponse::<()>::error(format!(
+                "A batch may hold at most {} lookups",
+                *MAX_BATCH_STATUS_SIZE
+            ))),
+        );
+    }
+
+    info!(
+        "Received batch status request for {} references",
+        requests.len()
+    );
+    let client = client.into_inner();
+    let options = request_options(&http_request);
 
-            Ok(HttpResponse::build(status_code).json(ApiResponse::<()> {
+    // At most BATCH_STATUS_CONCURRENCY lookups are in flight and each line is
+    // written as soon as it is ready, so memory stays flat with batch size
+    let lines = stream::iter(requests.into_iter().enumerate())
+        .map(move |(index, reference)| {
+            let context = OperationContext::new(
+                &http_request,
+                Operation::Status,
+                reference.reference().map(str::to_string),
+            );
+            // A bad lookup fails its own line, not the whole batch
+            let query = StatusQuery::try_from(reference);
+            let plexo_client = plexo_client.clone();
+            let client = client.clone();
+            let options = options.clone().with_correlation_id(&context.correlation_id);
+            async move {
+                let result = match query {
+                    Ok(query) => {
+                        client
+                            .status(StatusRequest::new(plexo_client, query), options)
+                            .await
+                    }
+                    Err(e) => Err(e.into()),
+                };
+                batch_status_line(&context, index, result)
+            }
+        })
+        .buffer_unordered(*BATCH_STATUS_CONCURRENCY)
+        .map(|line| Ok::<_, actix_web::Error>(web::Bytes::from(line)));
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .streaming(lines))
+}
+
+// Audit one lookup and render it as an NDJSON line
+fn batch_status_line(
+    context: &OperationContext,
+    index: usize,
+    result: Result<Value, PlexoServiceError>,
+) -> String {
+    let line = match result {
+        Ok(data) => {
+            let result_code = data.get("ResultCode").and_then(Value::as_i64);
+            context.audit(StatusCode::OK, result_code);
+            BatchStatusLine {
+                index,
+                reference: context.client_reference.clone(),
+                success: true,
+                outcome: Some(Outcome::from_plexo(&data)),
+                data: Some(data),
+                error: None,
+                error_code: None,
+                upstream_status: None,
+            }
+        }
+        Err(e) => {
+            log_operation_error("batch status", &e);
+            context.audit(error_status(&e), None);
+            BatchStatusLine {
+                index,
+                reference: context.client_reference.clone(),
                 success: false,
+                outcome: Some(Outcome::from_error(&e)),
                 data: None,
+                error_code: error_code(&e).map(str::to_string),
+                upstream_status: e.upstream_status(),
                 error: Some(e.to_string()),
-            }))
+            }
+        }
+    };
+
+    let mut line = serde_json::to_string(&line).unwrap_or_default();
+    line.push('\n');
+    line
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/instrument",
+    tag = "plexo",
+    description = "Register a payment instrument",
+    request_body = RegisterInstrumentRequest,
+    responses(
+        (status = 200, body = ApiResponse<RegisterInstrumentResponse>),
+        (status = 400, description = "Invalid request"),
+        (status = 502, description = "Plexo could not be reached"),
+        (status = 504, description = "Plexo did not answer in time"),
+    ),
+    security(("service_key" = []))
+)]
+pub async fn register_instrument(
+    http_request: HttpRequest,
+    client: web::Data<PlexoClient>,
+    request: web::Json<RegisterInstrumentRequest>,
+) -> ActixResult<HttpResponse> {
+    info!("Received instrument registration request");
+    let context = OperationContext::new(&http_request, Operation::RegisterInstrument, None);
+
+    match client
+        .register_instrument(
+            request.into_inner(),
+            request_options(&http_request).with_correlation_id(&context.correlation_id),
+        )
+        .await
+    {
+        Ok(response) => {
+            info!("Successfully registered instrument");
+            Ok(success_response(&context, response))
+        }
+        Err(e) => {
+            log_operation_error("instrument registration", &e);
+            Ok(failure_response(&context, e))
+        }
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/instrument",
+    tag = "plexo",
+    description = "Delete a registered payment instrument",
+    request_body = DeleteInstrumentRequest,
+    responses(
+        (status = 200, body = ApiResponse<DeleteInstrumentResponse>),
+        (status = 400, description = "Invalid request"),
+        (status = 502, description = "Plexo could not be reached"),
+        (status = 504, description = "Plexo did not answer in time"),
+    ),
+    security(("service_key" = []))
+)]
+pub async fn delete_instrument(
+    http_request: HttpRequest,
+    client: web::Data<PlexoClient>,
+    request: web::Json<DeleteInstrumentRequest>,
+) -> ActixResult<HttpResponse> {
+    info!("Received instrument deletion request");
+    let context = OperationContext::new(&http_request, Operation::DeleteInstrument, None);
+
+    match client
+        .delete_instrument(
+            request.into_inner(),
+            request_options(&http_request).with_correlation_id(&context.correlation_id),
+        )
+        .await
+    {
+        Ok(response) => {
+            info!("Successfully deleted instrument");
+            Ok(success_response(&context, response))
+        }
+        Err(e) => {
+            log_operation_error("instrument deletion", &e);
+            Ok(failure_response(&context, e))
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CommerceQuery {
+    /// Plexo client name the commerce belongs to
+    pub client: String,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/commerce/{id}",
+    tag = "plexo",
+    description = "Look up whether a commerce is active and which currencies and issuers it supports. Answers are cached for COMMERCE_INFO_TTL_SECS.",
+    params(("id" = i32, Path, description = "Plexo commerce id"), CommerceQuery),
+    responses(
+        (status = 200, body = ApiResponse<CommerceInfo>),
+        (status = 422, description = "Plexo rejected the lookup"),
+        (status = 502, description = "Plexo could not be reached"),
+        (status = 504, description = "Plexo did not answer in time"),
+    ),
+    security(("service_key" = []))
+)]
+pub async fn commerce_info(
+    http_request: HttpRequest,
+    client: web::Data<PlexoClient>,
+    commerce_id: web::Path<i32>,
+    query: web::Query<CommerceQuery>,
+) -> ActixResult<HttpResponse> {
+    let commerce_id = commerce_id.into_inner();
+    info!("Received commerce info request");
+    let context = OperationContext::new(
+        &http_request,
+        Operation::CommerceInfo,
+        Some(commerce_id.to_string()),
+    );
+
+    let request = CommerceInfoRequest {
+        Client: query.into_inner().client,
+        Request: CommerceInfoRequestData {
+            CommerceId: commerce_id,
+        },
+    };
+    match client
+        .commerce_info(
+            request,
+            request_options(&http_request).with_correlation_id(&context.correlation_id),
+        )
+        .await
+    {
+        Ok(response) => Ok(success_response(&context, response)),
+        Err(e) => {
+            log_operation_error("commerce info", &e);
+            Ok(failure_response(&context, e))
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TransactionHistoryQuery {
+    /// Plexo client name the transactions belong to
+    pub client: String,
+    /// Reference the transactions were created with
+    #[serde(rename = "clientRef")]
+    #[param(rename = "clientRef")]
+    pub client_ref: String,
+    /// Page to return, counting from 1
+    pub page: Option<u32>,
+    /// Transactions per page, at most 100
+    #[serde(rename = "pageSize")]
+    #[param(rename = "pageSize")]
+    pub page_size: Option<u32>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/transactions",
+    tag = "plexo",
+    description = "List the transactions Plexo holds for a client reference, a page at a time. Paging is passed through to Plexo.",
+    params(TransactionHistoryQuery),
+    responses(
+        (status = 200, body = ApiResponse<TransactionHistory>),
+        (status = 400, description = "Invalid request"),
+        (status = 422, description = "Plexo rejected the lookup"),
+        (status = 502, description = "Plexo could not be reached"),
+        (status = 504, description = "Plexo did not answer in time"),
+    ),
+    security(("service_key" = []))
+)]
+pub async fn transaction_history(
+    http_request: HttpRequest,
+    client: web::Data<PlexoClient>,
+    query: web::Query<TransactionHistoryQuery>,
+) -> ActixResult<HttpResponse> {
+    let query = query.into_inner();
+    info!("Received transaction history request");
+    let context = OperationContext::new(
+        &http_request,
+        Operation::TransactionHistory,
+        Some(query.client_ref.clone()),
+    );
+
+    let request = TransactionHistoryRequest {
+        Client: query.client,
+        Request: TransactionHistoryRequestData {
+            ClientReferenceId: query.client_ref,
+            PageNumber: query.page,
+            PageSize: query.page_size,
+        },
+    };
+    match client
+        .transaction_history(
+            request,
+            request_options(&http_request).with_correlation_id(&context.correlation_id),
+        )
+        .await
+    {
+        Ok(response) => Ok(success_response(&context, response)),
+        Err(e) => {
+            log_operation_error("transaction history", &e);
+            Ok(failure_response(&context, e))
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/validate",
+    tag = "plexo",
+    description = "Show the canonical form of an operation payload and the fields cleaning changed. Never calls Plexo or signs.",
+    request_body = Object,
+    responses(
+        (status = 200, body = ApiResponse<CanonicalPreview>),
+        (status = 400, description = "Invalid JSON"),
+    ),
+    security(("service_key" = []))
+)]
+pub async fn validate(request: web::Json<Value>) -> ActixResult<HttpResponse> {
+    match plexo_service::canonical_preview(&request) {
+        Ok(preview) => Ok(HttpResponse::Ok().json(ApiResponse::ok(preview))),
+        Err(e) => {
+            log_operation_error("validation", &e);
+            Ok(error_response(e, None))
+        }
+    }
+}
+
+// Sign the request and return the envelope that would be sent to Plexo
+fn dry_run_response<T: Serialize>(request: &T) -> HttpResponse {
+    if !*DRY_RUN_ENABLED {
+        return HttpResponse::Forbidden()
+            .json(ApiResponse::<()>::error("Dry run mode is disabled"));
+    }
+
+    info!("Returning dry run signed payload");
+    match plexo_service::build_signed_request(request) {
+        Ok(signed_request) => HttpResponse::Ok().json(ApiResponse::ok(signed_request)),
+        Err(e) => {
+            log_operation_error("dry run", &e);
+            error_response(e, None)
+        }
+    }
+}
+
+/// Identifies one API operation for audit logging and response correlation
+struct OperationContext {
+    correlation_id: String,
+    operation: Operation,
+    client_reference: Option<String>,
+    include_raw: bool,
+    api_version: ApiVersion,
+}
+
+/// Response shape a client is pinned to, chosen with
+/// `Accept: application/vnd.plexo.v1+json` (or `v2`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ApiVersion {
+    /// Plexo's result passed through untouched
+    V1,
+    /// Results as typed `OperationResult`s
+    V2,
+}
+
+impl ApiVersion {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim() {
+            "1" | "v1" => Some(Self::V1),
+            "2" | "v2" => Some(Self::V2),
+            _ => None,
+        }
+    }
+
+    fn from_request(http_request: &HttpRequest) -> Self {
+        http_request
+            .headers()
+            .get(header::ACCEPT)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|accept| {
+                accept.split(',').find_map(|media_type| {
+                    let media_type = media_type.split(';').next()?.trim();
+                    let version = media_type
+                        .strip_prefix("application/vnd.plexo.")?
+                        .strip_suffix("+json")?;
+                    Self::parse(version)
+                })
+            })
+            .unwrap_or(*DEFAULT_API_VERSION)
+    }
+
+    fn media_type(self) -> &'static str {
+        match self {
+            Self::V1 => "application/vnd.plexo.v1+json",
+            Self::V2 => "application/vnd.plexo.v2+json",
+        }
+    }
+}
+
+impl OperationContext {
+    fn new(
+        http_request: &HttpRequest,
+        operation: Operation,
+        client_reference: Option<String>,
+    ) -> Self {
+        Self {
+            correlation_id: correlation_id(http_request),
+            operation,
+            client_reference,
+            include_raw: include_raw(http_request),
+            api_version: ApiVersion::from_request(http_request),
+        }
+    }
+
+    fn audit(&self, status: StatusCode, result_code: Option<i64>) {
+        info!(
+            operation = self.operation.as_str(),
+            correlation_id = self.correlation_id.as_str(),
+            outcome = if status.is_success() { "success" } else { "failure" },
+            http_status = status.as_u16();
+            "Operation completed"
+        );
+        audit::record(&AuditRecord {
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            correlation_id: &self.correlation_id,
+            operation: self.operation.as_str(),
+            client_reference: self.client_reference.as_deref(),
+            result_code,
+            http_status: status.as_u16(),
+            success: status.is_success(),
+        });
+    }
+
+    // Hand a final Plexo result to the result webhook, if one is configured
+    fn notify(
+        &self,
+        status: StatusCode,
+        result_code: Option<i64>,
+        data: Option<Value>,
+        error: Option<String>,
+    ) {
+        // Configuration and history lookups are not payment results
+        if matches!(
+            self.operation,
+            Operation::CommerceInfo | Operation::TransactionHistory
+        ) {
+            return;
+        }
+        webhook::notify(ResultNotification {
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            correlation_id: self.correlation_id.clone(),
+            operation: self.operation.as_str().to_string(),
+            client_reference: self.client_reference.clone(),
+            http_status: status.as_u16(),
+            success: status.is_success(),
+            result_code,
+            data,
+            error,
+        });
+    }
+}
+
+// Reuse the caller's correlation id when it is sane, otherwise generate one
+fn correlation_id(http_request: &HttpRequest) -> String {
+    http_request
+        .headers()
+        .get(CORRELATION_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|value| {
+            !value.is_empty()
+                && value.len() <= 128
+                && value
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        })
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string())
+}
+
+fn idempotency_key(http_request: &HttpRequest) -> Option<String> {
+    http_request
+        .headers()
+        .get(IDEMPOTENCY_KEY_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|value| !value.is_empty() && value.len() <= 255)
+        .map(str::to_string)
+}
+
+// Store the finished response under the reserved `key`. A server-side failure
+// that provably never reached Plexo (unreachable, shed, maintenance) frees the
+// key instead so the caller can retry it. One Plexo may have acted on (a
+// timeout, a 5xx answer) is stored, so a retry replays it rather than charging twice.
+async fn remember_response(
+    key: &str,
+    fingerprint: String,
+    response: HttpResponse,
+    reached_plexo: bool,
+) -> ActixResult<HttpResponse> {
+    let status = response.status();
+    if status.is_server_error() && !reached_plexo {
+        idempotency::store().release(key).await;
+        return Ok(response);
+    }
+
+    let (response, body) = response.into_parts();
+    let body = actix_web::body::to_bytes(body)
+        .await
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to buffer response"))?;
+
+    idempotency::store()
+        .put(
+            key,
+            StoredResponse {
+                status: status.as_u16(),
+                body: body.to_vec(),
+                fingerprint,
+            },
+            idempotency::ttl(),
+        )
+        .await;
+
+    Ok(response.set_body(BoxBody::new(body)))
+}
+
+fn idempotency_conflict(status: StatusCode, code: &str, message: &str) -> HttpResponse {
+    HttpResponse::build(status).json(ApiResponse::<()> {
+        outcome: Some(Outcome::Error),
+        error_code: Some(code.to_string()),
+        ..ApiResponse::error(message)
+    })
+}
+
+fn replay_response(stored: StoredResponse) -> HttpResponse {
+    let status = StatusCode::from_u16(stored.status).unwrap_or(StatusCode::OK);
+    HttpResponse::build(status)
+        .content_type(ContentType::json())
+        .insert_header((IDEMPOTENT_REPLAY_HEADER, "true"))
+        .body(stored.body)
+}
+
+// A positive number from `name`, `default` when unset or invalid
+fn env_usize(name: &str, default: usize) -> usize {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.trim().parse::<usize>().ok())
+        .filter(|value| *value > 0)
+        .unwrap_or(default)
+}
+
+// Raw responses need both the server-side switch and the caller's header
+fn include_raw(http_request: &HttpRequest) -> bool {
+    *RAW_RESPONSE_ENABLED
+        && http_request
+            .headers()
+            .get(INCLUDE_RAW_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.trim().eq_ignore_ascii_case("true"))
+}
+
+fn success_response<T: Serialize + RawResponse>(
+    context: &OperationContext,
+    data: T,
+) -> HttpResponse {
+    success_response_with_void(context, data, None)
+}
+
+// A declined purchase also reports what became of its authorization
+fn success_response_with_void<T: Serialize + RawResponse>(
+    context: &OperationContext,
+    data: T,
+    authorization_void: Option<AuthorizationVoid>,
+) -> HttpResponse {
+    // Redacted like the logs, since raw responses carry customer and card data
+    let raw = if context.include_raw {
+        data.raw().map(redaction::redact_value)
+    } else {
+        None
+    };
+
+    let data = match serde_json::to_value(data) {
+        Ok(data) => data,
+        Err(e) => return failure_response(context, PlexoServiceError::SerializationError(e)),
+    };
+    // Read before projection, which may drop the transaction status
+    let outcome = Outcome::from_plexo(&data);
+
+    // Typed responses only exist for accepted operations, so they imply code 0
+    let result_code = data.get("ResultCode").and_then(Value::as_i64).or(Some(0));
+    context.audit(StatusCode::OK, result_code);
+    context.notify(StatusCode::OK, result_code, Some(data.clone()), None);
+
+    // Instrument results are typed in every version; the others pass Plexo's
+    // response through in v1 and are typed from v2 on
+    let untyped = matches!(
+        context.operation,
+        Operation::Authorize | Operation::Purchase | Operation::Status
+    );
+
+    // Plexo's envelope fields are part of our contract, only its payload is filtered
+    let always: &[&str] = if untyped {
+        &["ResultCode", "ErrorMessage"]
+    } else {
+        &[]
+    };
+    let data = projection::project(context.operation.as_str(), data, always);
+    let data = if untyped && context.api_version == ApiVersion::V2 {
+        match serde_json::to_value(OperationResult::from_plexo(&data)) {
+            Ok(data) => data,
+            Err(e) => return failure_response(context, PlexoServiceError::SerializationError(e)),
+        }
+    } else {
+        data
+    };
+
+    HttpResponse::Ok()
+        .insert_header((CORRELATION_ID_HEADER, context.correlation_id.as_str()))
+        .insert_header((header::CONTENT_TYPE, context.api_version.media_type()))
+        .insert_header((header::VARY, "Accept"))
+        .json(ApiResponse {
+            outcome: Some(outcome),
+            raw,
+            authorization_void,
+            ..ApiResponse::ok(data)
+        })
+}
+
+// The purchase is pending until the cardholder completes the issuer challenge
+fn challenge_response(
+    context: &OperationContext,
+    response: &Value,
+    challenge: ThreeDSChallenge,
+) -> HttpResponse {
+    let result_code = response.get("ResultCode").and_then(Value::as_i64);
+    context.audit(StatusCode::ACCEPTED, result_code);
+
+    HttpResponse::Accepted()
+        .insert_header((CORRELATION_ID_HEADER, context.correlation_id.as_str()))
+        .json(ApiResponse {
+            outcome: Some(Outcome::Requires3DS),
+            data: Some(challenge),
+            error: Some("3-D Secure challenge required".to_string()),
+            error_code: Some(THREE_DS_REQUIRED.to_string()),
+            ..Default::default()
+        })
+}
+
+fn failure_response(context: &OperationContext, e: PlexoServiceError) -> HttpResponse {
+    failure_response_with_void(context, e, None)
+}
+
+// A failed purchase also reports what became of its authorization
+fn failure_response_with_void(
+    context: &OperationContext,
+    e: PlexoServiceError,
+    authorization_void: Option<AuthorizationVoid>,
+) -> HttpResponse {
+    let result_code = match &e {
+        PlexoServiceError::PlexoResultError { code, .. } => Some(i64::from(*code)),
+        _ => None,
+    };
+    context.audit(error_status(&e), result_code);
+
+    // Declines are final results too; transport and validation failures are not
+    if matches!(
+        e,
+        PlexoServiceError::PlexoResultError { .. } | PlexoServiceError::InstrumentNotFound
+    ) {
+        context.notify(error_status(&e), result_code, None, Some(e.to_string()));
+    }
+
+    let mut response = error_response(e, authorization_void);
+    if let Ok(value) = HeaderValue::from_str(&context.correlation_id) {
+        response
+            .headers_mut()
+            .insert(HeaderName::from_static(CORRELATION_ID_HEADER), value);
+    }
+    response
+}
+
+// Build per-call options from the request headers
+fn request_options(http_request: &HttpRequest) -> RequestOptions {
+    let mut options = RequestOptions::default();
+
+    if let Some(value) = http_request.headers().get(TIMEOUT_HEADER) {
+        match value
+            .to_str()
+            .ok()
+            .and_then(|v| v.trim().parse::<u64>().ok())
+        {
+            Some(timeout_ms) => options = options.with_timeout_ms(timeout_ms),
+            None => warn!("Ignoring invalid {} header", TIMEOUT_HEADER),
         }
     }
+
+    options.allow_duplicate = http_request
+        .headers()
+        .get(ALLOW_DUPLICATE_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.trim().eq_ignore_ascii_case("true"));
+
+    options.no_cache = http_request
+        .headers()
+        .get(header::CACHE_CONTROL)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| {
+            value
+                .split(',')
+                .any(|directive| directive.trim().eq_ignore_ascii_case("no-cache"))
+        });
+
+    options
+}
+
+// All handlers log failures through here so the output stays redacted
+fn log_operation_error(operation: &str, e: &PlexoServiceError) {
+    error!("Error processing {} request: {}", operation, e);
+}
+
+fn error_status(e: &PlexoServiceError) -> StatusCode {
+    match e {
+        PlexoServiceError::Timeout => StatusCode::GATEWAY_TIMEOUT,
+        // Plexo's 4xx answers are about our request, so pass them through; its own
+        // gateway timeouts stay timeouts and any other server error is a bad gateway
+        PlexoServiceError::UpstreamStatus { status } => match StatusCode::from_u16(*status) {
+            Ok(status) if status.is_client_error() => status,
+            Ok(StatusCode::GATEWAY_TIMEOUT) => StatusCode::GATEWAY_TIMEOUT,
+            _ => StatusCode::BAD_GATEWAY,
+        },
+        PlexoServiceError::ConnectionError(_)
+        | PlexoServiceError::HttpRequestError(_)
+        | PlexoServiceError::UnexpectedResponse { .. } => StatusCode::BAD_GATEWAY,
+        PlexoServiceError::SerializationError(_)
+        | PlexoServiceError::ValidationError(_)
+        | PlexoServiceError::SigningError(CryptoError::UnknownCommerceError(_)) => {
+            StatusCode::BAD_REQUEST
+        }
+        PlexoServiceError::SigningError(CryptoError::PayloadTooLarge { .. }) => {
+            StatusCode::PAYLOAD_TOO_LARGE
+        }
+        // Signing is not available yet (or any more), callers should retry
+        PlexoServiceError::SigningError(CryptoError::InitializationError(_)) => {
+            StatusCode::SERVICE_UNAVAILABLE
+        }
+        PlexoServiceError::SigningError(_) | PlexoServiceError::ConfigurationError(_) => {
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+        PlexoServiceError::InstrumentNotFound => StatusCode::NOT_FOUND,
+        PlexoServiceError::DuplicateReference(_) => StatusCode::CONFLICT,
+        PlexoServiceError::PlexoResultError { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+        PlexoServiceError::Overloaded | PlexoServiceError::Maintenance { .. } => {
+            StatusCode::SERVICE_UNAVAILABLE
+        }
+    }
+}
+
+// Lets callers tell upstream failure modes apart without parsing messages
+fn error_code(e: &PlexoServiceError) -> Option<&'static str> {
+    match e {
+        PlexoServiceError::Timeout => Some("PLEXO_TIMEOUT"),
+        PlexoServiceError::ConnectionError(_) => Some("PLEXO_UNREACHABLE"),
+        PlexoServiceError::UpstreamStatus { .. } => Some("PLEXO_HTTP_ERROR"),
+        PlexoServiceError::Overloaded => Some("PLEXO_OVERLOADED"),
+        PlexoServiceError::Maintenance { .. } => Some("PLEXO_MAINTENANCE"),
+        PlexoServiceError::UnexpectedResponse { .. } => Some("PLEXO_INVALID_RESPONSE"),
+        PlexoServiceError::ValidationError(_) => Some("VALIDATION_ERROR"),
+        PlexoServiceError::SigningError(CryptoError::PayloadTooLarge { .. }) => {
+            Some("SIGNING_PAYLOAD_TOO_LARGE")
+        }
+        _ => None,
+    }
+}
+
+fn error_response(
+    e: PlexoServiceError,
+    authorization_void: Option<AuthorizationVoid>,
+) -> HttpResponse {
+    let mut builder = HttpResponse::build(error_status(&e));
+    match &e {
+        PlexoServiceError::Overloaded => {
+            builder.insert_header((header::RETRY_AFTER, "1"));
+        }
+        PlexoServiceError::Maintenance { retry_after_secs } => {
+            builder.insert_header((header::RETRY_AFTER, retry_after_secs.to_string()));
+        }
+        _ => {}
+    }
+
+    let validation_errors = match &e {
+        PlexoServiceError::ValidationError(errors) => Some(errors.0.clone()),
+        _ => None,
+    };
+
+    builder.json(ApiResponse::<()> {
+        outcome: Some(Outcome::from_error(&e)),
+        error_code: error_code(&e).map(str::to_string),
+        validation_errors,
+        upstream_status: e.upstream_status(),
+        authorization_void,
+        ..ApiResponse::error(e.to_string())
+    })
 }
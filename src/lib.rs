@@ -0,0 +1,6 @@
+//! Plexo payments gateway: request models, signing and the HTTP API, usable
+//! both by the bundled server binary and by crates that embed it.
+
+pub mod api;
+pub mod models;
+pub mod services;
@@ -1,19 +1,19 @@
-use actix_cors::Cors;
-use actix_web::{middleware, web, App, HttpResponse, HttpServer};
+use actix_web::HttpServer;
 use dotenvy::dotenv;
-use log::info;
+use log::{info, warn};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 
-mod api;
-mod models;
-mod services;
-
-use api::plexo_controller::{authorize, purchase, status};
-use services::middleware::{ServiceAuthConfig, ServiceAuthMiddleware};
+use plexo_back::api::app::{build_app, AppConfig};
+use plexo_back::api::plexo_controller::start_purchase_workers;
+use plexo_back::services;
+use plexo_back::services::middleware::{AuthMode, ServiceAuthConfig};
+use plexo_back::services::response_signing;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     // Initialize logger
-    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
+    services::logging::init();
     // Load .env file
     dotenv().ok();
     let secret_key =
@@ -22,6 +22,10 @@ async fn main() -> std::io::Result<()> {
         std::env::var("HEADER_NAME").expect("HEADER_NAME environment variable is required");
     let service_name =
         std::env::var("SERVICE_NAME").expect("SERVICE_NAME environment variable is required");
+    // The admin API must not be reachable with the key every service holds
+    if std::env::var("ADMIN_KEY").is_ok_and(|admin_key| admin_key == secret_key) {
+        panic!("ADMIN_KEY must differ from SECRET_KEY");
+    }
 
     // Get configuration from environment
     let host = std::env::var("HOST").unwrap_or_else(|_| "0.0.0.0".to_string());
@@ -34,41 +38,155 @@ async fn main() -> std::io::Result<()> {
 
     // Initialize services
     services::crypto::init().expect("Failed to initialize crypto service");
+    services::plexo_service::init().expect("Failed to initialize Plexo HTTP client");
+    services::metrics::init().expect("Failed to register metrics");
+    let plexo_client =
+        services::plexo_service::default_client().expect("Plexo client is initialized above");
+    start_purchase_workers(plexo_client.clone());
+
+    let allowed_origins = parse_allowed_origins(std::env::var("ALLOWED_ORIGINS").ok());
+    if allowed_origins.is_empty() {
+        warn!("ALLOWED_ORIGINS is not set, cross-origin requests will be rejected");
+    }
+
+    let auth_mode = match std::env::var("SERVICE_AUTH_MODE").as_deref() {
+        Ok("hmac") => AuthMode::Hmac,
+        _ => AuthMode::StaticKey,
+    };
 
-    let auth_config = ServiceAuthConfig::new(secret_key, &service_name)
+    let mut auth_config = ServiceAuthConfig::new(secret_key, &service_name);
+    for (key, allowed_paths) in
+        parse_restricted_keys(&std::env::var("RESTRICTED_SERVICE_KEYS").unwrap_or_default())
+    {
+        auth_config = auth_config.with_restricted_key(key, allowed_paths);
+    }
+
+    let auth_config = auth_config
+        .with_auth_mode(auth_mode)
         .with_rate_limit(100, 60)
         .with_header_name(&header_name)
-        .unwrap();
-
-    HttpServer::new(move || {
-        App::new()
-            .wrap(ServiceAuthMiddleware::new(auth_config.clone()))
-            .wrap(middleware::Logger::default())
-            .wrap(
-                Cors::default()
-                    .allow_any_origin()
-                    .allowed_methods(vec!["GET", "POST", "PUT", "PATCH", "DELETE"])
-                    .allowed_headers(vec![
-                        actix_web::http::header::AUTHORIZATION,
-                        actix_web::http::header::ACCEPT,
-                        actix_web::http::header::CONTENT_TYPE,
-                    ])
-                    .max_age(3600),
-            )
-            // Register API routes
-            .service(
-                web::scope("/api")
-                    .route("/authorize", web::post().to(authorize))
-                    .route("/purchase", web::post().to(purchase))
-                    .route("/status", web::post().to(status)),
-            )
-            // Add a health check endpoint
-            .route(
-                "/health",
-                web::get().to(|| async { HttpResponse::Ok().body("Service is running") }),
-            )
-    })
-    .bind((host, port))?
-    .run()
-    .await
+        .unwrap()
+        .with_exempt_path("/metrics")
+        .with_exempt_path("/ready")
+        // Guarded by ADMIN_KEY in the admin controller instead of the service key
+        .with_exempt_path("/api/admin")
+        .with_exempt_path("/openapi.json")
+        .with_exempt_path("/docs");
+
+    if response_signing::enabled() {
+        info!("Signing JSON responses in the X-Response-Signature header");
+    }
+
+    let mut app_config =
+        AppConfig::new(plexo_client, auth_config).with_allowed_origins(allowed_origins);
+    if let Some(max_body_bytes) = std::env::var("MAX_JSON_BODY_BYTES")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+    {
+        app_config = app_config.with_max_body_bytes(max_body_bytes);
+    }
+
+    let grace_period = std::env::var("SHUTDOWN_GRACE_SECS")
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(30);
+
+    let server = HttpServer::new(move || build_app(app_config.clone()))
+        .bind((host, port))?
+        // Signals are handled below so in-flight Plexo calls can be drained
+        .disable_signals()
+        .shutdown_timeout(grace_period)
+        .run();
+
+    let handle = server.handle();
+    let in_flight_at_signal = Arc::new(AtomicUsize::new(0));
+    let aborted_before_signal = Arc::new(AtomicUsize::new(0));
+    {
+        let in_flight_at_signal = in_flight_at_signal.clone();
+        let aborted_before_signal = aborted_before_signal.clone();
+        tokio::spawn(async move {
+            wait_for_shutdown_signal().await;
+            let in_flight = services::plexo_service::in_flight_requests();
+            in_flight_at_signal.store(in_flight, Ordering::SeqCst);
+            aborted_before_signal.store(
+                services::plexo_service::aborted_requests(),
+                Ordering::SeqCst,
+            );
+            info!(
+                "Shutdown requested, draining {} in-flight Plexo calls (grace period {}s)",
+                in_flight, grace_period
+            );
+            handle.stop(true).await;
+        });
+    }
+
+    server.await?;
+
+    let aborted = services::plexo_service::aborted_requests()
+        .saturating_sub(aborted_before_signal.load(Ordering::SeqCst));
+    let drained = in_flight_at_signal
+        .load(Ordering::SeqCst)
+        .saturating_sub(aborted);
+    info!(
+        "Server stopped: {} in-flight Plexo calls drained, {} aborted",
+        drained, aborted
+    );
+
+    Ok(())
+}
+
+// Resolve on SIGTERM (rolling deploys) or Ctrl+C
+async fn wait_for_shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            warn!("Failed to listen for Ctrl+C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                warn!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+/// Parse `RESTRICTED_SERVICE_KEYS` as `key1=/api/status,/api/cert-info;key2=/api/status`
+fn parse_restricted_keys(raw: &str) -> Vec<(String, Vec<String>)> {
+    raw.split(';')
+        .filter_map(|entry| {
+            let (key, paths) = entry.split_once('=')?;
+            let paths: Vec<String> = paths
+                .split(',')
+                .map(|path| path.trim().to_string())
+                .filter(|path| !path.is_empty())
+                .collect();
+            Some((key.trim().to_string(), paths))
+        })
+        .filter(|(key, _)| !key.is_empty())
+        .collect()
+}
+
+/// Parse the comma-separated `ALLOWED_ORIGINS` value, dropping empty entries
+fn parse_allowed_origins(raw: Option<String>) -> Vec<String> {
+    raw.unwrap_or_default()
+        .split(',')
+        .map(|origin| origin.trim().to_string())
+        .filter(|origin| !origin.is_empty())
+        .collect()
 }
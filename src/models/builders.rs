@@ -0,0 +1,162 @@
+use super::common::LosslessNumber;
+use super::requests::{
+    AuthorizationRequestData, FinancialInclusion, PaymentInstrumentInput, PaymentItem,
+    PaymentRequest, PaymentRequestData,
+};
+use crate::services::validation::{self, ValidationError, ValidationErrors};
+
+/// Chained construction of a `PaymentRequest`. `build()` checks that every
+/// required field was set and runs the same validation as the API.
+#[derive(Debug, Default)]
+pub struct PaymentRequestBuilder {
+    client: Option<String>,
+    client_reference_id: Option<String>,
+    currency_id: Option<i32>,
+    installments: Option<i32>,
+    items: Vec<PaymentItem>,
+    instrument_token: Option<String>,
+    use_extended_credit: bool,
+    financial_inclusion: Option<FinancialInclusion>,
+    commerce_id: Option<i32>,
+}
+
+impl PaymentRequestBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn client(mut self, client: impl Into<String>) -> Self {
+        self.client = Some(client.into());
+        self
+    }
+
+    pub fn client_reference_id(mut self, reference: impl Into<String>) -> Self {
+        self.client_reference_id = Some(reference.into());
+        self
+    }
+
+    /// ISO 4217 numeric currency id, e.g. 858 for Uruguayan pesos
+    pub fn currency(mut self, currency_id: i32) -> Self {
+        self.currency_id = Some(currency_id);
+        self
+    }
+
+    /// Defaults to a single installment
+    pub fn installments(mut self, installments: i32) -> Self {
+        self.installments = Some(installments);
+        self
+    }
+
+    pub fn add_item(mut self, amount: impl ToString, reference: impl Into<String>) -> Self {
+        self.items.push(PaymentItem {
+            Amount: LosslessNumber::new(amount),
+            ClientItemReferenceId: reference.into(),
+        });
+        self
+    }
+
+    pub fn instrument_token(mut self, token: impl Into<String>) -> Self {
+        self.instrument_token = Some(token.into());
+        self
+    }
+
+    pub fn use_extended_credit(mut self, enabled: bool) -> Self {
+        self.use_extended_credit = enabled;
+        self
+    }
+
+    pub fn financial_inclusion(mut self, financial_inclusion: FinancialInclusion) -> Self {
+        self.financial_inclusion = Some(financial_inclusion);
+        self
+    }
+
+    pub fn commerce_id(mut self, commerce_id: i32) -> Self {
+        self.commerce_id = Some(commerce_id);
+        self
+    }
+
+    pub fn build(self) -> Result<PaymentRequest, ValidationErrors> {
+        let currency_id = required(self.currency_id, "currency", "Request.CurrencyId")?;
+        validation::validate_currency(currency_id)?;
+
+        if self.items.is_empty() {
+            return Err(ValidationError::new(
+                "Request.Items",
+                "required",
+                "PaymentRequest needs at least one item",
+            )
+            .into());
+        }
+
+        let request = PaymentRequest {
+            Client: required(self.client, "client", "Client")?,
+            Request: PaymentRequestData {
+                ClientReferenceId: required(
+                    self.client_reference_id,
+                    "client_reference_id",
+                    "Request.ClientReferenceId",
+                )?,
+                CurrencyId: currency_id,
+                FinancialInclusion: required(
+                    self.financial_inclusion,
+                    "financial_inclusion",
+                    "Request.FinancialInclusion",
+                )?,
+                Installments: self.installments.unwrap_or(1),
+                Items: self.items,
+                PaymentInstrumentInput: PaymentInstrumentInput {
+                    InstrumentToken: required(
+                        self.instrument_token,
+                        "instrument_token",
+                        "Request.PaymentInstrumentInput.InstrumentToken",
+                    )?,
+                    UseExtendedClientCreditIfAvailable: self.use_extended_credit,
+                    OptionalFields: None,
+                    InstrumentData: None,
+                },
+                OptionalCommerceId: self.commerce_id,
+                LoyaltyProgramAmount: None,
+                OptionalInstrumentFields: None,
+                CommerceReserveExpirationInSeconds: None,
+                ThreeDSReferenceId: None,
+            },
+        };
+
+        validation::validate_payment_request(&request)?;
+        Ok(request)
+    }
+}
+
+// `setter` names the builder method, `path` the field it fills in the request
+fn required<T>(value: Option<T>, setter: &str, path: &str) -> Result<T, ValidationError> {
+    value.ok_or_else(|| {
+        ValidationError::new(
+            path,
+            "required",
+            format!("PaymentRequest is missing {}", setter),
+        )
+    })
+}
+
+impl AuthorizationRequestData {
+    /// Restrict the instrument selection to these issuers. Unknown codes are
+    /// rejected when the request is validated, not here.
+    pub fn limit_issuers<I, S>(mut self, issuers: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.LimitIssuers = Some(issuers.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Restrict the instrument selection to these banks
+    pub fn limit_banks<I, S>(mut self, banks: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.LimitBanks = Some(banks.into_iter().map(Into::into).collect());
+        self
+    }
+}
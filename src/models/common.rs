@@ -1,12 +1,133 @@
+use rust_decimal::Decimal;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{json, Value};
 use std::fmt;
+use std::sync::OnceLock;
+use thiserror::Error;
+use utoipa::openapi::schema::{ObjectBuilder, OneOfBuilder, Type};
+use utoipa::openapi::{RefOr, Schema};
+use utoipa::{PartialSchema, ToSchema};
+
+/// Decimal places used when the currency is unknown or not provided
+pub const DEFAULT_DECIMAL_PLACES: u32 = 2;
+
+/// Currencies Plexo accepts, keyed by their ISO 4217 numeric id. The wire
+/// format stays the bare integer, this type only exists for validation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Currency {
+    Ars = 32,
+    Bhd = 48,
+    Clp = 152,
+    Jpy = 392,
+    Kwd = 414,
+    Omr = 512,
+    Pyg = 600,
+    Usd = 840,
+    Uyu = 858,
+    Uyi = 940,
+    Eur = 978,
+    Brl = 986,
+}
+
+impl Currency {
+    /// Number of minor-unit decimal places used when formatting amounts
+    pub fn decimal_places(self) -> u32 {
+        match self {
+            // Chilean peso, Paraguayan guaraní, Japanese yen, Uruguayan indexed unit
+            Currency::Clp | Currency::Pyg | Currency::Jpy | Currency::Uyi => 0,
+            // Bahraini dinar, Kuwaiti dinar, Omani rial
+            Currency::Bhd | Currency::Kwd | Currency::Omr => 3,
+            Currency::Ars | Currency::Usd | Currency::Uyu | Currency::Eur | Currency::Brl => 2,
+        }
+    }
+}
+
+impl TryFrom<i32> for Currency {
+    type Error = i32;
+
+    fn try_from(currency_id: i32) -> Result<Self, Self::Error> {
+        match currency_id {
+            32 => Ok(Currency::Ars),
+            48 => Ok(Currency::Bhd),
+            152 => Ok(Currency::Clp),
+            392 => Ok(Currency::Jpy),
+            414 => Ok(Currency::Kwd),
+            512 => Ok(Currency::Omr),
+            600 => Ok(Currency::Pyg),
+            840 => Ok(Currency::Usd),
+            858 => Ok(Currency::Uyu),
+            940 => Ok(Currency::Uyi),
+            978 => Ok(Currency::Eur),
+            986 => Ok(Currency::Brl),
+            other => Err(other),
+        }
+    }
+}
+
+/// Number of minor-unit decimal places for a Plexo currency id (ISO 4217 numeric),
+/// falling back to `DEFAULT_DECIMAL_PLACES` for ids we don't know
+pub fn decimal_places_for_currency(currency_id: i32) -> u32 {
+    Currency::try_from(currency_id)
+        .map(Currency::decimal_places)
+        .unwrap_or(DEFAULT_DECIMAL_PLACES)
+}
+
+/// How `FinancialInclusion.InvoiceNumber` is put on the wire. Plexo takes a
+/// number by default, but some financial-inclusion setups expect a string,
+/// optionally zero-padded to a fixed width (`123` -> `"0000123"`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InvoiceNumberFormat {
+    #[default]
+    Numeric,
+    Text {
+        width: Option<usize>,
+    },
+}
+
+impl InvoiceNumberFormat {
+    /// Parse `number`, `string` or `string:<width>`
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw.trim().to_ascii_lowercase().as_str() {
+            "number" | "numeric" => Some(Self::Numeric),
+            "string" => Some(Self::Text { width: None }),
+            other => {
+                let width = other
+                    .strip_prefix("string:")?
+                    .trim()
+                    .parse::<usize>()
+                    .ok()?;
+                Some(Self::Text { width: Some(width) })
+            }
+        }
+    }
+
+    pub fn format(&self, invoice_number: i64) -> Value {
+        match self {
+            Self::Numeric => json!(invoice_number),
+            Self::Text { width: None } => json!(invoice_number.to_string()),
+            Self::Text { width: Some(width) } => {
+                json!(format!("{:0width$}", invoice_number, width = *width))
+            }
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct LosslessNumber(pub String);
 
+/// Why an amount could not be put at a currency's scale
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum AmountFormatError {
+    #[error("Invalid amount: {0}")]
+    Invalid(String),
+
+    #[error("Amount {amount} has more than {decimal_places} decimal places")]
+    TooManyDecimals { amount: String, decimal_places: u32 },
+}
+
 impl fmt::Display for LosslessNumber {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.format_for_json())
+        write!(f, "{}", self.0.trim())
     }
 }
 
@@ -15,9 +136,9 @@ impl Serialize for LosslessNumber {
     where
         S: Serializer,
     {
-        // Format the number and serialize as a raw number (without quotes)
-        let formatted = self.format_for_json();
-        serializer.serialize_str(&formatted)
+        // The exact string as given; the currency's scale is applied when the
+        // payload is cleaned, since only the whole request knows its currency
+        serializer.serialize_str(self.0.trim())
     }
 }
 
@@ -28,57 +149,163 @@ impl<'de> Deserialize<'de> for LosslessNumber {
     {
         // Accept either string or number, convert to string
         let value = serde_json::Value::deserialize(deserializer)?;
-        match value {
-            serde_json::Value::String(s) => Ok(LosslessNumber(s)),
-            serde_json::Value::Number(n) => Ok(LosslessNumber(n.to_string())),
-            _ => Err(serde::de::Error::custom("Expected string or number")),
+        let raw = match value {
+            // JSON numbers always use '.', only strings can carry a locale's separators
+            serde_json::Value::String(s) => AmountLocale::configured().normalize(&s),
+            serde_json::Value::Number(n) => n.to_string(),
+            _ => return Err(serde::de::Error::custom("Expected string or number")),
+        };
+        normalize_amount(&raw)
+            .map(LosslessNumber)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Separators callers use in string amounts, from `AMOUNT_DECIMAL_SEPARATOR`
+/// and `AMOUNT_THOUSANDS_SEPARATOR`. The default is US style: `.` for
+/// decimals and no thousands separator. With `,` and `.` configured,
+/// `"1.234,56"` is read as `1234.56`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AmountLocale {
+    pub decimal_separator: char,
+    pub thousands_separator: Option<char>,
+}
+
+impl Default for AmountLocale {
+    fn default() -> Self {
+        Self {
+            decimal_separator: '.',
+            thousands_separator: None,
         }
     }
 }
 
+impl AmountLocale {
+    /// The locale configured through the environment, read once
+    pub fn configured() -> Self {
+        static CONFIGURED: OnceLock<AmountLocale> = OnceLock::new();
+        *CONFIGURED.get_or_init(Self::from_env)
+    }
+
+    fn from_env() -> Self {
+        let separator = |name: &str| {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| value.trim().chars().next())
+        };
+        let locale = Self {
+            decimal_separator: separator("AMOUNT_DECIMAL_SEPARATOR").unwrap_or('.'),
+            thousands_separator: separator("AMOUNT_THOUSANDS_SEPARATOR"),
+        };
+
+        if locale.thousands_separator == Some(locale.decimal_separator) {
+            log::warn!("AMOUNT_THOUSANDS_SEPARATOR equals the decimal separator, ignoring both");
+            return Self::default();
+        }
+        locale
+    }
+
+    /// Rewrite an amount in this locale as a canonical `.`-decimal string
+    pub fn normalize(&self, raw: &str) -> String {
+        if *self == Self::default() {
+            return raw.to_string();
+        }
+
+        raw.chars()
+            .filter(|c| Some(*c) != self.thousands_separator)
+            .map(|c| if c == self.decimal_separator { '.' } else { c })
+            .collect()
+    }
+}
+
+/// Rewrite scientific notation ("1e2", "1.5E-1") as plain decimal so the
+/// canonical form doesn't depend on how the caller spelled the amount, and
+/// reject NaN and infinities outright.
+fn normalize_amount(raw: &str) -> Result<String, String> {
+    let trimmed = raw.trim();
+
+    if trimmed.parse::<f64>().is_ok_and(|value| !value.is_finite()) {
+        return Err(format!("Amount must be a finite number, got {}", trimmed));
+    }
+
+    if trimmed.contains(['e', 'E']) {
+        return Decimal::from_scientific(trimmed)
+            .map(|value| value.normalize().to_string())
+            .map_err(|e| format!("Invalid amount {}: {}", trimmed, e));
+    }
+
+    Ok(raw.to_string())
+}
+
+// Callers may send amounts as JSON numbers or as strings to keep their precision
+impl PartialSchema for LosslessNumber {
+    fn schema() -> RefOr<Schema> {
+        OneOfBuilder::new()
+            .item(ObjectBuilder::new().schema_type(Type::Number))
+            .item(ObjectBuilder::new().schema_type(Type::String))
+            .description(Some("Decimal amount, as a number or a numeric string"))
+            .into()
+    }
+}
+
+impl ToSchema for LosslessNumber {}
+
 impl LosslessNumber {
     pub fn new<T: ToString>(value: T) -> Self {
         LosslessNumber(value.to_string())
     }
 
-    /// Formats the number for JSON serialization according to Plexo requirements
-    /// - If it's an integer (no decimal point), append ".0"
-    /// - If it's already a decimal, ensure it has exactly 2 decimal places
-    /// - If it has more than 2 decimal places, truncate to 2
-    pub fn format_for_json(&self) -> String {
+    /// Exact decimal value of the original string, without an f64 round trip
+    pub fn to_decimal(&self) -> Option<Decimal> {
         let trimmed = self.0.trim();
+        trimmed
+            .parse::<Decimal>()
+            .or_else(|_| Decimal::from_scientific(trimmed))
+            .ok()
+    }
 
-        // Try to parse as a number to validate and format
-        if let Ok(num) = trimmed.parse::<f64>() {
-            // Check if it's effectively an integer
-            if num.fract() == 0.0 {
-                format!("{:.1}", num) // This will format as "131.0"
-            } else {
-                format!("{:.2}", num) // This will format with 2 decimal places
-            }
-        } else {
-            // If it can't be parsed as a number, return as-is but try to format it
-            if trimmed.contains('.') {
-                // It has a decimal point, try to ensure 2 decimal places
-                let parts: Vec<&str> = trimmed.split('.').collect();
-                if parts.len() == 2 {
-                    let integer_part = parts[0];
-                    let decimal_part = parts[1];
-
-                    if decimal_part.len() == 1 {
-                        format!("{}.{}0", integer_part, decimal_part)
-                    } else if decimal_part.len() > 2 {
-                        format!("{}.{}", integer_part, &decimal_part[..2])
-                    } else {
-                        trimmed.to_string()
-                    }
-                } else {
-                    trimmed.to_string()
-                }
-            } else {
-                // No decimal point, append ".0"
-                format!("{}.0", trimmed)
-            }
+    /// Exact sum, None when either side is not a valid decimal or it overflows
+    pub fn checked_add(&self, other: &LosslessNumber) -> Option<LosslessNumber> {
+        let sum = self.to_decimal()?.checked_add(other.to_decimal()?)?;
+        Some(LosslessNumber(sum.to_string()))
+    }
+
+    /// Exact difference, None when either side is not a valid decimal or it overflows
+    pub fn checked_sub(&self, other: &LosslessNumber) -> Option<LosslessNumber> {
+        let difference = self.to_decimal()?.checked_sub(other.to_decimal()?)?;
+        Some(LosslessNumber(difference.to_string()))
+    }
+
+    /// Compare by value, so `10`, `"10.0"` and `1e1` are equal. None when
+    /// either side is not a valid decimal.
+    pub fn decimal_cmp(&self, other: &LosslessNumber) -> Option<std::cmp::Ordering> {
+        Some(self.to_decimal()?.cmp(&other.to_decimal()?))
+    }
+
+    /// The amount with exactly `decimal_places` fraction digits, padding with
+    /// zeros (`0.3` -> `0.30`). An amount with more significant fraction
+    /// digits than that is an error; it is never rounded.
+    pub fn format_with_decimals(&self, decimal_places: u32) -> Result<String, AmountFormatError> {
+        let mut value = self
+            .to_decimal()
+            .ok_or_else(|| AmountFormatError::Invalid(self.0.clone()))?;
+        if value.normalize().scale() > decimal_places {
+            return Err(AmountFormatError::TooManyDecimals {
+                amount: self.0.trim().to_string(),
+                decimal_places,
+            });
+        }
+        // Only trailing zeros can be dropped here, so this never rounds
+        value.rescale(decimal_places);
+        if value.scale() != decimal_places {
+            // Too many integer digits left no room for the fraction
+            return Err(AmountFormatError::Invalid(self.0.clone()));
         }
+        Ok(value.to_string())
+    }
+
+    /// The amount at the fixed scale of a Plexo currency id, see `format_with_decimals`
+    pub fn format_for_currency(&self, currency_id: i32) -> Result<String, AmountFormatError> {
+        self.format_with_decimals(decimal_places_for_currency(currency_id))
     }
 }
@@ -1,3 +1,4 @@
+pub mod builders;
 pub mod common;
 pub mod requests;
 pub mod responses;
@@ -1,32 +1,258 @@
-use super::common::LosslessNumber;
+// Field names mirror Plexo's PascalCase wire format
+#![allow(non_snake_case)]
+
+use super::common::{decimal_places_for_currency, LosslessNumber};
+use crate::services::validation::{self, ValidationError};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use utoipa::openapi::schema::{ObjectBuilder, OneOfBuilder, Type};
+use utoipa::openapi::{RefOr, Schema};
+use utoipa::{PartialSchema, ToSchema};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct AuthorizationRequest {
     pub Client: String,
     pub Request: AuthorizationRequestData,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// `Type`: the customer is identified by our own `MetaReference`
+pub const AUTHORIZATION_TYPE_CLIENT_REFERENCE: i32 = 0;
+/// `Type`: the customer signs in through Plexo's OAuth flow
+pub const AUTHORIZATION_TYPE_OAUTH: i32 = 1;
+
+/// `Action` flags, combined with bitwise OR
+pub const ACTION_SELECT_INSTRUMENT: i32 = 1;
+pub const ACTION_REGISTER_INSTRUMENT: i32 = 2;
+pub const ACTION_DELETE_INSTRUMENT: i32 = 4;
+pub const ACTION_EXTEND_INSTRUMENT: i32 = 64;
+
+// `Type` and `Action` may be sent as Plexo's integer or by name
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum IntOrName {
+    Int(i32),
+    Name(String),
+}
+
+/// How the customer is identified in an authorization. Accepts
+/// `ClientReference`/`OAuth` or the integer; the integer is what gets signed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "i32", try_from = "IntOrName")]
+pub enum AuthorizationType {
+    ClientReference,
+    OAuth,
+}
+
+impl From<AuthorizationType> for i32 {
+    fn from(value: AuthorizationType) -> Self {
+        match value {
+            AuthorizationType::ClientReference => AUTHORIZATION_TYPE_CLIENT_REFERENCE,
+            AuthorizationType::OAuth => AUTHORIZATION_TYPE_OAUTH,
+        }
+    }
+}
+
+impl TryFrom<i32> for AuthorizationType {
+    type Error = String;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match value {
+            AUTHORIZATION_TYPE_CLIENT_REFERENCE => Ok(Self::ClientReference),
+            AUTHORIZATION_TYPE_OAUTH => Ok(Self::OAuth),
+            other => Err(format!("Unknown authorization Type: {}", other)),
+        }
+    }
+}
+
+impl TryFrom<IntOrName> for AuthorizationType {
+    type Error = String;
+
+    fn try_from(value: IntOrName) -> Result<Self, Self::Error> {
+        match value {
+            IntOrName::Int(value) => Self::try_from(value),
+            IntOrName::Name(name) => match name.trim().to_ascii_lowercase().as_str() {
+                "clientreference" => Ok(Self::ClientReference),
+                "oauth" => Ok(Self::OAuth),
+                _ => Err(format!("Unknown authorization Type: {}", name)),
+            },
+        }
+    }
+}
+
+impl PartialSchema for AuthorizationType {
+    fn schema() -> RefOr<Schema> {
+        int_or_name_schema("0 or ClientReference, 1 or OAuth")
+    }
+}
+
+impl ToSchema for AuthorizationType {}
+
+/// `Action` flags. Accepts the integer, a flag name (`SelectInstrument`,
+/// `RegisterInstrument`, `DeleteInstrument`, `ExtendInstrument`) or names
+/// joined with `|`; the combined integer is what gets signed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(into = "i32", try_from = "IntOrName")]
+pub struct AuthorizationAction(i32);
+
+impl AuthorizationAction {
+    pub const SELECT_INSTRUMENT: Self = Self(ACTION_SELECT_INSTRUMENT);
+    pub const REGISTER_INSTRUMENT: Self = Self(ACTION_REGISTER_INSTRUMENT);
+    pub const DELETE_INSTRUMENT: Self = Self(ACTION_DELETE_INSTRUMENT);
+    pub const EXTEND_INSTRUMENT: Self = Self(ACTION_EXTEND_INSTRUMENT);
+
+    const NAMED: [(&'static str, Self); 4] = [
+        ("SelectInstrument", Self::SELECT_INSTRUMENT),
+        ("RegisterInstrument", Self::REGISTER_INSTRUMENT),
+        ("DeleteInstrument", Self::DELETE_INSTRUMENT),
+        ("ExtendInstrument", Self::EXTEND_INSTRUMENT),
+    ];
+
+    pub fn bits(self) -> i32 {
+        self.0
+    }
+
+    /// True when any flag of `other` is set
+    pub fn intersects(self, other: Self) -> bool {
+        self.0 & other.0 != 0
+    }
+}
+
+impl std::ops::BitOr for AuthorizationAction {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl std::ops::BitOrAssign for AuthorizationAction {
+    fn bitor_assign(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+}
+
+impl From<AuthorizationAction> for i32 {
+    fn from(value: AuthorizationAction) -> Self {
+        value.0
+    }
+}
+
+impl TryFrom<i32> for AuthorizationAction {
+    type Error = String;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        let known = Self::NAMED.iter().fold(0, |bits, (_, flag)| bits | flag.0);
+        if value & !known != 0 {
+            return Err(format!("Unknown Action flags: {}", value));
+        }
+        Ok(Self(value))
+    }
+}
+
+impl TryFrom<IntOrName> for AuthorizationAction {
+    type Error = String;
+
+    fn try_from(value: IntOrName) -> Result<Self, Self::Error> {
+        let names = match value {
+            IntOrName::Int(value) => return Self::try_from(value),
+            IntOrName::Name(names) => names,
+        };
+
+        names.split('|').try_fold(Self::default(), |action, name| {
+            let name = name.trim();
+            Self::NAMED
+                .iter()
+                .find(|(known, _)| known.eq_ignore_ascii_case(name))
+                .map(|(_, flag)| action | *flag)
+                .ok_or_else(|| format!("Unknown Action: {}", name))
+        })
+    }
+}
+
+impl PartialSchema for AuthorizationAction {
+    fn schema() -> RefOr<Schema> {
+        int_or_name_schema(
+            "Flags as an integer, or SelectInstrument, RegisterInstrument, DeleteInstrument, ExtendInstrument joined with |",
+        )
+    }
+}
+
+impl ToSchema for AuthorizationAction {}
+
+fn int_or_name_schema(description: &str) -> RefOr<Schema> {
+    OneOfBuilder::new()
+        .item(ObjectBuilder::new().schema_type(Type::Integer))
+        .item(ObjectBuilder::new().schema_type(Type::String))
+        .description(Some(description))
+        .into()
+}
+
+/// Valid combinations:
+/// - Without `ExtendableInstrumentToken`: any of select/register/delete with
+///   either `Type`, but never `ACTION_EXTEND_INSTRUMENT`.
+/// - With `ExtendableInstrumentToken`: `Type` must be client reference (the
+///   token belongs to a known customer), `ACTION_EXTEND_INSTRUMENT` is set
+///   for the caller, `ACTION_SELECT_INSTRUMENT` may be combined with it, and
+///   register/delete are rejected because they contradict extending.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct AuthorizationRequestData {
     #[serde(rename = "Type")]
-    pub request_type: i32,
+    pub request_type: AuthorizationType,
     pub MetaReference: String,
-    pub Action: i32,
+    pub Action: AuthorizationAction,
     pub RedirectUri: String,
     pub OptionalCommerceId: Option<i32>,
     pub ClientInformation: ClientInformation,
     pub OptionalMetadata: Option<String>,
+    /// Plexo issuer ids the customer may choose from, as listed in the
+    /// commerce's Plexo backoffice. Checked against the issuer table in
+    /// `services::validation`, extended by `KNOWN_ISSUER_CODES`.
     pub LimitIssuers: Option<Vec<String>>,
-    pub WebFormSettings: Option<serde_json::Value>,
+    pub WebFormSettings: Option<WebFormSettings>,
     pub ExtendableInstrumentToken: Option<String>,
     pub DoNotUseCallback: Option<bool>,
+    /// Plexo bank ids the customer may choose from. Checked against the bank
+    /// table in `services::validation`, extended by `KNOWN_BANK_CODES`.
     pub LimitBanks: Option<Vec<String>>,
     pub PromotionInfoIssuers: Option<serde_json::Value>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Look of Plexo's hosted form. Fields not modelled here are passed through
+/// untouched in `additional_data`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct WebFormSettings {
+    /// `light` or `dark`
+    pub Theme: Option<String>,
+    /// ISO 639-1 language of the form, e.g. `es` or `en`
+    pub Language: Option<String>,
+    pub ButtonLabels: Option<WebFormButtonLabels>,
+    pub Styling: Option<WebFormStyling>,
+    #[serde(flatten)]
+    pub additional_data: Option<HashMap<String, serde_json::Value>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct WebFormButtonLabels {
+    pub Submit: Option<String>,
+    pub Cancel: Option<String>,
+    #[serde(flatten)]
+    pub additional_data: Option<HashMap<String, serde_json::Value>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct WebFormStyling {
+    /// Hex color, `#RGB` or `#RRGGBB`
+    pub PrimaryColor: Option<String>,
+    /// Hex color, `#RGB` or `#RRGGBB`
+    pub BackgroundColor: Option<String>,
+    pub FontFamily: Option<String>,
+    /// https URL of the logo shown on the form
+    pub LogoUrl: Option<String>,
+    #[serde(flatten)]
+    pub additional_data: Option<HashMap<String, serde_json::Value>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct ClientInformation {
     pub Name: String,
     pub Address: Option<String>,
@@ -36,13 +262,65 @@ pub struct ClientInformation {
     pub IdentificationType: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct RegisterInstrumentRequest {
+    pub Client: String,
+    pub Request: RegisterInstrumentRequestData,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct RegisterInstrumentRequestData {
+    pub ClientInformation: ClientInformation,
+    pub InstrumentData: InstrumentData,
+    pub OptionalCommerceId: Option<i32>,
+    pub OptionalMetadata: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct DeleteInstrumentRequest {
+    pub Client: String,
+    pub Request: DeleteInstrumentRequestData,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct DeleteInstrumentRequestData {
+    pub InstrumentToken: String,
+    pub OptionalCommerceId: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CommerceInfoRequest {
+    pub Client: String,
+    pub Request: CommerceInfoRequestData,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CommerceInfoRequestData {
+    pub CommerceId: i32,
+}
+
+/// Every transaction Plexo holds for one `ClientReferenceId`, a page at a time
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TransactionHistoryRequest {
+    pub Client: String,
+    pub Request: TransactionHistoryRequestData,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TransactionHistoryRequestData {
+    pub ClientReferenceId: String,
+    /// 1-based; Plexo's default applies when unset
+    pub PageNumber: Option<u32>,
+    pub PageSize: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct PaymentRequest {
     pub Client: String,
     pub Request: PaymentRequestData,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct PaymentRequestData {
     pub ClientReferenceId: String,
     pub CurrencyId: i32,
@@ -57,7 +335,26 @@ pub struct PaymentRequestData {
     pub ThreeDSReferenceId: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl PaymentRequestData {
+    /// Exact total of the item amounts at the currency's scale (`0.1 + 0.2`
+    /// is `0.30`), None when an amount is not a valid decimal. A total with
+    /// more fraction digits than the currency allows keeps them, unrounded.
+    pub fn sum_items(&self) -> Option<LosslessNumber> {
+        let total = self
+            .Items
+            .iter()
+            .try_fold(LosslessNumber::new(0), |total, item| {
+                total.checked_add(&item.Amount)
+            })?;
+        let decimal_places = decimal_places_for_currency(self.CurrencyId);
+        Some(match total.format_with_decimals(decimal_places) {
+            Ok(formatted) => LosslessNumber(formatted),
+            Err(_) => total,
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct FinancialInclusion {
     pub BilledAmount: LosslessNumber,
     pub InvoiceNumber: Option<i32>,
@@ -67,13 +364,13 @@ pub struct FinancialInclusion {
     pub VATAmount: Option<LosslessNumber>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct PaymentItem {
     pub Amount: LosslessNumber,
     pub ClientItemReferenceId: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct PaymentInstrumentInput {
     pub InstrumentToken: String,
     pub UseExtendedClientCreditIfAvailable: bool,
@@ -81,24 +378,124 @@ pub struct PaymentInstrumentInput {
     pub InstrumentData: Option<InstrumentData>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct InstrumentData {
     pub Issuer: Option<String>,
     #[serde(flatten)]
     pub additional_data: Option<HashMap<String, serde_json::Value>>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
 #[serde(rename_all = "PascalCase")]
 pub struct StatusRequest {
     pub client: String,
-    pub request: ReferenceRequest,
+    #[schema(value_type = ReferenceRequest)]
+    pub request: StatusQuery,
+}
+
+impl StatusRequest {
+    pub fn new(client: impl Into<String>, query: StatusQuery) -> Self {
+        Self {
+            client: client.into(),
+            request: query,
+        }
+    }
+}
+
+/// Many status lookups for the same client, answered as a stream
+#[derive(Deserialize, Debug, ToSchema)]
+#[serde(rename_all = "PascalCase")]
+pub struct BatchStatusRequest {
+    pub client: String,
+    pub requests: Vec<ReferenceRequest>,
+}
+
+/// Status `Type`: look the operation up by our `ClientReferenceId`
+pub const REFERENCE_TYPE_CLIENT_REFERENCE: i32 = 0;
+/// Status `Type`: look the operation up by Plexo's transaction id
+pub const REFERENCE_TYPE_TRANSACTION_ID: i32 = 1;
+
+/// What a status request looks up. Read from any `ReferenceRequest` form, so
+/// an empty or ambiguous lookup is rejected when the body is parsed, and
+/// always sent to Plexo as `Type` + `MetaReference`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "ReferenceRequest")]
+pub enum StatusQuery {
+    ByTransactionId(String),
+    ByClientReference(String),
+}
+
+impl StatusQuery {
+    pub fn reference(&self) -> &str {
+        match self {
+            Self::ByTransactionId(reference) | Self::ByClientReference(reference) => reference,
+        }
+    }
+
+    pub(crate) fn reference_type(&self) -> i32 {
+        match self {
+            Self::ByTransactionId(_) => REFERENCE_TYPE_TRANSACTION_ID,
+            Self::ByClientReference(_) => REFERENCE_TYPE_CLIENT_REFERENCE,
+        }
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// The lookup as callers send it: either `TransactionId` or
+/// `ClientReferenceId`, or Plexo's own `Type` + `MetaReference` pair. Exactly
+/// one form must be given, see `validation::validate_status_request`.
+#[derive(Deserialize, Debug, Default, ToSchema)]
 #[serde(rename_all = "PascalCase")]
 pub struct ReferenceRequest {
+    pub transaction_id: Option<String>,
+    pub client_reference_id: Option<String>,
     #[serde(rename = "Type")]
-    pub reference_type: i32,
-    pub meta_reference: String,
+    pub reference_type: Option<i32>,
+    pub meta_reference: Option<String>,
+}
+
+impl ReferenceRequest {
+    /// The reference being looked up, whichever form carried it
+    pub fn reference(&self) -> Option<&str> {
+        self.transaction_id
+            .as_deref()
+            .or(self.client_reference_id.as_deref())
+            .or(self.meta_reference.as_deref())
+    }
+}
+
+impl TryFrom<ReferenceRequest> for StatusQuery {
+    type Error = ValidationError;
+
+    fn try_from(request: ReferenceRequest) -> Result<Self, Self::Error> {
+        validation::validate_status_request(&request)
+    }
+}
+
+impl Serialize for StatusQuery {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::SerializeStruct;
+
+        let mut state = serializer.serialize_struct("ReferenceRequest", 2)?;
+        state.serialize_field("Type", &self.reference_type())?;
+        state.serialize_field("MetaReference", self.reference())?;
+        state.end()
+    }
+}
+
+/// Body of `POST /api/admin/rotate-cert`
+#[derive(Deserialize, ToSchema)]
+pub struct RotateCertRequest {
+    /// The new PKCS12 bundle, base64 encoded
+    pub pfx_base64: String,
+    pub pfx_password: String,
+}
+
+// Never print key material, even in debug logs
+impl std::fmt::Debug for RotateCertRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RotateCertRequest").finish_non_exhaustive()
+    }
 }
@@ -1,21 +1,575 @@
-use serde::{Deserialize, Serialize};
+// Field names mirror Plexo's PascalCase wire format
+#![allow(non_snake_case)]
 
-#[derive(Debug, Serialize, Deserialize)]
+use crate::models::common::LosslessNumber;
+use crate::services::plexo_service::PlexoServiceError;
+use crate::services::redaction;
+use crate::services::validation::ValidationError;
+use serde::de::DeserializeOwned;
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::json;
+use utoipa::ToSchema;
+
+/// Layout of the signed envelope on the wire, selected by `ENVELOPE_FORMAT`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EnvelopeFormat {
+    /// `{"Object": {"Fingerprint", "Object", "UTCUnixTimeExpiration"}, "Signature"}`
+    #[default]
+    V1,
+    /// `{"signedObject": {"header": {"fingerprint", "utcUnixTimeExpiration"}, "payload"}, "signature"}`
+    V2,
+}
+
+impl EnvelopeFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "v1" | "1" => Some(Self::V1),
+            "v2" | "2" => Some(Self::V2),
+            _ => None,
+        }
+    }
+}
+
+/// Serialized in the layout of its `format`; the schema documents V1
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct SignedRequest {
     pub Object: SignedObject,
     pub Signature: String,
+    #[serde(skip)]
+    pub format: EnvelopeFormat,
+}
+
+impl Serialize for SignedRequest {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (object_key, signature_key) = match self.format {
+            EnvelopeFormat::V1 => ("Object", "Signature"),
+            EnvelopeFormat::V2 => ("signedObject", "signature"),
+        };
+        let mut map = serializer.serialize_map(Some(2))?;
+        map.serialize_entry(object_key, &self.Object.to_value(self.format))?;
+        map.serialize_entry(signature_key, &self.Signature)?;
+        map.end()
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct SignedObject {
     pub Fingerprint: String,
     pub Object: serde_json::Value,
     pub UTCUnixTimeExpiration: i64,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl SignedObject {
+    /// The object as laid out in `format`. This is both what gets
+    /// canonicalized and signed and what is sent, so the two always agree.
+    pub fn to_value(&self, format: EnvelopeFormat) -> serde_json::Value {
+        match format {
+            EnvelopeFormat::V1 => json!({
+                "Fingerprint": self.Fingerprint,
+                "Object": self.Object,
+                "UTCUnixTimeExpiration": self.UTCUnixTimeExpiration,
+            }),
+            EnvelopeFormat::V2 => json!({
+                "header": {
+                    "fingerprint": self.Fingerprint,
+                    "utcUnixTimeExpiration": self.UTCUnixTimeExpiration,
+                },
+                "payload": self.Object,
+            }),
+        }
+    }
+}
+
+// Transaction states Plexo can still move out of
+const IN_PROGRESS_STATUSES: [&str; 4] = ["Pending", "Processing", "InProgress", "Created"];
+// Transaction states where the issuer or Plexo turned the payment down
+const DECLINED_STATUSES: [&str; 3] = ["Rejected", "Declined", "Denied"];
+
+/// Whether a transaction `Status` from Plexo is still expected to change
+pub fn is_in_progress_status(status: &str) -> bool {
+    IN_PROGRESS_STATUSES
+        .iter()
+        .any(|pending| status.eq_ignore_ascii_case(pending))
+}
+
+/// What happened to a Plexo operation, more precisely than `success`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum Outcome {
+    /// Plexo accepted the operation
+    Approved,
+    /// Plexo or the issuer turned it down
+    Declined,
+    /// Accepted, but the transaction has not reached a final state
+    Pending,
+    /// The cardholder must complete a 3-D Secure challenge first
+    #[serde(rename = "Requires3DS")]
+    Requires3DS,
+    /// The operation did not get a result from Plexo
+    Error,
+}
+
+impl Outcome {
+    /// Classify a result Plexo answered with by its result code and, when
+    /// it carries one, the transaction status
+    pub fn from_plexo(response: &serde_json::Value) -> Self {
+        let result_code = response
+            .get("ResultCode")
+            .and_then(serde_json::Value::as_i64)
+            .unwrap_or(0);
+        if result_code != 0 {
+            return Outcome::Declined;
+        }
+
+        match response
+            .pointer("/Response/Status")
+            .and_then(serde_json::Value::as_str)
+        {
+            Some(status) if is_in_progress_status(status) => Outcome::Pending,
+            Some(status)
+                if DECLINED_STATUSES
+                    .iter()
+                    .any(|declined| status.eq_ignore_ascii_case(declined)) =>
+            {
+                Outcome::Declined
+            }
+            _ => Outcome::Approved,
+        }
+    }
+
+    /// Plexo turning a request down is a decline; anything else that failed
+    /// never produced a result
+    pub fn from_error(error: &PlexoServiceError) -> Self {
+        match error {
+            PlexoServiceError::PlexoResultError { .. } | PlexoServiceError::InstrumentNotFound => {
+                Outcome::Declined
+            }
+            _ => Outcome::Error,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct ApiResponse<T> {
     pub success: bool,
+    /// Set on Plexo operations; `success` is kept for existing callers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outcome: Option<Outcome>,
     pub data: Option<T>,
     pub error: Option<String>,
+    /// Machine-readable reason when `success` is false and the caller must act
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<String>,
+    /// Plexo's redacted response as received, only when explicitly requested
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw: Option<serde_json::Value>,
+    /// Every rejected field when the request failed validation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub validation_errors: Option<Vec<ValidationError>>,
+    /// HTTP status Plexo answered with, when the failure came with one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upstream_status: Option<u16>,
+    /// What became of the authorization, when a purchase that asked for a void did not go through
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub authorization_void: Option<AuthorizationVoid>,
+}
+
+// Written out because deriving would require `T: Default`
+impl<T> Default for ApiResponse<T> {
+    fn default() -> Self {
+        Self {
+            success: false,
+            outcome: None,
+            data: None,
+            error: None,
+            error_code: None,
+            raw: None,
+            validation_errors: None,
+            upstream_status: None,
+            authorization_void: None,
+        }
+    }
+}
+
+impl<T> ApiResponse<T> {
+    /// A successful response carrying `data`. Set anything else with
+    /// `ApiResponse { outcome, ..ApiResponse::ok(data) }`.
+    pub fn ok(data: T) -> Self {
+        Self {
+            success: true,
+            data: Some(data),
+            ..Default::default()
+        }
+    }
+
+    /// A failed response with `message` and nothing else set
+    pub fn error(message: impl Into<String>) -> Self {
+        Self {
+            error: Some(message.into()),
+            ..Default::default()
+        }
+    }
+}
+
+/// What became of the authorization behind a purchase that did not go through
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum VoidStatus {
+    /// Plexo declined the purchase and accepted the cancel
+    Voided,
+    /// Plexo declined the purchase but the cancel did not go through
+    VoidFailed,
+    /// The purchase failed without a decline and may have been charged, so
+    /// no cancel was sent
+    LeftOpen,
+}
+
+/// The authorization behind a purchase sent with `x-void-authorization`,
+/// reported when the purchase did not go through
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AuthorizationVoid {
+    /// `MetaReference` of the authorization
+    pub reference: String,
+    pub status: VoidStatus,
+    /// Outcome of the cancel, when one was sent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outcome: Option<Outcome>,
+    /// Why the cancel failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl AuthorizationVoid {
+    /// Report the cancel sent for a declined purchase
+    pub fn attempted(
+        reference: impl Into<String>,
+        result: &Result<serde_json::Value, PlexoServiceError>,
+    ) -> Self {
+        let (outcome, error) = match result {
+            Ok(response) => (
+                Outcome::from_plexo(response),
+                response
+                    .get("ErrorMessage")
+                    .and_then(serde_json::Value::as_str)
+                    .map(str::to_string),
+            ),
+            Err(e) => (Outcome::from_error(e), Some(e.to_string())),
+        };
+        Self {
+            reference: reference.into(),
+            status: match outcome {
+                Outcome::Approved => VoidStatus::Voided,
+                _ => VoidStatus::VoidFailed,
+            },
+            outcome: Some(outcome),
+            error,
+        }
+    }
+
+    /// Report an authorization no cancel was sent for
+    pub fn left_open(reference: impl Into<String>) -> Self {
+        Self {
+            reference: reference.into(),
+            status: VoidStatus::LeftOpen,
+            outcome: None,
+            error: None,
+        }
+    }
+}
+
+/// Access to the untouched Plexo response behind an API result
+pub trait RawResponse {
+    fn raw(&self) -> Option<&serde_json::Value>;
+}
+
+// Untyped results are Plexo's response itself
+impl RawResponse for serde_json::Value {
+    fn raw(&self) -> Option<&serde_json::Value> {
+        Some(self)
+    }
+}
+
+/// One line of a streamed batch status answer. Lines arrive in completion
+/// order, `index` points back to the lookup in the request.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct BatchStatusLine {
+    pub index: usize,
+    pub reference: Option<String>,
+    pub success: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outcome: Option<Outcome>,
+    pub data: Option<serde_json::Value>,
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upstream_status: Option<u16>,
+}
+
+/// Typed form of an authorize, purchase or status result, returned to
+/// clients that ask for API v2
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct OperationResult {
+    pub result_code: i64,
+    pub error_message: Option<String>,
+    pub response: Option<serde_json::Value>,
+}
+
+impl OperationResult {
+    pub fn from_plexo(value: &serde_json::Value) -> Self {
+        Self {
+            result_code: value
+                .get("ResultCode")
+                .and_then(serde_json::Value::as_i64)
+                .unwrap_or(0),
+            error_message: value
+                .get("ErrorMessage")
+                .and_then(serde_json::Value::as_str)
+                .map(str::to_string),
+            response: value.get("Response").filter(|r| !r.is_null()).cloned(),
+        }
+    }
+}
+
+/// Envelope Plexo wraps around every operation result
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct PlexoResponse<T> {
+    pub ResultCode: i32,
+    pub ErrorMessage: Option<String>,
+    pub Response: Option<T>,
+}
+
+impl<T: DeserializeOwned> PlexoResponse<T> {
+    /// Read the envelope out of a successful Plexo answer. A body of the wrong
+    /// shape is Plexo's fault, not the caller's, so it is reported as an
+    /// unexpected response rather than a serialization error.
+    pub fn from_value(response: &serde_json::Value) -> Result<Self, PlexoServiceError> {
+        Self::deserialize(response).map_err(|e| PlexoServiceError::UnexpectedResponse {
+            // Only 2xx answers get this far
+            status: 200,
+            snippet: redaction::describe_json_error(&e),
+        })
+    }
+}
+
+impl<T> PlexoResponse<T> {
+    /// Plexo reports success with a zero result code
+    pub fn ensure_ok(&self) -> Result<(), PlexoServiceError> {
+        if self.ResultCode != 0 {
+            return Err(PlexoServiceError::PlexoResultError {
+                code: self.ResultCode,
+                message: self
+                    .ErrorMessage
+                    .clone()
+                    .unwrap_or_else(|| "Unknown Plexo error".to_string()),
+            });
+        }
+
+        Ok(())
+    }
+
+    pub fn into_result(self) -> Result<T, PlexoServiceError> {
+        self.ensure_ok()?;
+
+        self.Response
+            .ok_or_else(|| PlexoServiceError::PlexoResultError {
+                code: self.ResultCode,
+                message: "Plexo response did not include a result".to_string(),
+            })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct RegisteredInstrument {
+    pub InstrumentToken: String,
+    pub Issuer: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct RegisterInstrumentResponse {
+    pub instrument_token: String,
+    pub issuer: Option<serde_json::Value>,
+    #[serde(skip)]
+    pub raw: serde_json::Value,
+}
+
+impl RawResponse for RegisterInstrumentResponse {
+    fn raw(&self) -> Option<&serde_json::Value> {
+        Some(&self.raw)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct DeleteInstrumentResponse {
+    pub instrument_token: String,
+    pub deleted: bool,
+    #[serde(skip)]
+    pub raw: serde_json::Value,
+}
+
+impl RawResponse for DeleteInstrumentResponse {
+    fn raw(&self) -> Option<&serde_json::Value> {
+        Some(&self.raw)
+    }
+}
+
+/// Commerce configuration as Plexo returns it
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CommerceConfig {
+    pub CommerceId: i32,
+    pub Name: Option<String>,
+    #[serde(default)]
+    pub IsActive: bool,
+    /// ISO 4217 numeric ids the commerce can charge in
+    #[serde(default)]
+    pub Currencies: Vec<i32>,
+    #[serde(default)]
+    pub Issuers: Vec<serde_json::Value>,
+}
+
+/// Whether a commerce can take payments, and with what
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CommerceInfo {
+    pub commerce_id: i32,
+    pub name: Option<String>,
+    pub active: bool,
+    pub currencies: Vec<i32>,
+    pub issuers: Vec<serde_json::Value>,
+    #[serde(skip)]
+    pub raw: serde_json::Value,
+}
+
+impl RawResponse for CommerceInfo {
+    fn raw(&self) -> Option<&serde_json::Value> {
+        Some(&self.raw)
+    }
+}
+
+/// A page of transaction history as Plexo returns it
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TransactionPage {
+    #[serde(default)]
+    pub Transactions: Vec<TransactionRecord>,
+    pub PageNumber: Option<u32>,
+    pub PageSize: Option<u32>,
+    pub TotalCount: Option<u64>,
+}
+
+/// One transaction in Plexo's history
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TransactionRecord {
+    pub Id: String,
+    /// Operation kind, e.g. `Purchase` or `Refund`
+    #[serde(rename = "Type")]
+    pub transaction_type: Option<String>,
+    pub Status: Option<String>,
+    pub CurrencyId: Option<i32>,
+    pub Amount: Option<LosslessNumber>,
+    pub CreatedAt: Option<String>,
+}
+
+/// One transaction tied to a client reference
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TransactionSummary {
+    pub transaction_id: String,
+    pub transaction_type: Option<String>,
+    pub status: Option<String>,
+    pub currency_id: Option<i32>,
+    pub amount: Option<LosslessNumber>,
+    pub created_at: Option<String>,
+}
+
+impl From<TransactionRecord> for TransactionSummary {
+    fn from(record: TransactionRecord) -> Self {
+        Self {
+            transaction_id: record.Id,
+            transaction_type: record.transaction_type,
+            status: record.Status,
+            currency_id: record.CurrencyId,
+            amount: record.Amount,
+            created_at: record.CreatedAt,
+        }
+    }
+}
+
+/// A page of the transactions tied to a client reference
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TransactionHistory {
+    pub client_reference_id: String,
+    pub transactions: Vec<TransactionSummary>,
+    pub page_number: Option<u32>,
+    pub page_size: Option<u32>,
+    /// Transactions across all pages, when Plexo reports it
+    pub total_count: Option<u64>,
+    #[serde(skip)]
+    pub raw: serde_json::Value,
+}
+
+impl RawResponse for TransactionHistory {
+    fn raw(&self) -> Option<&serde_json::Value> {
+        Some(&self.raw)
+    }
+}
+
+/// Signing certificate details reported by `/api/cert-info`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CertInfo {
+    pub fingerprint: String,
+    pub subject: String,
+    pub issuer: String,
+    pub not_before: chrono::DateTime<chrono::Utc>,
+    pub not_after: chrono::DateTime<chrono::Utc>,
+}
+
+/// Which dependencies are usable, as `/ready` reports it
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct Readiness {
+    pub crypto_service: bool,
+    pub plexo_client: bool,
+    /// False once the signing certificate has expired; Plexo rejects its signatures
+    pub certificate_valid: bool,
+    pub certificate_not_after: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Canonical form of a payload as `/api/validate` reports it
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CanonicalPreview {
+    /// Exactly the string that would be signed for this payload
+    pub canonical: String,
+    /// The same form with keys in the order they were submitted, for comparing
+    /// against other implementations. Never what gets signed.
+    pub canonical_unsorted: String,
+    pub modified_fields: Vec<ModifiedField>,
+}
+
+/// A field that cleaning changed before canonicalization
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ModifiedField {
+    /// JSON pointer to the field in the submitted payload
+    pub path: String,
+    /// `null_removed`, `number_reformatted` or `unicode_normalized`
+    pub change: String,
+    pub original: serde_json::Value,
+    pub canonical: Option<serde_json::Value>,
+}
+
+/// 3-D Secure challenge Plexo returns when the issuer needs the cardholder to
+/// authenticate before the purchase can complete
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "PascalCase")]
+pub struct ThreeDSChallenge {
+    /// Issuer access control server the cardholder must be sent to
+    pub acs_url: String,
+    pub pa_req: String,
+    pub transaction_id: String,
+}
+
+impl ThreeDSChallenge {
+    /// Extract the challenge from a purchase response, if Plexo asked for one
+    pub fn from_response(response: &serde_json::Value) -> Option<Self> {
+        let challenge = response.pointer("/Response/ThreeDSChallenge")?;
+        if challenge.is_null() {
+            return None;
+        }
+        serde_json::from_value(challenge.clone()).ok()
+    }
 }
@@ -0,0 +1,122 @@
+use crate::services::plexo_service::{Operation, PlexoServiceError};
+use lazy_static::lazy_static;
+use log::warn;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Latencies older than this no longer count, so shedding stops once Plexo recovers
+const LATENCY_WINDOW: Duration = Duration::from_secs(30);
+// Upper bound on remembered latencies, the p99 of the most recent ones
+const MAX_SAMPLES: usize = 1_000;
+// Below this many samples a p99 is noise and never sheds
+const MIN_SAMPLES: usize = 20;
+
+lazy_static! {
+    // Enabled by SHED_MAX_IN_FLIGHT and/or SHED_MAX_P99_MS
+    static ref THRESHOLDS: Option<SheddingThresholds> = SheddingThresholds::from_env();
+    static ref LATENCIES: Mutex<VecDeque<(Instant, Duration)>> = Mutex::new(VecDeque::new());
+}
+
+/// Load at which non-critical operations are turned away before any work
+#[derive(Debug, Clone, Copy)]
+pub struct SheddingThresholds {
+    pub max_in_flight: Option<usize>,
+    pub max_p99: Option<Duration>,
+}
+
+impl SheddingThresholds {
+    fn from_env() -> Option<Self> {
+        let read = |name: &str| {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| value.trim().parse::<u64>().ok())
+                .filter(|value| *value > 0)
+        };
+        let thresholds = Self {
+            max_in_flight: read("SHED_MAX_IN_FLIGHT").map(|value| value as usize),
+            max_p99: read("SHED_MAX_P99_MS").map(Duration::from_millis),
+        };
+        (thresholds.max_in_flight.is_some() || thresholds.max_p99.is_some()).then_some(thresholds)
+    }
+
+    /// Why a sheddable call should be refused at this load, if it should
+    pub fn exceeded(&self, in_flight: usize, p99: Option<Duration>) -> Option<String> {
+        if let Some(max) = self.max_in_flight.filter(|max| in_flight >= *max) {
+            return Some(format!(
+                "{} Plexo calls in flight (limit {})",
+                in_flight, max
+            ));
+        }
+        match (self.max_p99, p99) {
+            (Some(max), Some(p99)) if p99 > max => Some(format!(
+                "Plexo p99 latency {} ms (limit {} ms)",
+                p99.as_millis(),
+                max.as_millis()
+            )),
+            _ => None,
+        }
+    }
+}
+
+// Lookups can be retried later at no cost; payments and instrument changes
+// are what callers are waiting on, so they are never shed
+fn sheddable(operation: Operation) -> bool {
+    matches!(
+        operation,
+        Operation::Status | Operation::CommerceInfo | Operation::TransactionHistory
+    )
+}
+
+/// Refuse `operation` with `Overloaded` when it is non-critical and the
+/// service is past a shedding threshold
+pub fn admit(operation: Operation, in_flight: usize) -> Result<(), PlexoServiceError> {
+    let Some(thresholds) = THRESHOLDS.as_ref() else {
+        return Ok(());
+    };
+    if !sheddable(operation) {
+        return Ok(());
+    }
+
+    match thresholds.exceeded(in_flight, p99_latency()) {
+        Some(reason) => {
+            warn!("Shedding {} request: {}", operation.as_str(), reason);
+            Err(PlexoServiceError::Overloaded)
+        }
+        None => Ok(()),
+    }
+}
+
+/// Remember how long a Plexo round trip took
+pub fn record_latency(latency: Duration) {
+    let now = Instant::now();
+    let mut samples = LATENCIES.lock().unwrap_or_else(|e| e.into_inner());
+    samples.push_back((now, latency));
+    while samples.len() > MAX_SAMPLES {
+        samples.pop_front();
+    }
+    evict_stale(&mut samples, now);
+}
+
+/// p99 of recent Plexo latencies, None until there are enough samples
+pub fn p99_latency() -> Option<Duration> {
+    let mut samples = LATENCIES.lock().unwrap_or_else(|e| e.into_inner());
+    evict_stale(&mut samples, Instant::now());
+    if samples.len() < MIN_SAMPLES {
+        return None;
+    }
+
+    let mut latencies: Vec<Duration> = samples.iter().map(|(_, latency)| *latency).collect();
+    latencies.sort_unstable();
+    let index = (latencies.len() * 99).div_ceil(100) - 1;
+    Some(latencies[index])
+}
+
+fn evict_stale(samples: &mut VecDeque<(Instant, Duration)>, now: Instant) {
+    while samples
+        .front()
+        .is_some_and(|(recorded, _)| now.duration_since(*recorded) > LATENCY_WINDOW)
+    {
+        samples.pop_front();
+    }
+}
@@ -0,0 +1,81 @@
+use crate::services::metrics;
+use lazy_static::lazy_static;
+use log::error;
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::sync::Mutex;
+
+/// One line of the audit trail, written per completed payment operation.
+/// Only identifiers and outcomes are recorded, never card or personal data.
+#[derive(Debug, Serialize)]
+pub struct AuditRecord<'a> {
+    pub timestamp: String,
+    pub correlation_id: &'a str,
+    pub operation: &'a str,
+    pub client_reference: Option<&'a str>,
+    pub result_code: Option<i64>,
+    pub http_status: u16,
+    pub success: bool,
+}
+
+enum AuditSink {
+    Stdout,
+    File(File),
+    Disabled,
+}
+
+lazy_static! {
+    // AUDIT_LOG_PATH selects a file to append to; unset writes to stdout, "off" disables
+    static ref AUDIT_SINK: Mutex<AuditSink> = Mutex::new(open_sink());
+}
+
+fn open_sink() -> AuditSink {
+    match std::env::var("AUDIT_LOG_PATH") {
+        Ok(path) if path == "off" => AuditSink::Disabled,
+        Ok(path) if !path.is_empty() && path != "-" => {
+            match OpenOptions::new().create(true).append(true).open(&path) {
+                Ok(file) => AuditSink::File(file),
+                Err(e) => {
+                    error!(
+                        "Failed to open audit log {}: {}, falling back to stdout",
+                        path, e
+                    );
+                    AuditSink::Stdout
+                }
+            }
+        }
+        _ => AuditSink::Stdout,
+    }
+}
+
+/// Append a record to the audit sink. Failures never propagate to the
+/// request; they are logged and counted so they can be alerted on.
+pub fn record(record: &AuditRecord) {
+    if let Err(e) = write_record(record) {
+        metrics::record_audit_failure();
+        error!(
+            "Failed to write audit record for {} ({}): {}",
+            record.operation, record.correlation_id, e
+        );
+    }
+}
+
+fn write_record(record: &AuditRecord) -> io::Result<()> {
+    let mut line = serde_json::to_vec(record)?;
+    line.push(b'\n');
+
+    let mut sink = AUDIT_SINK.lock().unwrap_or_else(|e| e.into_inner());
+    match &mut *sink {
+        AuditSink::Stdout => {
+            let mut stdout = io::stdout().lock();
+            stdout.write_all(&line)?;
+            stdout.flush()
+        }
+        AuditSink::File(file) => {
+            file.write_all(&line)?;
+            file.flush()
+        }
+        AuditSink::Disabled => Ok(()),
+    }
+}
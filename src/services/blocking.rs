@@ -0,0 +1,125 @@
+//! Synchronous `PlexoClient` for tools that don't run an async runtime.
+//!
+//! Like reqwest's blocking client, every call is driven to completion on a
+//! runtime owned by the wrapper, so its methods must not be called from inside
+//! an async context; they panic there.
+
+use crate::models::requests::{
+    AuthorizationRequest, CommerceInfoRequest, DeleteInstrumentRequest, PaymentRequest,
+    RegisterInstrumentRequest, StatusQuery, StatusRequest, TransactionHistoryRequest,
+};
+use crate::models::responses::{
+    CommerceInfo, DeleteInstrumentResponse, RegisterInstrumentResponse, TransactionHistory,
+};
+use crate::services::plexo_service::{
+    CompensatedPurchase, PlexoClient, PlexoServiceError, RequestOptions,
+};
+use serde_json::Value;
+use tokio::runtime::{Builder, Runtime};
+
+pub struct BlockingPlexoClient {
+    inner: PlexoClient,
+    runtime: Runtime,
+}
+
+impl BlockingPlexoClient {
+    /// Wrap an already configured client
+    pub fn new(inner: PlexoClient) -> Result<Self, PlexoServiceError> {
+        let runtime = Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| {
+                PlexoServiceError::ConfigurationError(format!(
+                    "Failed to start the blocking client runtime: {}",
+                    e
+                ))
+            })?;
+        Ok(Self { inner, runtime })
+    }
+
+    /// Configure from the environment as described on `plexo_service::init()`.
+    /// The crypto service must be initialized first.
+    pub fn from_env() -> Result<Self, PlexoServiceError> {
+        Self::new(PlexoClient::from_env()?)
+    }
+
+    /// The wrapped client, e.g. for `presign`, which never blocks
+    pub fn inner(&self) -> &PlexoClient {
+        &self.inner
+    }
+
+    pub fn authorize(
+        &self,
+        auth_request: AuthorizationRequest,
+        options: RequestOptions,
+    ) -> Result<Value, PlexoServiceError> {
+        self.runtime
+            .block_on(self.inner.authorize(auth_request, options))
+    }
+
+    pub fn purchase(
+        &self,
+        payment_request: PaymentRequest,
+        options: RequestOptions,
+    ) -> Result<Value, PlexoServiceError> {
+        self.runtime
+            .block_on(self.inner.purchase(payment_request, options))
+    }
+
+    pub fn status(
+        &self,
+        status_request: StatusRequest,
+        options: RequestOptions,
+    ) -> Result<Value, PlexoServiceError> {
+        self.runtime
+            .block_on(self.inner.status(status_request, options))
+    }
+
+    pub fn purchase_or_void(
+        &self,
+        payment_request: PaymentRequest,
+        authorization: StatusQuery,
+        options: RequestOptions,
+    ) -> CompensatedPurchase {
+        self.runtime.block_on(
+            self.inner
+                .purchase_or_void(payment_request, authorization, options),
+        )
+    }
+
+    pub fn register_instrument(
+        &self,
+        register_request: RegisterInstrumentRequest,
+        options: RequestOptions,
+    ) -> Result<RegisterInstrumentResponse, PlexoServiceError> {
+        self.runtime
+            .block_on(self.inner.register_instrument(register_request, options))
+    }
+
+    pub fn commerce_info(
+        &self,
+        commerce_request: CommerceInfoRequest,
+        options: RequestOptions,
+    ) -> Result<CommerceInfo, PlexoServiceError> {
+        self.runtime
+            .block_on(self.inner.commerce_info(commerce_request, options))
+    }
+
+    pub fn delete_instrument(
+        &self,
+        delete_request: DeleteInstrumentRequest,
+        options: RequestOptions,
+    ) -> Result<DeleteInstrumentResponse, PlexoServiceError> {
+        self.runtime
+            .block_on(self.inner.delete_instrument(delete_request, options))
+    }
+
+    pub fn transaction_history(
+        &self,
+        history_request: TransactionHistoryRequest,
+        options: RequestOptions,
+    ) -> Result<TransactionHistory, PlexoServiceError> {
+        self.runtime
+            .block_on(self.inner.transaction_history(history_request, options))
+    }
+}
@@ -0,0 +1,182 @@
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use log::{error, info};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Largest difference from Plexo's clock accepted silently, when CLOCK_SKEW_MAX_SECS is not set
+const DEFAULT_MAX_SKEW_SECS: i64 = 30;
+// How long a bad skew observation keeps signing refused
+const SKEW_REFUSAL_WINDOW: Duration = Duration::from_secs(60);
+
+lazy_static! {
+    // CLOCK_SKEW_MAX_SECS sets the threshold; CLOCK_SKEW_REFUSE=true stops signing past it
+    static ref SKEW_MONITOR: SkewMonitor = SkewMonitor::new(
+        std::env::var("CLOCK_SKEW_MAX_SECS")
+            .ok()
+            .and_then(|value| value.trim().parse::<i64>().ok())
+            .filter(|max| *max > 0)
+            .unwrap_or(DEFAULT_MAX_SKEW_SECS),
+        std::env::var("CLOCK_SKEW_REFUSE")
+            .is_ok_and(|value| value.trim().eq_ignore_ascii_case("true")),
+    );
+}
+
+/// Source of the current time for expiration and rate-limit logic
+pub trait Clock: Send + Sync {
+    /// Wall-clock time, for anything Plexo or callers compare against
+    fn now_utc(&self) -> DateTime<Utc>;
+    /// Monotonic time, for measuring windows and durations
+    fn now_instant(&self) -> Instant;
+}
+
+/// The real clock
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_utc(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    fn now_instant(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Tracks how far our clock is from a trusted one (Plexo's `Date` header).
+/// Expirations are computed locally, so a clock that is far off makes Plexo
+/// reject every signature as expired or too far ahead.
+pub struct SkewMonitor {
+    max_skew_secs: i64,
+    refuse: bool,
+    // Local minus reference in seconds, and when it was observed
+    last: Mutex<Option<(i64, Instant)>>,
+}
+
+impl SkewMonitor {
+    pub fn new(max_skew_secs: i64, refuse: bool) -> Self {
+        Self {
+            max_skew_secs,
+            refuse,
+            last: Mutex::new(None),
+        }
+    }
+
+    /// Record a reference time seen at `local`, logging when the skew crosses
+    /// the threshold in either direction. Returns the skew in seconds.
+    pub fn observe(&self, local: DateTime<Utc>, reference: DateTime<Utc>) -> i64 {
+        let skew = (local - reference).num_seconds();
+        let previous = self
+            .last
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .replace((skew, Instant::now()));
+        let was_over = previous.is_some_and(|(previous, _)| self.exceeds(previous));
+
+        if self.exceeds(skew) && !was_over {
+            error!(
+                "Local clock is {} s {} Plexo's (limit {} s); signatures will carry wrong expirations{}",
+                skew.abs(),
+                if skew > 0 { "ahead of" } else { "behind" },
+                self.max_skew_secs,
+                if self.refuse { ", signing is refused until it is fixed" } else { "" }
+            );
+        } else if was_over && !self.exceeds(skew) {
+            info!(
+                "Local clock is back within {} s of Plexo's",
+                self.max_skew_secs
+            );
+        }
+        skew
+    }
+
+    /// Latest observed skew in seconds, None before any reference time was seen
+    pub fn last_skew_secs(&self) -> Option<i64> {
+        self.last
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .map(|(skew, _)| skew)
+    }
+
+    /// Err with the reason when signing should be refused because of the
+    /// skew. A refusal lapses once the observation is old, so a later call can
+    /// reach Plexo and measure the clock again.
+    pub fn check(&self) -> Result<(), String> {
+        let last = *self.last.lock().unwrap_or_else(|e| e.into_inner());
+        match last {
+            Some((skew, observed_at))
+                if self.refuse
+                    && self.exceeds(skew)
+                    && observed_at.elapsed() < SKEW_REFUSAL_WINDOW =>
+            {
+                Err(format!(
+                    "Local clock is {} s off Plexo's, more than the {} s allowed",
+                    skew, self.max_skew_secs
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn exceeds(&self, skew: i64) -> bool {
+        skew.saturating_abs() > self.max_skew_secs
+    }
+}
+
+/// The process-wide monitor configured from the environment
+pub fn skew_monitor() -> &'static SkewMonitor {
+    &SKEW_MONITOR
+}
+
+/// Clock that only moves when told to, so expiration and rate-limit windows
+/// can be crossed deterministically. Only compiled with the `test-utils` feature.
+#[cfg(feature = "test-utils")]
+pub struct MockClock {
+    start_utc: DateTime<Utc>,
+    start_instant: Instant,
+    elapsed: Mutex<Duration>,
+}
+
+#[cfg(feature = "test-utils")]
+impl MockClock {
+    /// Frozen at `start`
+    pub fn new(start: DateTime<Utc>) -> Self {
+        Self {
+            start_utc: start,
+            start_instant: Instant::now(),
+            elapsed: Mutex::new(Duration::ZERO),
+        }
+    }
+
+    /// Move both wall-clock and monotonic time forward by `by`
+    pub fn advance(&self, by: Duration) {
+        *self.elapsed.lock().unwrap_or_else(|e| e.into_inner()) += by;
+    }
+
+    fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(feature = "test-utils")]
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new(Utc::now())
+    }
+}
+
+#[cfg(feature = "test-utils")]
+impl Clock for MockClock {
+    fn now_utc(&self) -> DateTime<Utc> {
+        // Durations this large are not a realistic test; saturate rather than panic
+        chrono::Duration::from_std(self.elapsed())
+            .ok()
+            .and_then(|elapsed| self.start_utc.checked_add_signed(elapsed))
+            .unwrap_or(DateTime::<Utc>::MAX_UTC)
+    }
+
+    fn now_instant(&self) -> Instant {
+        self.start_instant + self.elapsed()
+    }
+}
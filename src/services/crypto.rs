@@ -1,20 +1,23 @@
-use crate::models::responses::{ApiResponse, SignedObject, SignedRequest};
+use crate::models::responses::{CertInfo, EnvelopeFormat, SignedObject, SignedRequest};
+use crate::services::clock::{skew_monitor, Clock, SystemClock};
+use crate::services::secrets;
+#[cfg(feature = "test-utils")]
+use crate::services::signer::NoopSigner;
+use crate::services::signer::{PayloadSigner, PfxSigner, Pkcs11Signer};
+use arc_swap::ArcSwap;
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
-use lazy_static::lazy_static;
-use log::{error, info};
+use log::{debug, error, info, warn};
+use openssl::asn1::{Asn1Time, Asn1TimeRef};
 use openssl::hash::MessageDigest;
 use openssl::pkcs12::Pkcs12;
 use openssl::pkey::PKey;
-use openssl::rsa::Rsa;
-use openssl::sign::Signer;
-use openssl::x509::X509;
-use serde_json::{json, Value};
-use std::fs;
-use std::io::Write;
-use std::sync::{Arc, Mutex, Once};
-use tempfile::NamedTempFile;
+use openssl::x509::{X509NameRef, X509};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
 use thiserror::Error;
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Error, Debug)]
 pub enum CryptoError {
     #[error("Failed to initialize crypto service: {0}")]
@@ -31,78 +34,215 @@ pub enum CryptoError {
 
     #[error("JSON serialization error: {0}")]
     SerializationError(#[from] serde_json::Error),
-}
 
-lazy_static! {
-    static ref CRYPTO_SERVICE: Arc<Mutex<Option<CryptoService>>> = Arc::new(Mutex::new(None));
-    static ref INIT: Once = Once::new();
+    #[error("No signing certificate configured for commerce {0}")]
+    UnknownCommerceError(i32),
+
+    #[error("Secret decryption failed: {0}")]
+    SecretError(String),
+
+    #[error("Canonical payload is {size} bytes, over the {limit} byte signing limit")]
+    PayloadTooLarge { size: usize, limit: usize },
 }
 
+// Set once at startup and read lock-free afterwards; signing only needs `&self`.
+// The ArcSwap lets `rotate_certificate` replace the service while requests
+// that already loaded the old one finish with it.
+static CRYPTO_SERVICE: OnceLock<Arc<ArcSwap<CryptoService>>> = OnceLock::new();
+
 pub struct CryptoService {
-    private_key: PKey<openssl::pkey::Private>,
-    fingerprint: String,
+    signer: Arc<dyn PayloadSigner>,
+    cert_info: CertInfo,
+    /// Per-commerce keys, selected by the request's `OptionalCommerceId`
+    commerce_signers: HashMap<i32, Arc<dyn PayloadSigner>>,
+    /// Time source for signature expirations
+    clock: Arc<dyn Clock>,
+    /// Envelope layout the target gateway version expects
+    envelope_format: EnvelopeFormat,
 }
 
+/// Load the signing key configured in the environment. An invalid
+/// `MAX_SIGNING_PAYLOAD_BYTES` or `SIGNATURE_TTL_SECS` fails here rather than
+/// falling back.
 pub fn init() -> Result<(), CryptoError> {
-    let mut initialized = false;
+    let _ = SIGNATURE_TTL_SECS.set(parse_signature_ttl_secs(
+        std::env::var("SIGNATURE_TTL_SECS").ok().as_deref(),
+    )?);
+    let _ = MAX_SIGNING_PAYLOAD_BYTES.set(parse_max_signing_payload_bytes(
+        std::env::var("MAX_SIGNING_PAYLOAD_BYTES").ok().as_deref(),
+    )?);
+
+    if CRYPTO_SERVICE.get().is_some() {
+        return Ok(());
+    }
 
-    INIT.call_once(|| {
+    let service = if std::env::var("SIGNER").as_deref() == Ok("noop") {
+        noop_service()
+    } else if std::env::var("PKCS11_MODULE").is_ok() {
+        // Keep the key inside the HSM and sign through the PKCS#11 engine
+        Pkcs11Signer::from_env().and_then(|signer| {
+            let cert_info = cert_info_from_x509(signer.certificate(), signer.fingerprint())?;
+            Ok(CryptoService::from_signer(Arc::new(signer), cert_info))
+        })
+    } else {
         // In a real app, load these from env vars or secure storage
         let pfx_base64 =
             std::env::var("PFX_BASE64").expect("PFX_BASE64 environment variable is required");
-        let pfx_password =
-            std::env::var("PFX_PASSWORD").expect("PFX_PASSWORD environment variable is required");
+        // PFX_PASSWORD_ENC keeps the password out of plain sight in the environment
+        secrets::read_secret("PFX_PASSWORD").and_then(|pfx_password| {
+            let pfx_password = pfx_password
+                .expect("PFX_PASSWORD or PFX_PASSWORD_ENC environment variable is required");
+            CryptoService::new(&pfx_base64, &pfx_password)
+        })
+    };
 
-        match CryptoService::new(&pfx_base64, &pfx_password) {
-            Ok(service) => {
-                let mut guard = CRYPTO_SERVICE.lock().unwrap();
-                *guard = Some(service);
-                initialized = true;
-            }
-            Err(e) => {
-                error!("Failed to initialize crypto service: {}", e);
-            }
-        }
+    let service = service.and_then(|service| {
+        let commerce_signers = load_commerce_signers()?;
+        Ok(service
+            .with_commerce_signers(commerce_signers)
+            .with_envelope_format(envelope_format_from_env()?))
     });
 
-    if initialized {
-        Ok(())
-    } else {
-        Err(CryptoError::InitializationError(
-            "Failed to initialize crypto service".to_string(),
-        ))
+    match service {
+        Ok(service) => {
+            // A concurrent init may have won the race; either service is equivalent
+            let _ = CRYPTO_SERVICE.set(Arc::new(ArcSwap::from_pointee(service)));
+            Ok(())
+        }
+        Err(e) => {
+            error!("Failed to initialize crypto service: {}", e);
+            Err(CryptoError::InitializationError(
+                "Failed to initialize crypto service".to_string(),
+            ))
+        }
     }
 }
 
+// Stub signing for contract tests, see `NoopSigner`
+#[cfg(feature = "test-utils")]
+fn noop_service() -> Result<CryptoService, CryptoError> {
+    warn!("SIGNER=noop: requests are NOT signed with a real key");
+    let cert_info = CertInfo {
+        fingerprint: display_fingerprint(NoopSigner::FINGERPRINT),
+        subject: "CN=noop-signer".to_string(),
+        issuer: "CN=noop-signer".to_string(),
+        not_before: chrono::DateTime::UNIX_EPOCH,
+        // 2100-01-01, far enough that expiry checks never trip
+        not_after: chrono::DateTime::from_timestamp(4_102_444_800, 0).unwrap_or_default(),
+    };
+    Ok(CryptoService::from_signer(Arc::new(NoopSigner), cert_info))
+}
+
+#[cfg(not(feature = "test-utils"))]
+fn noop_service() -> Result<CryptoService, CryptoError> {
+    Err(CryptoError::InitializationError(
+        "SIGNER=noop requires a build with the test-utils feature".to_string(),
+    ))
+}
+
 impl CryptoService {
     fn new(pfx_base64: &str, pfx_password: &str) -> Result<Self, CryptoError> {
+        let (signer, cert_info) = Self::load_pfx(pfx_base64, pfx_password)?;
+
+        info!(
+            "Crypto service initialized. Fingerprint: {}",
+            display_fingerprint(signer.fingerprint())
+        );
+
+        Ok(Self::from_signer(Arc::new(signer), cert_info))
+    }
+
+    fn load_pfx(
+        pfx_base64: &str,
+        pfx_password: &str,
+    ) -> Result<(PfxSigner, CertInfo), CryptoError> {
         let pfx_data = BASE64.decode(pfx_base64).map_err(|e| {
             CryptoError::InitializationError(format!("Failed to decode PFX base64: {}", e))
         })?;
 
-        // Create a temporary file to store PFX data
-        let mut temp_file = NamedTempFile::new()?;
-        temp_file.write_all(&pfx_data)?;
-        let temp_path = temp_file.path().to_string_lossy().to_string();
+        // Parsed from memory; the key material never touches the disk
+        let (private_key, cert) = Self::extract_from_pfx(&pfx_data, pfx_password)?;
 
-        // Extract private key and fingerprint
-        let (private_key, fingerprint) = Self::extract_from_pfx(&temp_path, pfx_password)?;
+        // Calculate SHA1 fingerprint
+        let fingerprint = format_fingerprint(&cert.digest(MessageDigest::sha1())?);
+        let cert_info = cert_info_from_x509(&cert, &fingerprint)?;
 
-        info!("Crypto service initialized. Fingerprint: {}", fingerprint);
+        Ok((PfxSigner::new(private_key, fingerprint), cert_info))
+    }
 
-        Ok(CryptoService {
-            private_key,
-            fingerprint,
-        })
+    pub fn from_signer(signer: Arc<dyn PayloadSigner>, cert_info: CertInfo) -> Self {
+        CryptoService {
+            signer,
+            cert_info,
+            commerce_signers: HashMap::new(),
+            clock: Arc::new(SystemClock),
+            envelope_format: EnvelopeFormat::default(),
+        }
+    }
+
+    /// Sign and lay out envelopes for another Plexo gateway version
+    pub fn with_envelope_format(mut self, envelope_format: EnvelopeFormat) -> Self {
+        self.envelope_format = envelope_format;
+        self
+    }
+
+    /// Compute expirations from `clock` instead of the system time
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn with_commerce_signers(
+        mut self,
+        commerce_signers: HashMap<i32, Arc<dyn PayloadSigner>>,
+    ) -> Self {
+        self.commerce_signers = commerce_signers;
+        self
+    }
+
+    /// Pick the key for a payload: its commerce's own certificate when one is
+    /// configured, the default one when the payload names no commerce or when
+    /// no per-commerce certificates exist at all.
+    fn signer_for(&self, payload: &Value) -> Result<&Arc<dyn PayloadSigner>, CryptoError> {
+        let commerce_id = payload
+            .pointer("/Request/OptionalCommerceId")
+            .and_then(Value::as_i64)
+            .map(|id| id as i32);
+
+        match commerce_id {
+            Some(id) if !self.commerce_signers.is_empty() => self
+                .commerce_signers
+                .get(&id)
+                .ok_or(CryptoError::UnknownCommerceError(id)),
+            _ => Ok(&self.signer),
+        }
+    }
+
+    /// Base64 signature over the canonical form of one of our own responses,
+    /// made with the default certificate so callers can verify it against
+    /// `/api/cert-info`
+    pub fn sign_response(&self, body: &Value) -> Result<String, CryptoError> {
+        let canonical = canonize_json(body)?;
+        let signature = self.signer.sign(canonical.as_bytes())?;
+        Ok(BASE64.encode(signature))
+    }
+
+    /// Compare a trusted `reference` time with the clock expirations are
+    /// computed from, returning the skew in seconds
+    pub fn observe_reference_time(&self, reference: chrono::DateTime<chrono::Utc>) -> i64 {
+        skew_monitor().observe(self.clock.now_utc(), reference)
+    }
+
+    /// Details of the signing certificate, captured at initialization
+    pub fn cert_info(&self) -> &CertInfo {
+        &self.cert_info
     }
 
     fn extract_from_pfx(
-        pfx_path: &str,
+        pfx_data: &[u8],
         password: &str,
-    ) -> Result<(PKey<openssl::pkey::Private>, String), CryptoError> {
-        // Load PFX file
-        let pfx_data = fs::read(pfx_path)?;
-        let pkcs12 = Pkcs12::from_der(&pfx_data).map_err(|e| {
+    ) -> Result<(PKey<openssl::pkey::Private>, X509), CryptoError> {
+        let pkcs12 = Pkcs12::from_der(pfx_data).map_err(|e| {
             CryptoError::InitializationError(format!("Failed to parse PKCS12 data: {}", e))
         })?;
 
@@ -121,187 +261,549 @@ impl CryptoService {
             CryptoError::InitializationError("No private key found in PFX".to_string())
         })?;
 
-        // Calculate SHA1 fingerprint
-        let fingerprint_data = cert.digest(MessageDigest::sha1())?;
-        let fingerprint = fingerprint_data
-            .iter()
-            .map(|b| format!("{:02X}", b))
-            .collect::<Vec<String>>()
-            .join("");
-
-        Ok((private_key, fingerprint))
+        Ok((private_key, cert))
     }
 
-    // Sort keys alphabetically and handle nulls according to Plexo requirements
+    // Kept as a method for existing callers; the logic is the free function below
     fn canonize_json(&self, value: &Value) -> Result<String, CryptoError> {
-        match value {
-            Value::Object(map) => {
-                let mut result = String::from("{");
-
-                // Sort keys alphabetically
-                let mut keys: Vec<&String> = map.keys().collect();
-                keys.sort();
-
-                let mut is_first = true;
-
-                for key in keys {
-                    let val = &map[key];
+        canonize_json(value)
+    }
 
-                    // Skip null values completely as per Plexo requirements
-                    if val.is_null() {
-                        continue;
-                    }
+    fn sign_payload(
+        &self,
+        signer: &dyn PayloadSigner,
+        signed_object: &SignedObject,
+    ) -> Result<String, CryptoError> {
+        // Canonize the object in the layout it is sent in
+        let canonized_json = self.canonize_json(&signed_object.to_value(self.envelope_format))?;
+
+        // The canonical string carries the full payload, so only its size is logged
+        debug!("Canonized JSON ({} bytes)", canonized_json.len());
+
+        // Body limits apply before cleaning and envelope expansion; this bounds
+        // what actually gets signed
+        let limit = max_signing_payload_bytes();
+        if canonized_json.len() > limit {
+            return Err(CryptoError::PayloadTooLarge {
+                size: canonized_json.len(),
+                limit,
+            });
+        }
 
-                    if !is_first {
-                        result.push(',');
-                    }
-                    is_first = false;
-
-                    // Add key
-                    result.push('"');
-                    result.push_str(key);
-                    result.push('"');
-                    result.push(':');
-
-                    // Add value (recursively canonized)
-                    let canonized_value = match val {
-                        Value::Object(_) => self.canonize_json(val)?,
-                        Value::Array(arr) => {
-                            let mut array_result = String::from("[");
-                            let mut is_first_item = true;
-
-                            for item in arr {
-                                // Skip null array items
-                                if item.is_null() {
-                                    continue;
-                                }
+        // Convert to UTF-8 bytes
+        let data_to_sign = canonized_json.as_bytes();
 
-                                if !is_first_item {
-                                    array_result.push(',');
-                                }
-                                is_first_item = false;
-
-                                // Recursively canonize array items
-                                match item {
-                                    Value::Object(_) => {
-                                        array_result.push_str(&self.canonize_json(item)?)
-                                    }
-                                    Value::Array(_) => {
-                                        array_result.push_str(&self.canonize_json(item)?)
-                                    }
-                                    Value::String(s) => {
-                                        array_result.push_str(&format!("\"{}\"", s))
-                                    }
-                                    _ => array_result.push_str(&item.to_string()),
-                                }
-                            }
+        // Sign the data through the configured key source
+        let signature = signer.sign(data_to_sign)?;
 
-                            array_result.push(']');
-                            array_result
-                        }
-                        Value::String(s) => format!("\"{}\"", s),
-                        // For numbers, booleans, etc. - use direct string representation without quotes
-                        _ => val.to_string(),
-                    };
+        // Encode the signature to base64
+        Ok(BASE64.encode(&signature))
+    }
 
-                    result.push_str(&canonized_value);
-                }
+    pub fn create_signed_payload(&self, payload: &Value) -> Result<SignedRequest, CryptoError> {
+        let expiration = expiration_after(self.clock.now_utc().timestamp(), signature_ttl_secs())?;
+        self.sign_with_expiration(payload, expiration)
+    }
 
-                result.push('}');
-                Ok(result)
-            }
-            Value::Array(arr) => {
-                let mut result = String::from("[");
-                let mut is_first = true;
+    /// Sign `payload` with a fixed `UTCUnixTimeExpiration` instead of now plus
+    /// the TTL, for requests prepared ahead of time. The expiration must be in
+    /// the future and no further out than `SIGNATURE_MAX_HORIZON_SECS`.
+    pub fn create_signed_payload_expiring_at(
+        &self,
+        payload: &Value,
+        expiration: i64,
+    ) -> Result<SignedRequest, CryptoError> {
+        let expiration = validate_expiration(
+            self.clock.now_utc().timestamp(),
+            expiration,
+            signature_max_horizon_secs(),
+        )?;
+        self.sign_with_expiration(payload, expiration)
+    }
 
-                for item in arr {
-                    if !is_first {
-                        result.push(',');
-                    }
-                    is_first = false;
+    fn sign_with_expiration(
+        &self,
+        payload: &Value,
+        expiration: i64,
+    ) -> Result<SignedRequest, CryptoError> {
+        skew_monitor().check().map_err(CryptoError::SigningError)?;
+        let signer = self.signer_for(payload)?;
+
+        let signed_object = SignedObject {
+            Fingerprint: signer.fingerprint().to_string(),
+            Object: payload.clone(),
+            UTCUnixTimeExpiration: expiration,
+        };
+        let signature = self.sign_payload(signer.as_ref(), &signed_object)?;
 
-                    match item {
-                        Value::Object(_) => result.push_str(&self.canonize_json(item)?),
-                        Value::Array(_) => result.push_str(&self.canonize_json(item)?),
-                        Value::String(s) => result.push_str(&format!("\"{}\"", s)),
-                        _ => result.push_str(&item.to_string()),
-                    }
-                }
+        Ok(SignedRequest {
+            Object: signed_object,
+            Signature: signature,
+            format: self.envelope_format,
+        })
+    }
+}
 
-                result.push(']');
-                Ok(result)
-            }
-            _ => Ok(value.to_string()),
+/// `ENVELOPE_FORMAT=v1|v2`, V1 when unset
+fn envelope_format_from_env() -> Result<EnvelopeFormat, CryptoError> {
+    match std::env::var("ENVELOPE_FORMAT") {
+        Ok(value) if !value.trim().is_empty() => {
+            let format = EnvelopeFormat::parse(&value).ok_or_else(|| {
+                CryptoError::InitializationError("ENVELOPE_FORMAT must be v1 or v2".to_string())
+            })?;
+            info!("Signing envelopes in the {:?} layout", format);
+            Ok(format)
         }
+        _ => Ok(EnvelopeFormat::default()),
+    }
+}
+
+/// Load `PFX_BASE64_<commerceId>` / `PFX_PASSWORD_<commerceId>` pairs; the
+/// password may also be given encrypted as `PFX_PASSWORD_<commerceId>_ENC`
+fn load_commerce_signers() -> Result<HashMap<i32, Arc<dyn PayloadSigner>>, CryptoError> {
+    let mut signers: HashMap<i32, Arc<dyn PayloadSigner>> = HashMap::new();
+
+    for (name, pfx_base64) in std::env::vars() {
+        let Some(suffix) = name.strip_prefix("PFX_BASE64_") else {
+            continue;
+        };
+        let commerce_id = suffix.parse::<i32>().map_err(|_| {
+            CryptoError::InitializationError(format!("Invalid commerce id in {}", name))
+        })?;
+        let pfx_password =
+            secrets::read_secret(&format!("PFX_PASSWORD_{}", suffix))?.ok_or_else(|| {
+                CryptoError::InitializationError(format!(
+                    "PFX_PASSWORD_{} or PFX_PASSWORD_{}_ENC is required",
+                    suffix, suffix
+                ))
+            })?;
+
+        let (signer, _) = CryptoService::load_pfx(&pfx_base64, &pfx_password)?;
+        info!(
+            "Loaded signing certificate for commerce {}. Fingerprint: {}",
+            commerce_id,
+            display_fingerprint(signer.fingerprint())
+        );
+        signers.insert(commerce_id, Arc::new(signer));
     }
 
-    fn sign_payload(&self, payload: &Value) -> Result<(String, i64), CryptoError> {
-        // Generate expiration time (5 minutes in the future)
-        let expiration = chrono::Utc::now().timestamp() + (5 * 60);
+    Ok(signers)
+}
 
-        // Create the object to sign with required fields
-        let object_to_sign = json!({
-            "Fingerprint": self.fingerprint,
-            "Object": payload,
-            "UTCUnixTimeExpiration": expiration
-        });
+// How long a signature stays valid when SIGNATURE_TTL_SECS is not set
+const DEFAULT_SIGNATURE_TTL_SECS: i64 = 5 * 60;
 
-        // Canonize the JSON
-        let canonized_json = self.canonize_json(&object_to_sign)?;
+// SIGNATURE_TTL_SECS, set by `init()`
+static SIGNATURE_TTL_SECS: OnceLock<i64> = OnceLock::new();
 
-        info!("Canonized JSON: {}", canonized_json);
+fn signature_ttl_secs() -> i64 {
+    SIGNATURE_TTL_SECS
+        .get()
+        .copied()
+        .unwrap_or(DEFAULT_SIGNATURE_TTL_SECS)
+}
 
-        // Convert to UTF-8 bytes
-        let data_to_sign = canonized_json.as_bytes();
+fn parse_signature_ttl_secs(raw: Option<&str>) -> Result<i64, CryptoError> {
+    let Some(raw) = raw.map(str::trim).filter(|raw| !raw.is_empty()) else {
+        return Ok(DEFAULT_SIGNATURE_TTL_SECS);
+    };
+    match raw.parse::<i64>() {
+        Ok(ttl) if ttl > 0 => Ok(ttl),
+        _ => Err(CryptoError::InitializationError(format!(
+            "SIGNATURE_TTL_SECS must be a positive number of seconds, got {}",
+            raw
+        ))),
+    }
+}
+
+/// `UTCUnixTimeExpiration` for a signature made at `now`. Plexo rejects
+/// expired signatures with an unhelpful error, so an expiration that is not
+/// strictly in the future (or overflows) fails signing instead.
+pub fn expiration_after(now: i64, ttl_secs: i64) -> Result<i64, CryptoError> {
+    match now.checked_add(ttl_secs) {
+        Some(expiration) if expiration > now => Ok(expiration),
+        Some(_) => Err(CryptoError::SigningError(format!(
+            "Signature TTL must be positive, got {} s",
+            ttl_secs
+        ))),
+        None => Err(CryptoError::SigningError(format!(
+            "Signature TTL of {} s overflows the expiration timestamp",
+            ttl_secs
+        ))),
+    }
+}
 
-        // Create a signer using RSA-SHA512
-        let mut signer = Signer::new(MessageDigest::sha512(), &self.private_key)
-            .map_err(|e| CryptoError::SigningError(format!("Failed to create signer: {}", e)))?;
+// Largest canonical string signed when MAX_SIGNING_PAYLOAD_BYTES is not set
+const DEFAULT_MAX_SIGNING_PAYLOAD_BYTES: usize = 256 * 1024;
 
-        // Sign the data
-        let signature = signer
-            .sign_oneshot_to_vec(data_to_sign)
-            .map_err(|e| CryptoError::SigningError(format!("Failed to sign payload: {}", e)))?;
+// MAX_SIGNING_PAYLOAD_BYTES, set by `init()`
+static MAX_SIGNING_PAYLOAD_BYTES: OnceLock<usize> = OnceLock::new();
 
-        // Encode the signature to base64
-        let base64_signature = BASE64.encode(&signature);
+fn max_signing_payload_bytes() -> usize {
+    MAX_SIGNING_PAYLOAD_BYTES
+        .get()
+        .copied()
+        .unwrap_or(DEFAULT_MAX_SIGNING_PAYLOAD_BYTES)
+}
 
-        Ok((base64_signature, expiration))
+fn parse_max_signing_payload_bytes(raw: Option<&str>) -> Result<usize, CryptoError> {
+    let Some(raw) = raw.map(str::trim).filter(|raw| !raw.is_empty()) else {
+        return Ok(DEFAULT_MAX_SIGNING_PAYLOAD_BYTES);
+    };
+    match raw.parse::<usize>() {
+        Ok(limit) if limit > 0 => Ok(limit),
+        _ => Err(CryptoError::InitializationError(format!(
+            "MAX_SIGNING_PAYLOAD_BYTES must be a positive number, got {}",
+            raw
+        ))),
     }
+}
 
-    pub fn create_signed_payload(&self, payload: &Value) -> Result<SignedRequest, CryptoError> {
-        let (signature, expiration) = self.sign_payload(payload)?;
+// How far ahead an explicit expiration may be when SIGNATURE_MAX_HORIZON_SECS is not set
+const DEFAULT_SIGNATURE_MAX_HORIZON_SECS: i64 = 24 * 60 * 60;
+
+// SIGNATURE_MAX_HORIZON_SECS, read once
+fn signature_max_horizon_secs() -> i64 {
+    static HORIZON: OnceLock<i64> = OnceLock::new();
+    *HORIZON.get_or_init(|| {
+        std::env::var("SIGNATURE_MAX_HORIZON_SECS")
+            .ok()
+            .and_then(|raw| raw.trim().parse::<i64>().ok())
+            .filter(|horizon| *horizon > 0)
+            .unwrap_or(DEFAULT_SIGNATURE_MAX_HORIZON_SECS)
+    })
+}
 
-        Ok(SignedRequest {
-            Object: SignedObject {
-                Fingerprint: self.fingerprint.clone(),
-                Object: payload.clone(),
-                UTCUnixTimeExpiration: expiration,
-            },
-            Signature: signature,
-        })
+/// Check an explicit `UTCUnixTimeExpiration` against `now`: it must be
+/// strictly in the future and at most `max_horizon_secs` away
+pub fn validate_expiration(
+    now: i64,
+    expiration: i64,
+    max_horizon_secs: i64,
+) -> Result<i64, CryptoError> {
+    if expiration <= now {
+        return Err(CryptoError::SigningError(format!(
+            "Signature expiration {} is not in the future (now {})",
+            expiration, now
+        )));
+    }
+    if expiration.saturating_sub(now) > max_horizon_secs {
+        return Err(CryptoError::SigningError(format!(
+            "Signature expiration {} is more than {} s ahead",
+            expiration, max_horizon_secs
+        )));
     }
+    Ok(expiration)
 }
 
 // Singleton access to crypto service
 pub fn get_crypto_service() -> Result<Arc<CryptoService>, CryptoError> {
-    let guard = CRYPTO_SERVICE.lock().unwrap();
+    crypto_handle().map(|handle| handle.load_full())
+}
 
-    match &*guard {
-        Some(service) => Ok(Arc::new(service.clone())),
-        None => Err(CryptoError::InitializationError(
-            "Crypto service not initialized".to_string(),
-        )),
+/// The swappable slot behind `get_crypto_service`, for holders that must
+/// observe certificate rotations
+pub fn crypto_handle() -> Result<Arc<ArcSwap<CryptoService>>, CryptoError> {
+    CRYPTO_SERVICE.get().cloned().ok_or_else(|| {
+        CryptoError::InitializationError("Crypto service not initialized".to_string())
+    })
+}
+
+/// Replace the default signing certificate with a new PFX, keeping the
+/// per-commerce ones. The certificate must be currently valid. In-flight
+/// requests finish with the certificate they started with.
+pub fn rotate_certificate(pfx_base64: &str, pfx_password: &str) -> Result<CertInfo, CryptoError> {
+    let handle = crypto_handle()?;
+    let (signer, cert_info) = CryptoService::load_pfx(pfx_base64, pfx_password)?;
+
+    let current = handle.load();
+    let now = current.clock.now_utc();
+    if cert_info.not_before > now || cert_info.not_after <= now {
+        return Err(CryptoError::InitializationError(format!(
+            "Certificate is not valid now (valid from {} to {})",
+            cert_info.not_before, cert_info.not_after
+        )));
     }
+
+    let service = CryptoService::from_signer(Arc::new(signer), cert_info.clone())
+        .with_commerce_signers(current.commerce_signers.clone())
+        .with_clock(current.clock.clone())
+        .with_envelope_format(current.envelope_format);
+    handle.store(Arc::new(service));
+
+    info!(
+        "Signing certificate rotated. Fingerprint: {}",
+        display_fingerprint(&cert_info.fingerprint)
+    );
+    Ok(cert_info)
 }
 
-// Implement Clone for CryptoService
-impl Clone for CryptoService {
-    fn clone(&self) -> Self {
-        // Create a new instance with the same private key and fingerprint
-        CryptoService {
-            private_key: self.private_key.clone(),
-            fingerprint: self.fingerprint.clone(),
+/// How `canonize_json_with` lays out objects
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CanonOptions {
+    /// Sort object keys as Plexo does. Only inspection tools turn this off to
+    /// see keys in the order they arrived; signatures always use sorted keys.
+    pub sort_keys: bool,
+}
+
+impl Default for CanonOptions {
+    fn default() -> Self {
+        Self { sort_keys: true }
+    }
+}
+
+/// Sort keys alphabetically and handle nulls according to Plexo requirements.
+/// Pure function of the input, so it needs no key material.
+///
+/// Works on the parsed map, never on source text. serde_json keeps the last
+/// value of a key repeated in the source, so `{"A":1,"A":2}` canonicalizes
+/// as `{"A":2}` however the duplicates were ordered around other keys.
+pub fn canonize_json(value: &Value) -> Result<String, CryptoError> {
+    canonize_json_with(value, CanonOptions::default())
+}
+
+/// `canonize_json` with explicit options. Never sign the output of anything
+/// but the default options.
+pub fn canonize_json_with(value: &Value, options: CanonOptions) -> Result<String, CryptoError> {
+    match value {
+        Value::Object(map) => {
+            let mut result = String::from("{");
+
+            // Sort keys alphabetically
+            let mut keys: Vec<&String> = map.keys().collect();
+            if options.sort_keys {
+                keys.sort();
+            }
+
+            let mut is_first = true;
+
+            for key in keys {
+                let val = &map[key];
+
+                // Skip null values completely as per Plexo requirements
+                if val.is_null() {
+                    continue;
+                }
+
+                if !is_first {
+                    result.push(',');
+                }
+                is_first = false;
+
+                // Add key
+                result.push('"');
+                result.push_str(key);
+                result.push('"');
+                result.push(':');
+
+                // Add value (recursively canonized)
+                let canonized_value = match val {
+                    Value::Object(_) => canonize_json_with(val, options)?,
+                    Value::Array(arr) => {
+                        let mut array_result = String::from("[");
+                        let mut is_first_item = true;
+
+                        for item in arr {
+                            // Skip null array items
+                            if item.is_null() {
+                                continue;
+                            }
+
+                            if !is_first_item {
+                                array_result.push(',');
+                            }
+                            is_first_item = false;
+
+                            // Recursively canonize array items
+                            match item {
+                                Value::Object(_) => {
+                                    array_result.push_str(&canonize_json_with(item, options)?)
+                                }
+                                Value::Array(_) => {
+                                    array_result.push_str(&canonize_json_with(item, options)?)
+                                }
+                                Value::String(s) => array_result.push_str(&format!("\"{}\"", s)),
+                                _ => array_result.push_str(&canonize_scalar(item)),
+                            }
+                        }
+
+                        array_result.push(']');
+                        array_result
+                    }
+                    Value::String(s) => format!("\"{}\"", s),
+                    // Numbers and booleans use the explicit scalar canonical form
+                    _ => canonize_scalar(val),
+                };
+
+                result.push_str(&canonized_value);
+            }
+
+            result.push('}');
+            Ok(result)
+        }
+        Value::Array(arr) => {
+            let mut result = String::from("[");
+            let mut is_first = true;
+
+            for item in arr {
+                if !is_first {
+                    result.push(',');
+                }
+                is_first = false;
+
+                match item {
+                    Value::Object(_) => result.push_str(&canonize_json_with(item, options)?),
+                    Value::Array(_) => result.push_str(&canonize_json_with(item, options)?),
+                    Value::String(s) => result.push_str(&format!("\"{}\"", s)),
+                    _ => result.push_str(&canonize_scalar(item)),
+                }
+            }
+
+            result.push(']');
+            Ok(result)
         }
+        _ => Ok(canonize_scalar(value)),
     }
 }
+
+/// Canonical form of a non-container JSON value, matching Plexo's serializer:
+/// lowercase booleans, integers as-is, decimals exactly as written (amounts
+/// keep their currency scale, `10.50`), and anything else in plain decimal
+/// notation (never exponent form) with a trailing ".0" for whole numbers.
+fn canonize_scalar(value: &Value) -> String {
+    match value {
+        Value::Bool(true) => "true".to_string(),
+        Value::Bool(false) => "false".to_string(),
+        Value::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                i.to_string()
+            } else if let Some(u) = n.as_u64() {
+                u.to_string()
+            } else if is_plain_decimal(&n.to_string()) {
+                // Numbers keep their written digits (arbitrary_precision), so
+                // this is the same text that goes on the wire
+                n.to_string()
+            } else {
+                match n.as_f64() {
+                    // Display for f64 never uses exponent notation
+                    Some(f) if f.is_finite() && f.fract() == 0.0 => format!("{:.1}", f),
+                    Some(f) if f.is_finite() => format!("{}", f),
+                    _ => n.to_string(),
+                }
+            }
+        }
+        _ => value.to_string(),
+    }
+}
+
+// `-12.50`, but not `1e5` or `1.5E-3`
+fn is_plain_decimal(number: &str) -> bool {
+    let digits = number.strip_prefix('-').unwrap_or(number);
+    match digits.split_once('.') {
+        Some((integer, fraction)) => {
+            !integer.is_empty()
+                && !fraction.is_empty()
+                && integer.chars().all(|c| c.is_ascii_digit())
+                && fraction.chars().all(|c| c.is_ascii_digit())
+        }
+        None => false,
+    }
+}
+
+/// How fingerprints are shown in logs and `/api/cert-info`, from
+/// `FINGERPRINT_FORMAT`. Plexo always receives the uppercase form.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FingerprintFormat {
+    /// `A1B2C3...`, as sent to Plexo
+    Upper,
+    /// `a1b2c3...`
+    Lower,
+    /// `A1:B2:C3...`, as most certificate tools print it
+    Colon,
+}
+
+impl FingerprintFormat {
+    fn from_env() -> Self {
+        match std::env::var("FINGERPRINT_FORMAT")
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "" | "upper" => Self::Upper,
+            "lower" => Self::Lower,
+            "colon" => Self::Colon,
+            other => {
+                warn!("Ignoring unknown FINGERPRINT_FORMAT {}, using upper", other);
+                Self::Upper
+            }
+        }
+    }
+
+    pub fn apply(self, fingerprint: &str) -> String {
+        match self {
+            Self::Upper => fingerprint.to_ascii_uppercase(),
+            Self::Lower => fingerprint.to_ascii_lowercase(),
+            Self::Colon => fingerprint
+                .to_ascii_uppercase()
+                .as_bytes()
+                .chunks(2)
+                .map(|pair| String::from_utf8_lossy(pair).into_owned())
+                .collect::<Vec<String>>()
+                .join(":"),
+        }
+    }
+}
+
+/// Render a fingerprint for humans; never use the result in a signed payload
+pub fn display_fingerprint(fingerprint: &str) -> String {
+    static FORMAT: OnceLock<FingerprintFormat> = OnceLock::new();
+    FORMAT
+        .get_or_init(FingerprintFormat::from_env)
+        .apply(fingerprint)
+}
+
+/// Uppercase, separator-less hex rendering of a certificate digest
+pub(crate) fn format_fingerprint(digest: &[u8]) -> String {
+    digest
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<String>>()
+        .join("")
+}
+
+pub(crate) fn cert_info_from_x509(cert: &X509, fingerprint: &str) -> Result<CertInfo, CryptoError> {
+    Ok(CertInfo {
+        fingerprint: display_fingerprint(fingerprint),
+        subject: format_name(cert.subject_name()),
+        issuer: format_name(cert.issuer_name()),
+        not_before: asn1_time_to_utc(cert.not_before())?,
+        not_after: asn1_time_to_utc(cert.not_after())?,
+    })
+}
+
+// Render a distinguished name as "CN=..., O=..."
+fn format_name(name: &X509NameRef) -> String {
+    name.entries()
+        .map(|entry| {
+            let key = entry.object().nid().short_name().unwrap_or("?");
+            let value = entry
+                .data()
+                .as_utf8()
+                .map(|v| v.to_string())
+                .unwrap_or_default();
+            format!("{}={}", key, value)
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+fn asn1_time_to_utc(time: &Asn1TimeRef) -> Result<chrono::DateTime<chrono::Utc>, CryptoError> {
+    let epoch = Asn1Time::from_unix(0)?;
+    let diff = epoch.diff(time)?;
+    let seconds = i64::from(diff.days) * 86_400 + i64::from(diff.secs);
+
+    chrono::DateTime::from_timestamp(seconds, 0).ok_or_else(|| {
+        CryptoError::InitializationError("Certificate date out of range".to_string())
+    })
+}
@@ -0,0 +1,181 @@
+use async_trait::async_trait;
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use lazy_static::lazy_static;
+use openssl::sha::sha256;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+const DEFAULT_TTL_SECS: u64 = 24 * 60 * 60;
+// Longer than any Plexo call with retries, so a live request keeps its key
+const DEFAULT_IN_FLIGHT_TTL_SECS: u64 = 5 * 60;
+// Expired keys are swept once every this many reservations, not on each one
+const SWEEP_EVERY_WRITES: usize = 1024;
+
+lazy_static! {
+    // How long a finished response is replayed for, from IDEMPOTENCY_TTL_SECS
+    static ref IDEMPOTENCY_TTL: Duration = Duration::from_secs(
+        std::env::var("IDEMPOTENCY_TTL_SECS")
+            .ok()
+            .and_then(|value| value.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_TTL_SECS),
+    );
+
+    // How long a key stays reserved by a request that never finished (a crashed
+    // worker), from IDEMPOTENCY_IN_FLIGHT_TTL_SECS
+    static ref IN_FLIGHT_TTL: Duration = Duration::from_secs(
+        std::env::var("IDEMPOTENCY_IN_FLIGHT_TTL_SECS")
+            .ok()
+            .and_then(|value| value.parse::<u64>().ok())
+            .filter(|secs| *secs > 0)
+            .unwrap_or(DEFAULT_IN_FLIGHT_TTL_SECS),
+    );
+}
+
+static IDEMPOTENCY_STORE: OnceLock<Arc<dyn IdempotencyStore>> = OnceLock::new();
+
+/// A finished API response, replayed verbatim for a repeated idempotency key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredResponse {
+    pub status: u16,
+    pub body: Vec<u8>,
+    /// `fingerprint()` of the request body that produced it
+    pub fingerprint: String,
+}
+
+/// What a key held when a request tried to claim it
+#[derive(Debug, Clone)]
+pub enum Reservation {
+    /// The key was free and now belongs to this request
+    Reserved,
+    /// Another request with the same key and body is still running
+    InFlight,
+    /// A request with the same key and body already finished
+    Completed(StoredResponse),
+    /// The key was used with a different request body
+    Mismatch,
+}
+
+/// Where idempotency keys are reserved and their responses remembered. The
+/// default keeps them in memory; implement this over Redis or a database to
+/// survive restarts. `reserve` must be atomic across every replica sharing
+/// the store, or two requests with one key can both reach Plexo.
+#[async_trait]
+pub trait IdempotencyStore: Send + Sync {
+    /// Claim `key` for a request whose body has `fingerprint`, holding it for
+    /// at most `ttl`, or report what the key already holds
+    async fn reserve(&self, key: &str, fingerprint: &str, ttl: Duration) -> Reservation;
+    /// Replace the reservation with the finished response
+    async fn put(&self, key: &str, response: StoredResponse, ttl: Duration);
+    /// Free a reservation whose request may be sent again
+    async fn release(&self, key: &str);
+}
+
+// What the in-memory store holds per key
+enum KeyState {
+    InFlight { fingerprint: String },
+    Completed(StoredResponse),
+}
+
+/// Process-local store, lost on restart
+#[derive(Default)]
+pub struct InMemoryIdempotencyStore {
+    entries: DashMap<String, (KeyState, Instant)>,
+    writes: AtomicUsize,
+}
+
+impl InMemoryIdempotencyStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl IdempotencyStore for InMemoryIdempotencyStore {
+    async fn reserve(&self, key: &str, fingerprint: &str, ttl: Duration) -> Reservation {
+        let now = Instant::now();
+        // Sweep expired keys now and then so the map can't grow without bound
+        if self
+            .writes
+            .fetch_add(1, Ordering::Relaxed)
+            .is_multiple_of(SWEEP_EVERY_WRITES)
+        {
+            self.entries.retain(|_, (_, expires_at)| *expires_at > now);
+        }
+
+        let reserved = (
+            KeyState::InFlight {
+                fingerprint: fingerprint.to_string(),
+            },
+            now + ttl,
+        );
+        // The entry holds the shard lock, so check and claim are one step
+        match self.entries.entry(key.to_string()) {
+            Entry::Vacant(vacant) => {
+                vacant.insert(reserved);
+                Reservation::Reserved
+            }
+            // Not swept yet, but as good as gone
+            Entry::Occupied(mut occupied) if occupied.get().1 <= now => {
+                occupied.insert(reserved);
+                Reservation::Reserved
+            }
+            Entry::Occupied(occupied) => match &occupied.get().0 {
+                KeyState::InFlight { fingerprint: held } if held == fingerprint => {
+                    Reservation::InFlight
+                }
+                KeyState::Completed(stored) if stored.fingerprint == fingerprint => {
+                    Reservation::Completed(stored.clone())
+                }
+                _ => Reservation::Mismatch,
+            },
+        }
+    }
+
+    async fn put(&self, key: &str, response: StoredResponse, ttl: Duration) {
+        self.entries.insert(
+            key.to_string(),
+            (KeyState::Completed(response), Instant::now() + ttl),
+        );
+    }
+
+    async fn release(&self, key: &str) {
+        self.entries.remove_if(key, |_, (entry, _)| {
+            matches!(entry, KeyState::InFlight { .. })
+        });
+    }
+}
+
+/// Install a custom store. Must be called before the first request; returns
+/// false when a store is already in place.
+pub fn set_store(store: Arc<dyn IdempotencyStore>) -> bool {
+    IDEMPOTENCY_STORE.set(store).is_ok()
+}
+
+/// The configured store, falling back to the in-memory one
+pub fn store() -> Arc<dyn IdempotencyStore> {
+    IDEMPOTENCY_STORE
+        .get_or_init(|| Arc::new(InMemoryIdempotencyStore::new()))
+        .clone()
+}
+
+pub fn ttl() -> Duration {
+    *IDEMPOTENCY_TTL
+}
+
+/// How long a reservation outlives a request that never finishes
+pub fn in_flight_ttl() -> Duration {
+    *IN_FLIGHT_TTL
+}
+
+/// Keys are per calling service, so two services can't replay each other's responses
+pub fn scoped_key(service: &str, key: &str) -> String {
+    format!("{}:{}", service, key)
+}
+
+/// Hex SHA-256 of a request body, to tell a retry from a reused key
+pub fn fingerprint(body: &[u8]) -> String {
+    sha256(body).iter().map(|b| format!("{:02x}", b)).collect()
+}
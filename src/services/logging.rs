@@ -0,0 +1,73 @@
+use env_logger::{Builder, Env};
+use log::kv::{Key, Value as KvValue, VisitSource};
+use serde_json::{Map, Value};
+use std::io::Write;
+
+/// Set up the global logger. `RUST_LOG` picks the level (`info` by default)
+/// and `LOG_FORMAT` the output: `text` (default) for humans, or `json` for one
+/// object per line with timestamp, level, target, message and every
+/// structured field of the record (e.g. `correlation_id`).
+pub fn init() {
+    let mut builder = Builder::from_env(Env::default().default_filter_or("info"));
+
+    let format = std::env::var("LOG_FORMAT").unwrap_or_default();
+    match format.trim().to_ascii_lowercase().as_str() {
+        "json" => {
+            builder.format(|buf, record| {
+                let mut line = Map::new();
+                line.insert(
+                    "timestamp".to_string(),
+                    Value::String(
+                        chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+                    ),
+                );
+                line.insert(
+                    "level".to_string(),
+                    Value::String(record.level().to_string()),
+                );
+                line.insert(
+                    "target".to_string(),
+                    Value::String(record.target().to_string()),
+                );
+                line.insert(
+                    "message".to_string(),
+                    Value::String(record.args().to_string()),
+                );
+
+                let mut fields = JsonFields(&mut line);
+                // A field that fails to render only loses itself, never the line
+                let _ = record.key_values().visit(&mut fields);
+
+                writeln!(buf, "{}", Value::Object(line))
+            });
+        }
+        "" | "text" => {}
+        other => {
+            builder.init();
+            log::warn!("Unknown LOG_FORMAT {:?}, using text", other);
+            return;
+        }
+    }
+
+    builder.init();
+}
+
+// Copies a record's structured fields into the JSON line, keeping numbers and
+// booleans typed. The fixed keys win over a field of the same name.
+struct JsonFields<'a>(&'a mut Map<String, Value>);
+
+impl<'kvs> VisitSource<'kvs> for JsonFields<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: KvValue<'kvs>) -> Result<(), log::kv::Error> {
+        let value = if let Some(boolean) = value.to_bool() {
+            Value::Bool(boolean)
+        } else if let Some(number) = value.to_i64() {
+            Value::from(number)
+        } else if let Some(number) = value.to_u64() {
+            Value::from(number)
+        } else {
+            Value::String(value.to_string())
+        };
+        self.0.entry(key.as_str().to_string()).or_insert(value);
+        Ok(())
+    }
+}
@@ -0,0 +1,80 @@
+use lazy_static::lazy_static;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, Opts, Registry, TextEncoder,
+};
+
+lazy_static! {
+    static ref REGISTRY: Registry = Registry::new();
+    static ref PLEXO_REQUESTS_TOTAL: IntCounterVec = IntCounterVec::new(
+        Opts::new(
+            "plexo_requests_total",
+            "Plexo operations by operation and outcome"
+        ),
+        &["operation", "outcome"]
+    )
+    .expect("valid plexo_requests_total metric");
+    static ref PLEXO_REQUEST_DURATION_SECONDS: HistogramVec = HistogramVec::new(
+        HistogramOpts::new(
+            "plexo_request_duration_seconds",
+            "Round-trip time of HTTP calls to Plexo"
+        ),
+        &["operation"]
+    )
+    .expect("valid plexo_request_duration_seconds metric");
+    static ref RATE_LIMITED_TOTAL: IntCounter = IntCounter::new(
+        "service_rate_limited_total",
+        "Requests rejected by the service rate limiter"
+    )
+    .expect("valid service_rate_limited_total metric");
+    static ref AUDIT_WRITE_FAILURES_TOTAL: IntCounter = IntCounter::new(
+        "audit_write_failures_total",
+        "Audit records that could not be written"
+    )
+    .expect("valid audit_write_failures_total metric");
+    static ref WEBHOOK_DEAD_LETTERS_TOTAL: IntCounter = IntCounter::new(
+        "webhook_dead_letters_total",
+        "Result notifications dropped after exhausting retries"
+    )
+    .expect("valid webhook_dead_letters_total metric");
+}
+
+/// Register all collectors with the registry served at `/metrics`
+pub fn init() -> Result<(), prometheus::Error> {
+    REGISTRY.register(Box::new(PLEXO_REQUESTS_TOTAL.clone()))?;
+    REGISTRY.register(Box::new(PLEXO_REQUEST_DURATION_SECONDS.clone()))?;
+    REGISTRY.register(Box::new(RATE_LIMITED_TOTAL.clone()))?;
+    REGISTRY.register(Box::new(AUDIT_WRITE_FAILURES_TOTAL.clone()))?;
+    REGISTRY.register(Box::new(WEBHOOK_DEAD_LETTERS_TOTAL.clone()))?;
+    Ok(())
+}
+
+pub fn record_outcome(operation: &str, outcome: &str) {
+    PLEXO_REQUESTS_TOTAL
+        .with_label_values(&[operation, outcome])
+        .inc();
+}
+
+pub fn observe_plexo_duration(operation: &str, seconds: f64) {
+    PLEXO_REQUEST_DURATION_SECONDS
+        .with_label_values(&[operation])
+        .observe(seconds);
+}
+
+pub fn record_rate_limited() {
+    RATE_LIMITED_TOTAL.inc();
+}
+
+pub fn record_audit_failure() {
+    AUDIT_WRITE_FAILURES_TOTAL.inc();
+}
+
+pub fn record_webhook_dead_letter() {
+    WEBHOOK_DEAD_LETTERS_TOTAL.inc();
+}
+
+/// Render the registry in the Prometheus text exposition format
+pub fn render() -> Result<String, prometheus::Error> {
+    let mut buffer = Vec::new();
+    TextEncoder::new().encode(&REGISTRY.gather(), &mut buffer)?;
+    Ok(String::from_utf8_lossy(&buffer).into_owned())
+}
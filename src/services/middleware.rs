@@ -1,12 +1,19 @@
 use actix_web::{
     body::{BoxBody, MessageBody},
-    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    dev::{Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    error::InternalError,
     http::{header::HeaderName, StatusCode},
-    Error, HttpResponse,
+    web::Bytes,
+    Error, FromRequest, HttpMessage, HttpRequest, HttpResponse,
 };
 use dashmap::DashMap;
 use futures_util::Future;
+use openssl::hash::{hash, MessageDigest};
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+use serde::Serialize;
 use std::{
+    collections::VecDeque,
     future::{ready, Ready},
     pin::Pin,
     sync::Arc,
@@ -14,42 +21,219 @@ use std::{
     time::{Duration, Instant},
 };
 use subtle::ConstantTimeEq;
+use utoipa::ToSchema;
+
+use crate::models::responses::ApiResponse;
+use crate::services::clock::{Clock, SystemClock};
+use crate::services::metrics;
+
+const HMAC_SIGNATURE_HEADER: &str = "x-signature";
+const HMAC_TIMESTAMP_HEADER: &str = "x-timestamp";
+// Maximum distance between the caller's timestamp and our clock
+const HMAC_MAX_SKEW: Duration = Duration::from_secs(5 * 60);
+
+/// How callers prove their identity
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuthMode {
+    /// The shared key is sent verbatim in the service key header
+    StaticKey,
+    /// Requests carry an HMAC-SHA256 signature over method, path with query
+    /// string, timestamp and body hash, keyed with the shared secret
+    Hmac,
+}
 
 /// Configuration for service-to-service API key middleware
 #[derive(Clone)]
 pub struct ServiceAuthConfig {
-    /// Single trusted service key (for simplicity in service-to-service communication)
-    service_key: Arc<Vec<u8>>,
+    /// Trusted service keys, the first one being the unrestricted primary key
+    service_keys: Arc<Vec<ServiceKey>>,
     /// Custom header name (defaults to "x-service-key")
     header_name: HeaderName,
     /// Strict rate limiting to prevent abuse
     rate_limit: ServiceRateLimit,
-    /// Service identifier for metrics
+    /// Identity of the primary key; restricted keys are named after it
     service_name: String,
+    /// Static key (default) or HMAC-signed requests
+    auth_mode: AuthMode,
+    /// Paths served without authentication (e.g. metrics scraping)
+    exempt_paths: Arc<Vec<String>>,
+    /// Time source for rate-limit windows and HMAC timestamp skew
+    clock: Arc<dyn Clock>,
 }
 
+/// A key callers may present, optionally limited to some API paths
+#[derive(Clone)]
+struct ServiceKey {
+    /// Identity handed to handlers when this key is presented
+    name: String,
+    secret: Vec<u8>,
+    /// `None` grants access to every path
+    allowed_paths: Option<Vec<String>>,
+}
+
+impl ServiceKey {
+    fn allows(&self, path: &str) -> bool {
+        self.allowed_paths
+            .as_ref()
+            .is_none_or(|allowed| allowed.iter().any(|prefix| path_matches(path, prefix)))
+    }
+}
+
+/// Sliding-window rate limiter: each key keeps the timestamps of its requests
+/// within the last `window`, so the limit holds across window boundaries.
 #[derive(Clone)]
 pub struct ServiceRateLimit {
     max_requests: u32, // Conservative limit for service calls
     window: Duration,  // Short window for burst protection
-    storage: Arc<DashMap<String, (u32, Instant)>>,
+    storage: Arc<DashMap<String, VecDeque<Instant>>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl ServiceRateLimit {
+    fn new(max_requests: u32, window: Duration, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            max_requests,
+            window,
+            storage: Arc::new(DashMap::new()),
+            clock,
+        }
+    }
+
+    /// Record a request for `key`, returning false when it exceeds the limit
+    fn try_acquire(&self, key: &str) -> bool {
+        let now = self.clock.now_instant();
+        let mut timestamps = self.storage.entry(key.to_string()).or_default();
+
+        // Drop requests that have slid out of the window
+        while let Some(oldest) = timestamps.front() {
+            if now.duration_since(*oldest) >= self.window {
+                timestamps.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        if timestamps.len() >= self.max_requests as usize {
+            return false;
+        }
+
+        timestamps.push_back(now);
+        true
+    }
+
+    /// Current usage of every key, busiest first. Requests that have slid out
+    /// of the window are not counted, even if cleanup has not dropped them yet.
+    pub fn snapshot(&self) -> Vec<RateLimitUsage> {
+        let now = self.clock.now_instant();
+        let now_utc = self.clock.now_utc();
+        let mut usage: Vec<RateLimitUsage> = self
+            .storage
+            .iter()
+            .map(|entry| {
+                let live: Vec<&Instant> = entry
+                    .value()
+                    .iter()
+                    .filter(|at| now.duration_since(**at) < self.window)
+                    .collect();
+                // Sliding window: a slot frees up when the oldest request ages out
+                let resets_at = live.first().map(|oldest| {
+                    let remaining = self.window.saturating_sub(now.duration_since(**oldest));
+                    now_utc + chrono::Duration::from_std(remaining).unwrap_or_default()
+                });
+                RateLimitUsage {
+                    key: entry.key().clone(),
+                    count: live.len(),
+                    limit: self.max_requests,
+                    window_secs: self.window.as_secs(),
+                    resets_at,
+                }
+            })
+            .collect();
+        usage.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.key.cmp(&b.key)));
+        usage
+    }
+}
+
+/// One rate-limited caller as `/api/admin/ratelimit` reports it
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct RateLimitUsage {
+    /// Identity of the service key the requests are counted under
+    pub key: String,
+    /// Requests within the current window
+    pub count: usize,
+    pub limit: u32,
+    pub window_secs: u64,
+    /// When the oldest counted request leaves the window, None when nothing is counted
+    pub resets_at: Option<chrono::DateTime<chrono::Utc>>,
 }
 
 impl ServiceAuthConfig {
     /// Create new configuration for service-to-service auth
     pub fn new(service_key: String, service_name: &str) -> Self {
+        let clock: Arc<dyn Clock> = Arc::new(SystemClock);
         Self {
-            service_key: Arc::new(service_key.into_bytes()),
+            service_keys: Arc::new(vec![ServiceKey {
+                name: service_name.to_string(),
+                secret: service_key.into_bytes(),
+                allowed_paths: None,
+            }]),
             header_name: HeaderName::from_static("x-service-key"),
-            rate_limit: ServiceRateLimit {
-                max_requests: 1000, // Default conservative limit
-                window: Duration::from_secs(60),
-                storage: Arc::new(DashMap::new()),
-            },
+            // Default conservative limit
+            rate_limit: ServiceRateLimit::new(1000, Duration::from_secs(60), clock.clone()),
             service_name: service_name.to_string(),
+            auth_mode: AuthMode::StaticKey,
+            exempt_paths: Arc::new(Vec::new()),
+            clock,
         }
     }
 
+    /// Measure rate-limit windows and timestamp skew with `clock` instead of
+    /// the system time. Keeps the configured rate limit.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.rate_limit = ServiceRateLimit::new(
+            self.rate_limit.max_requests,
+            self.rate_limit.window,
+            clock.clone(),
+        );
+        self.clock = clock;
+        self
+    }
+
+    /// Select how callers authenticate
+    pub fn with_auth_mode(mut self, auth_mode: AuthMode) -> Self {
+        self.auth_mode = auth_mode;
+        self
+    }
+
+    /// Accept another key that may only call the given paths (and anything below them).
+    /// Callers using it are identified as `<service_name>-restricted-<n>`, counting from 1
+    /// in the order keys are added.
+    pub fn with_restricted_key(mut self, service_key: String, allowed_paths: Vec<String>) -> Self {
+        let name = format!(
+            "{}-restricted-{}",
+            self.service_name,
+            self.service_keys.len()
+        );
+        Arc::make_mut(&mut self.service_keys).push(ServiceKey {
+            name,
+            secret: service_key.into_bytes(),
+            allowed_paths: Some(allowed_paths),
+        });
+        self
+    }
+
+    /// Serve the given path, and everything below it, without requiring the service key
+    pub fn with_exempt_path(mut self, path: &str) -> Self {
+        Arc::make_mut(&mut self.exempt_paths).push(path.to_string());
+        self
+    }
+
+    fn is_exempt(&self, path: &str) -> bool {
+        self.exempt_paths
+            .iter()
+            .any(|exempt| path_matches(path, exempt))
+    }
+
     /// Set custom header name
     pub fn with_header_name(
         mut self,
@@ -59,27 +243,43 @@ impl ServiceAuthConfig {
         Ok(self)
     }
 
-    /// Configure rate limiting suitable for service-to-service communication
+    /// Configure rate limiting suitable for service-to-service communication.
+    /// The limit applies to each service key separately.
     pub fn with_rate_limit(mut self, max_requests: u32, window_seconds: u64) -> Self {
-        self.rate_limit = ServiceRateLimit {
+        self.rate_limit = ServiceRateLimit::new(
             max_requests,
-            window: Duration::from_secs(window_seconds),
-            storage: Arc::new(DashMap::new()),
-        };
+            Duration::from_secs(window_seconds),
+            self.clock.clone(),
+        );
         self
     }
 
+    /// Header the static service key is read from
+    pub fn header_name(&self) -> &HeaderName {
+        &self.header_name
+    }
+
+    /// The rate limiter requests are counted against
+    pub fn rate_limit(&self) -> &ServiceRateLimit {
+        &self.rate_limit
+    }
+
     /// Start the background cleanup task for rate limiting
     pub fn start_cleanup_task(&self) {
         let storage = self.rate_limit.storage.clone();
         let window = self.rate_limit.window;
+        let clock = self.rate_limit.clock.clone();
 
         tokio::spawn(async move {
             let mut interval = tokio::time::interval(window / 2);
             loop {
                 interval.tick().await;
-                let now = Instant::now();
-                storage.retain(|_, (_, timestamp)| now.duration_since(*timestamp) < window);
+                let now = clock.now_instant();
+                storage.retain(|_, timestamps| {
+                    timestamps
+                        .back()
+                        .is_some_and(|latest| now.duration_since(*latest) < window)
+                });
             }
         });
     }
@@ -138,55 +338,40 @@ where
         let service = self.service.clone();
 
         Box::pin(async move {
-            // Extract service key
-            let service_key = match req.headers().get(&config.header_name) {
-                Some(key) => key,
-                None => {
-                    return Ok(create_service_error_response(
-                        req,
-                        StatusCode::UNAUTHORIZED,
-                        "Missing service authentication",
-                    ))
-                }
+            let mut req = req;
+            if config.is_exempt(req.path()) {
+                let res = service.call(req).await?;
+                return Ok(res.map_into_boxed_body());
+            }
+
+            let verification = match config.auth_mode {
+                AuthMode::StaticKey => verify_static_key(&req, &config),
+                AuthMode::Hmac => verify_hmac(&mut req, &config).await,
             };
 
-            // Constant-time comparison
-            let key_bytes = match service_key.to_str() {
-                Ok(key) => key.as_bytes(),
-                Err(_) => {
-                    return Ok(create_service_error_response(
-                        req,
-                        StatusCode::BAD_REQUEST,
-                        "Invalid service key format",
-                    ))
+            let key = match verification {
+                Ok(key) => key,
+                Err((status, message)) => {
+                    return Ok(create_service_error_response(req, status, message));
                 }
             };
 
-            if key_bytes.ct_eq(&config.service_key).unwrap_u8() != 1 {
+            if !key.allows(req.path()) {
                 return Ok(create_service_error_response(
                     req,
                     StatusCode::FORBIDDEN,
-                    "Invalid service credentials",
+                    "Service key is not allowed to call this operation",
                 ));
             }
 
-            // Strict rate limiting
-            let service_id = &config.service_name;
-            let mut entry = config
-                .rate_limit
-                .storage
-                .entry(service_id.to_string())
-                .or_insert((0, Instant::now()));
+            req.extensions_mut().insert(ServiceIdentity {
+                name: key.name.clone(),
+                restricted: key.allowed_paths.is_some(),
+            });
 
-            let (count, last_request) = &mut *entry;
-            let now = Instant::now();
-
-            if now.duration_since(*last_request) >= config.rate_limit.window {
-                *count = 0;
-                *last_request = now;
-            }
-
-            if *count >= config.rate_limit.max_requests {
+            // Strict rate limiting, each key counted on its own
+            if !config.rate_limit.try_acquire(&key.name) {
+                metrics::record_rate_limited();
                 return Ok(create_service_error_response(
                     req,
                     StatusCode::TOO_MANY_REQUESTS,
@@ -194,8 +379,6 @@ where
                 ));
             }
 
-            *count += 1;
-
             // Authentication successful, proceed with request
             let res = service.call(req).await?;
             Ok(res.map_into_boxed_body())
@@ -203,17 +386,202 @@ where
     }
 }
 
+/// Which service key authenticated the request. Take it as a handler
+/// argument; extraction fails with 401 on paths served without a key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ServiceIdentity {
+    name: String,
+    restricted: bool,
+}
+
+impl ServiceIdentity {
+    /// The service name for the primary key, `<service_name>-restricted-<n>` otherwise
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether the key is limited to some paths
+    pub fn is_restricted(&self) -> bool {
+        self.restricted
+    }
+}
+
+impl FromRequest for ServiceIdentity {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(
+            req.extensions()
+                .get::<ServiceIdentity>()
+                .cloned()
+                .ok_or_else(|| {
+                    let message = "Request was not authenticated";
+                    InternalError::from_response(
+                        message,
+                        service_error_body(StatusCode::UNAUTHORIZED, message),
+                    )
+                    .into()
+                }),
+        )
+    }
+}
+
+type AuthFailure = (StatusCode, &'static str);
+
+fn verify_static_key<'a>(
+    req: &ServiceRequest,
+    config: &'a ServiceAuthConfig,
+) -> Result<&'a ServiceKey, AuthFailure> {
+    // Extract service key
+    let service_key = req
+        .headers()
+        .get(&config.header_name)
+        .ok_or((StatusCode::UNAUTHORIZED, "Missing service authentication"))?;
+
+    // Constant-time comparison
+    let key_bytes = service_key
+        .to_str()
+        .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid service key format"))?
+        .as_bytes();
+
+    // Constant-time per key; which key matched is not secret
+    config
+        .service_keys
+        .iter()
+        .find(|key| key_bytes.ct_eq(&key.secret).unwrap_u8() == 1)
+        .ok_or((StatusCode::FORBIDDEN, "Invalid service credentials"))
+}
+
+/// Verify `X-Signature = hex(HMAC-SHA256(secret, method + path_and_query + timestamp + hex(sha256(body))))`
+/// and reject timestamps outside `HMAC_MAX_SKEW` to prevent replay. The query
+/// string is signed as sent, so flags like `?dryRun=true` cannot be added or
+/// stripped in transit.
+async fn verify_hmac<'a>(
+    req: &mut ServiceRequest,
+    config: &'a ServiceAuthConfig,
+) -> Result<&'a ServiceKey, AuthFailure> {
+    let signature = header_str(req, HMAC_SIGNATURE_HEADER)?
+        .ok_or((StatusCode::UNAUTHORIZED, "Missing service authentication"))?;
+    let timestamp = header_str(req, HMAC_TIMESTAMP_HEADER)?
+        .ok_or((StatusCode::UNAUTHORIZED, "Missing request timestamp"))?;
+
+    let timestamp_secs = timestamp
+        .parse::<i64>()
+        .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid request timestamp"))?;
+    let skew = (config.clock.now_utc().timestamp() - timestamp_secs).unsigned_abs();
+    if skew > HMAC_MAX_SKEW.as_secs() {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            "Request timestamp outside allowed window",
+        ));
+    }
+
+    let provided =
+        decode_hex(&signature).ok_or((StatusCode::BAD_REQUEST, "Invalid signature format"))?;
+
+    // Buffer the body to hash it, then hand it back to the handler
+    let body = req
+        .extract::<Bytes>()
+        .await
+        .map_err(|_| (StatusCode::BAD_REQUEST, "Unable to read request body"))?;
+    req.set_payload(Payload::from(body.clone()));
+
+    let body_hash = hash(MessageDigest::sha256(), &body).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to hash request body",
+        )
+    })?;
+    let message = format!(
+        "{}{}{}{}",
+        req.method().as_str(),
+        req.uri()
+            .path_and_query()
+            .map_or_else(|| req.path(), |path_and_query| path_and_query.as_str()),
+        timestamp,
+        encode_hex(&body_hash)
+    );
+
+    for key in config.service_keys.iter() {
+        let expected = hmac_sha256(&key.secret, message.as_bytes()).map_err(|_| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to verify signature",
+            )
+        })?;
+
+        if provided.ct_eq(&expected).unwrap_u8() == 1 {
+            return Ok(key);
+        }
+    }
+
+    Err((StatusCode::FORBIDDEN, "Invalid service credentials"))
+}
+
+// `prefix` matches itself and anything below it, never a sibling like `/api/statusx`
+fn path_matches(path: &str, prefix: &str) -> bool {
+    path.strip_prefix(prefix)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+fn header_str(req: &ServiceRequest, name: &str) -> Result<Option<String>, AuthFailure> {
+    match req.headers().get(name) {
+        Some(value) => value
+            .to_str()
+            .map(|v| Some(v.trim().to_string()))
+            .map_err(|_| {
+                (
+                    StatusCode::BAD_REQUEST,
+                    "Invalid authentication header format",
+                )
+            }),
+        None => Ok(None),
+    }
+}
+
+fn hmac_sha256(secret: &[u8], data: &[u8]) -> Result<Vec<u8>, openssl::error::ErrorStack> {
+    let key = PKey::hmac(secret)?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+    signer.update(data)?;
+    signer.sign_to_vec()
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    if !value.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..value.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(value.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+// Same envelope the controllers answer with, so auth failures need no special casing
+fn service_error_body(status: StatusCode, message: &str) -> HttpResponse {
+    let error_code = match status {
+        StatusCode::UNAUTHORIZED => "UNAUTHORIZED",
+        StatusCode::FORBIDDEN => "FORBIDDEN",
+        StatusCode::TOO_MANY_REQUESTS => "RATE_LIMITED",
+        StatusCode::BAD_REQUEST => "INVALID_AUTHENTICATION",
+        _ => "AUTHENTICATION_ERROR",
+    };
+
+    HttpResponse::build(status).json(ApiResponse::<()> {
+        error_code: Some(error_code.to_string()),
+        ..ApiResponse::error(message)
+    })
+}
+
 fn create_service_error_response(
     req: ServiceRequest,
     status: StatusCode,
     message: &str,
 ) -> ServiceResponse<BoxBody> {
-    let response = HttpResponse::build(status).json(serde_json::json!({
-        "error": status.canonical_reason().unwrap_or("Service Error"),
-        "message": message,
-        "service_error": true,
-        "timestamp": chrono::Utc::now().to_rfc3339(),
-    }));
-
+    let response = service_error_body(status, message);
     req.into_response(response).map_into_boxed_body()
 }
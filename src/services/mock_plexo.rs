@@ -0,0 +1,252 @@
+//! In-process stand-in for Plexo's gateway, for exercising the full
+//! controller → service → signing → HTTP path without the shared testing
+//! server. Point `PLEXO_BASE_URL` at the address returned by `start`.
+//!
+//! Scenarios are picked from the request's reference (`ClientReferenceId`,
+//! `MetaReference` or `InstrumentToken`):
+//! - containing `decline`: Plexo answers with a non-zero result code
+//! - containing `timeout`: the answer is delayed by `MOCK_TIMEOUT_DELAY`
+//! - containing `status-NNN`: Plexo answers with HTTP status NNN
+//! - containing `3ds` (purchases): a 3-D Secure challenge is returned
+//! - containing `pending` (status): the transaction is reported as `Pending`
+//! - history searches list `MOCK_HISTORY_TRANSACTIONS` transactions (a
+//!   purchase followed by refunds), paged by `PageNumber` and `PageSize`
+//! - containing `empty-body`: HTTP 200 with no body
+//! - containing `html-body`: HTTP 200 with an HTML error page, as a proxy would send
+//! - containing `maintenance`: HTTP 503 with Plexo's maintenance notice
+//! - anything else: a canned successful response
+//!
+//! Responses are compressed with whichever encoding the caller accepts, as
+//! Plexo's edge may do.
+
+use actix_web::dev::ServerHandle;
+use actix_web::http::StatusCode;
+use actix_web::{middleware, web, App, HttpResponse, HttpServer};
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::time::Duration;
+
+/// Longer than `MAX_TIMEOUT_MS`, so callers always give up first
+pub const MOCK_TIMEOUT_DELAY: Duration = Duration::from_secs(65);
+
+/// Result code returned for declined scenarios
+pub const MOCK_DECLINED_RESULT_CODE: i32 = 1;
+
+/// Result code returned for maintenance scenarios
+pub const MOCK_MAINTENANCE_RESULT_CODE: i32 = 503;
+
+/// Transactions every history search reports for a reference
+pub const MOCK_HISTORY_TRANSACTIONS: u32 = 5;
+
+/// Start the mock on `addr` (use port 0 for an ephemeral port) and return the
+/// bound address with a handle to stop it.
+pub fn start(addr: SocketAddr) -> std::io::Result<(SocketAddr, ServerHandle)> {
+    let server = HttpServer::new(|| {
+        App::new()
+            .wrap(middleware::Compress::default())
+            .route("/Auth", web::post().to(authorize))
+            .route("/Operation/Purchase", web::post().to(purchase))
+            .route("/Operation/Status", web::post().to(status))
+            .route("/Operation/Search", web::post().to(search))
+            .route("/Instruments/Create", web::post().to(create_instrument))
+            .route("/Instruments/Delete", web::post().to(delete_instrument))
+            .route("/Commerce/Config", web::post().to(commerce_config))
+    })
+    .workers(1)
+    .disable_signals()
+    .bind(addr)?;
+
+    let bound = server.addrs()[0];
+    let server = server.run();
+    let handle = server.handle();
+    tokio::spawn(server);
+
+    Ok((bound, handle))
+}
+
+async fn authorize(body: web::Json<Value>) -> HttpResponse {
+    respond(&body, |_| {
+        json!({
+            "Id": "mock-session",
+            "Uri": "https://mock.plexo.local/session/mock-session",
+            "ExpirationUTC": chrono::Utc::now().timestamp() + 600,
+        })
+    })
+    .await
+}
+
+async fn purchase(body: web::Json<Value>) -> HttpResponse {
+    respond(&body, |request| {
+        let reference = request
+            .get("ClientReferenceId")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        if reference.to_ascii_lowercase().contains("3ds") {
+            return json!({
+                "Id": "mock-transaction",
+                "Status": "Pending",
+                "ThreeDSChallenge": {
+                    "AcsUrl": "https://mock.plexo.local/acs",
+                    "PaReq": "bW9jay1wYXJlcQ==",
+                    "TransactionId": "mock-3ds-transaction",
+                },
+            });
+        }
+        json!({
+            "Id": "mock-transaction",
+            "Status": "Approved",
+            "ClientReferenceId": request.get("ClientReferenceId"),
+            "CurrencyId": request.get("CurrencyId"),
+        })
+    })
+    .await
+}
+
+async fn status(body: web::Json<Value>) -> HttpResponse {
+    respond(&body, |request| {
+        let reference = request
+            .get("MetaReference")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        let status = if reference.to_ascii_lowercase().contains("pending") {
+            "Pending"
+        } else {
+            "Approved"
+        };
+        json!({
+            "Id": "mock-transaction",
+            "Status": status,
+            "MetaReference": request.get("MetaReference"),
+        })
+    })
+    .await
+}
+
+async fn search(body: web::Json<Value>) -> HttpResponse {
+    respond(&body, |request| {
+        let reference = request.get("ClientReferenceId").cloned();
+        let page_number = request
+            .get("PageNumber")
+            .and_then(Value::as_u64)
+            .unwrap_or(1)
+            .max(1) as u32;
+        let page_size = request
+            .get("PageSize")
+            .and_then(Value::as_u64)
+            .unwrap_or(10)
+            .max(1) as u32;
+
+        let first = (page_number - 1).saturating_mul(page_size);
+        let transactions: Vec<Value> = (first
+            ..MOCK_HISTORY_TRANSACTIONS.min(first.saturating_add(page_size)))
+            .map(|index| {
+                json!({
+                    "Id": format!("mock-transaction-{}", index + 1),
+                    "Type": if index == 0 { "Purchase" } else { "Refund" },
+                    "Status": "Approved",
+                    "ClientReferenceId": reference,
+                    "CurrencyId": 858,
+                    "Amount": if index == 0 { json!(100.50) } else { json!(10.10) },
+                    "CreatedAt": format!("2024-01-0{}T12:00:00Z", index + 1),
+                })
+            })
+            .collect();
+        json!({
+            "Transactions": transactions,
+            "PageNumber": page_number,
+            "PageSize": page_size,
+            "TotalCount": MOCK_HISTORY_TRANSACTIONS,
+        })
+    })
+    .await
+}
+
+async fn create_instrument(body: web::Json<Value>) -> HttpResponse {
+    respond(&body, |_| {
+        json!({
+            "InstrumentToken": "mock-instrument-token",
+            "Issuer": { "Id": 1, "Name": "Mock issuer" },
+        })
+    })
+    .await
+}
+
+async fn commerce_config(body: web::Json<Value>) -> HttpResponse {
+    respond(&body, |request| {
+        json!({
+            "CommerceId": request.get("CommerceId"),
+            "Name": "Mock commerce",
+            "IsActive": true,
+            "Currencies": [858, 840],
+            "Issuers": [{ "Id": 1, "Name": "Mock issuer" }],
+        })
+    })
+    .await
+}
+
+async fn delete_instrument(body: web::Json<Value>) -> HttpResponse {
+    respond(&body, |_| json!({})).await
+}
+
+async fn respond(body: &Value, success: impl Fn(&Value) -> Value) -> HttpResponse {
+    // The service sends the signed envelope; the operation sits under
+    // Object.Object (V1) or signedObject.payload (V2)
+    let request = body
+        .pointer("/Object/Object/Request")
+        .or_else(|| body.pointer("/signedObject/payload/Request"))
+        .cloned()
+        .unwrap_or(Value::Null);
+
+    let reference = ["ClientReferenceId", "MetaReference", "InstrumentToken"]
+        .iter()
+        .find_map(|key| request.get(key).and_then(Value::as_str))
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+
+    if let Some(status) = requested_status(&reference) {
+        return HttpResponse::build(status).finish();
+    }
+
+    if reference.contains("timeout") {
+        tokio::time::sleep(MOCK_TIMEOUT_DELAY).await;
+    }
+
+    if reference.contains("empty-body") {
+        return HttpResponse::Ok().finish();
+    }
+
+    if reference.contains("html-body") {
+        return HttpResponse::Ok()
+            .content_type("text/html")
+            .body("<html><body><h1>502 Bad Gateway</h1></body></html>");
+    }
+
+    if reference.contains("maintenance") {
+        return HttpResponse::ServiceUnavailable().json(json!({
+            "ResultCode": MOCK_MAINTENANCE_RESULT_CODE,
+            "ErrorMessage": "Service under scheduled maintenance",
+            "Response": null,
+        }));
+    }
+
+    if reference.contains("decline") {
+        return HttpResponse::Ok().json(json!({
+            "ResultCode": MOCK_DECLINED_RESULT_CODE,
+            "ErrorMessage": "Declined by mock issuer",
+            "Response": null,
+        }));
+    }
+
+    HttpResponse::Ok().json(json!({
+        "ResultCode": 0,
+        "ErrorMessage": null,
+        "Response": success(&request),
+    }))
+}
+
+// `status-503` in the reference asks for that HTTP status
+fn requested_status(reference: &str) -> Option<StatusCode> {
+    let (_, rest) = reference.split_once("status-")?;
+    let code = rest.get(..3)?.parse::<u16>().ok()?;
+    StatusCode::from_u16(code).ok()
+}
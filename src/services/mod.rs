@@ -1,3 +1,24 @@
+pub mod admission;
+pub mod audit;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+pub mod clock;
 pub mod crypto;
+pub mod idempotency;
+pub mod logging;
+pub mod metrics;
 pub mod middleware;
+#[cfg(feature = "mock-plexo")]
+pub mod mock_plexo;
 pub mod plexo_service;
+pub mod projection;
+pub mod purchase_queue;
+pub mod recent_requests;
+pub mod redaction;
+pub mod reference_cache;
+pub mod response_signing;
+pub mod secrets;
+pub mod signer;
+pub mod status_cache;
+pub mod validation;
+pub mod webhook;
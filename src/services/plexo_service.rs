@@ -1,20 +1,60 @@
-use std::time::Duration;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
 
-use crate::models::common::LosslessNumber;
-use crate::models::requests::{AuthorizationRequest, PaymentRequest, StatusRequest};
-use crate::services::crypto::{get_crypto_service, CryptoError};
-use log::{error, info};
-use reqwest::Client;
+use crate::models::common::{
+    decimal_places_for_currency, AmountFormatError, InvoiceNumberFormat, LosslessNumber,
+    DEFAULT_DECIMAL_PLACES,
+};
+use crate::models::requests::{
+    AuthorizationAction, AuthorizationRequest, CommerceInfoRequest, DeleteInstrumentRequest,
+    PaymentRequest, RegisterInstrumentRequest, StatusQuery, StatusRequest,
+    TransactionHistoryRequest,
+};
+use crate::models::responses::{
+    CanonicalPreview, CommerceConfig, CommerceInfo, DeleteInstrumentResponse, ModifiedField,
+    Outcome, PlexoResponse, RegisterInstrumentResponse, RegisteredInstrument, SignedRequest,
+    TransactionHistory, TransactionPage,
+};
+use crate::services::admission;
+use crate::services::crypto::{
+    canonize_json, canonize_json_with, crypto_handle, get_crypto_service, CanonOptions,
+    CryptoError, CryptoService,
+};
+use crate::services::metrics;
+use crate::services::recent_requests;
+use crate::services::redaction;
+use crate::services::reference_cache;
+use crate::services::status_cache;
+use crate::services::validation::{self, ValidationError, ValidationErrors};
+use arc_swap::ArcSwap;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use dashmap::DashMap;
+use log::{debug, info, warn};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{tls, Client, Identity};
+use serde::Serialize;
 use serde_json::{json, Value};
 use thiserror::Error;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use unicode_normalization::{is_nfc_quick, IsNormalized, UnicodeNormalization};
 
-const PLEXO_AUTH_URL: &str = "https://testing.plexo.com.uy:4043/SecurePaymentGateway.svc/Auth";
-const PLEXO_PURCHASE_URL: &str =
-    "https://testing.plexo.com.uy:4043/SecurePaymentGateway.svc/Operation/Purchase";
-const PLEXO_STATUS_URL: &str =
-    "https://testing.plexo.com.uy:4043/SecurePaymentGateway.svc/Operation/Status";
+const DEFAULT_PLEXO_BASE_URL: &str = "https://testing.plexo.com.uy:4043/SecurePaymentGateway.svc";
+const PLEXO_AUTH_PATH: &str = "/Auth";
+const PLEXO_PURCHASE_PATH: &str = "/Operation/Purchase";
+const PLEXO_STATUS_PATH: &str = "/Operation/Status";
+const PLEXO_INSTRUMENT_CREATE_PATH: &str = "/Instruments/Create";
+const PLEXO_INSTRUMENT_DELETE_PATH: &str = "/Instruments/Delete";
+const PLEXO_COMMERCE_CONFIG_PATH: &str = "/Commerce/Config";
+const PLEXO_TRANSACTION_HISTORY_PATH: &str = "/Operation/Search";
+// How long commerce configuration is reused when COMMERCE_INFO_TTL_SECS is not set
+const DEFAULT_COMMERCE_INFO_TTL_SECS: u64 = 300;
 
-#[derive(Error, Debug)]
+// Result code Plexo returns when the instrument token is unknown
+const PLEXO_RESULT_INSTRUMENT_NOT_FOUND: i32 = 14;
+
+#[derive(Error)]
 pub enum PlexoServiceError {
     #[error("Failed to sign request: {0}")]
     SigningError(#[from] CryptoError),
@@ -25,127 +65,1515 @@ pub enum PlexoServiceError {
     #[error("HTTP request timeout")]
     Timeout,
 
-    #[error("Serialization error: {0}")]
+    // DNS, refused connections and TLS handshake failures: Plexo never saw the request
+    #[error("Could not connect to Plexo: {0}")]
+    ConnectionError(reqwest::Error),
+
+    #[error("Plexo answered with HTTP {status}")]
+    UpstreamStatus { status: u16 },
+
+    // The underlying serde message can quote request values, so only the
+    // error category and position are rendered
+    #[error("Serialization error: {}", redaction::describe_json_error(.0))]
     SerializationError(#[from] serde_json::Error),
+
+    #[error("Configuration error: {0}")]
+    ConfigurationError(String),
+
+    #[error("Validation error: {0}")]
+    ValidationError(#[from] ValidationErrors),
+
+    #[error("Duplicate ClientReferenceId: {0}")]
+    DuplicateReference(String),
+
+    #[error("Instrument not found")]
+    InstrumentNotFound,
+
+    #[error("Plexo rejected the request (code {code}): {message}")]
+    PlexoResultError { code: i32, message: String },
+
+    #[error("Too many concurrent Plexo requests, retry shortly")]
+    Overloaded,
+
+    #[error("Plexo answered HTTP {status} with a body that could not be read: {snippet:?}")]
+    UnexpectedResponse { status: u16, snippet: String },
+
+    // Scheduled downtime, not a failure of this request; retrying soon is pointless
+    #[error("Plexo is in a maintenance window, retry in {retry_after_secs} s")]
+    Maintenance { retry_after_secs: u64 },
 }
 
-pub async fn send_authorization_request(
-    auth_request: AuthorizationRequest,
-) -> Result<Value, PlexoServiceError> {
-    // Convert request to Value and remove null values before signing
-    let mut request_value = json!(auth_request);
-    clean_nulls(&mut request_value);
-    println!("signed_payload: {:#?}", request_value);
+// Default outbound timeout when PLEXO_TIMEOUT_MS is not set
+const DEFAULT_TIMEOUT_MS: u64 = 10_000;
+// Upper bound for per-request timeout overrides
+pub const MAX_TIMEOUT_MS: u64 = 60_000;
+// How long a call waits for a free slot under MAX_CONCURRENT_PLEXO before it is shed
+const CONCURRENCY_WAIT_MS: u64 = 250;
+// Pause before the first retry when PLEXO_RETRY_BACKOFF_MS is not set; grows linearly
+const DEFAULT_RETRY_BACKOFF_MS: u64 = 200;
+// Idle connections kept to Plexo when PLEXO_POOL_MAX_IDLE is not set; there is
+// only one upstream host, so this is the whole pool
+const DEFAULT_POOL_MAX_IDLE: u64 = 32;
+// Time to open a connection when PLEXO_CONNECT_TIMEOUT_MS is not set, well under
+// the request timeout so an unreachable Plexo fails fast
+const DEFAULT_CONNECT_TIMEOUT_MS: u64 = 3_000;
+// How long an unused connection is kept when PLEXO_IDLE_TIMEOUT_MS is not set
+const DEFAULT_IDLE_TIMEOUT_MS: u64 = 90_000;
 
-    // Sign the payload
-    let crypto_service = get_crypto_service()?;
-    let signed_payload = crypto_service.create_signed_payload(&request_value)?;
+impl From<ValidationError> for PlexoServiceError {
+    fn from(error: ValidationError) -> Self {
+        PlexoServiceError::ValidationError(error.into())
+    }
+}
+
+impl PlexoServiceError {
+    /// HTTP status Plexo answered with, if it answered at all. Timeouts and
+    /// connection failures never reached a response, so they have none.
+    pub fn upstream_status(&self) -> Option<u16> {
+        match self {
+            PlexoServiceError::UpstreamStatus { status }
+            | PlexoServiceError::UnexpectedResponse { status, .. } => Some(*status),
+            PlexoServiceError::HttpRequestError(e) => e.status().map(|status| status.as_u16()),
+            _ => None,
+        }
+    }
+}
 
-    info!("Sending authorization request to Plexo");
+// Debug mirrors Display so `{:?}` can never leak payload fragments into logs
+impl fmt::Debug for PlexoServiceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PlexoServiceError({})", self)
+    }
+}
 
-    // Send the request to Plexo
-    let client = Client::builder().timeout(Duration::from_secs(10)).build()?;
-    let response = client
-        .post(PLEXO_AUTH_URL)
-        .json(&signed_payload)
-        .send()
-        .await
-        .map_err(|e| {
-            if e.is_timeout() {
-                PlexoServiceError::Timeout
-            } else {
-                PlexoServiceError::HttpRequestError(e)
-            }
+// Client built by `init()` from the environment, shared by the free functions
+static DEFAULT_CLIENT: OnceLock<Arc<PlexoClient>> = OnceLock::new();
+// Set by `init()` from INVOICE_NUMBER_FORMAT, numeric when unset
+static INVOICE_NUMBER_FORMAT: OnceLock<InvoiceNumberFormat> = OnceLock::new();
+// Set by `init()` from NORMALIZE_UNICODE, on when unset
+static NORMALIZE_UNICODE: OnceLock<bool> = OnceLock::new();
+
+// Free text that may arrive in any Unicode normalization form; every string
+// below these keys is sent in NFC
+const FREE_TEXT_KEYS: &[&str] = &["ClientInformation", "WebFormSettings", "OptionalMetadata"];
+
+// Set by `init()` from PLEXO_MAINTENANCE_PATTERN and PLEXO_MAINTENANCE_RETRY_AFTER_SECS
+static MAINTENANCE_SIGNAL: OnceLock<MaintenanceSignal> = OnceLock::new();
+
+/// How Plexo's maintenance answers are recognized. Plexo's exact wording
+/// differs between environments, so the pattern is a case-insensitive
+/// substring looked for in error bodies and in `ErrorMessage`.
+#[derive(Debug, Clone)]
+pub struct MaintenanceSignal {
+    pattern: String,
+    retry_after_secs: u64,
+}
+
+impl Default for MaintenanceSignal {
+    fn default() -> Self {
+        Self {
+            pattern: "maintenance".to_string(),
+            retry_after_secs: 600,
+        }
+    }
+}
+
+impl MaintenanceSignal {
+    fn from_env() -> Result<Self, PlexoServiceError> {
+        let mut signal = Self::default();
+        if let Some(pattern) = std::env::var("PLEXO_MAINTENANCE_PATTERN")
+            .ok()
+            .filter(|pattern| !pattern.trim().is_empty())
+        {
+            signal.pattern = pattern.trim().to_lowercase();
+        }
+        if let Ok(raw) = std::env::var("PLEXO_MAINTENANCE_RETRY_AFTER_SECS") {
+            signal.retry_after_secs = raw.trim().parse::<u64>().map_err(|_| {
+                PlexoServiceError::ConfigurationError(
+                    "PLEXO_MAINTENANCE_RETRY_AFTER_SECS must be a number".to_string(),
+                )
+            })?;
+        }
+        Ok(signal)
+    }
+
+    fn matches(&self, text: &str) -> bool {
+        text.to_lowercase().contains(&self.pattern)
+    }
+
+    fn error(&self) -> PlexoServiceError {
+        PlexoServiceError::Maintenance {
+            retry_after_secs: self.retry_after_secs,
+        }
+    }
+}
+
+fn maintenance_signal() -> &'static MaintenanceSignal {
+    MAINTENANCE_SIGNAL.get_or_init(MaintenanceSignal::default)
+}
+
+// Plexo calls currently running, and calls dropped before they completed
+static IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+static ABORTED: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of Plexo calls currently in flight
+pub fn in_flight_requests() -> usize {
+    IN_FLIGHT.load(Ordering::SeqCst)
+}
+
+/// Number of Plexo calls cancelled before completing (e.g. at shutdown)
+pub fn aborted_requests() -> usize {
+    ABORTED.load(Ordering::SeqCst)
+}
+
+// Tracks a call for graceful shutdown; dropping it unfinished counts as aborted
+struct InFlightGuard {
+    finished: bool,
+}
+
+impl InFlightGuard {
+    fn new() -> Self {
+        IN_FLIGHT.fetch_add(1, Ordering::SeqCst);
+        Self { finished: false }
+    }
+
+    fn finish(mut self) {
+        self.finished = true;
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        IN_FLIGHT.fetch_sub(1, Ordering::SeqCst);
+        if !self.finished {
+            ABORTED.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Plexo operations exposed by this service
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Authorize,
+    Purchase,
+    Status,
+    Cancel,
+    RegisterInstrument,
+    DeleteInstrument,
+    CommerceInfo,
+    TransactionHistory,
+}
+
+impl Operation {
+    /// Stable name used in logs and metric labels
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Operation::Authorize => "authorize",
+            Operation::Purchase => "purchase",
+            Operation::Status => "status",
+            Operation::Cancel => "cancel",
+            Operation::RegisterInstrument => "register_instrument",
+            Operation::DeleteInstrument => "delete_instrument",
+            Operation::CommerceInfo => "commerce_info",
+            Operation::TransactionHistory => "transaction_history",
+        }
+    }
+
+    /// Whether sending this operation twice is harmless. Lookups change
+    /// nothing, an authorization only opens a session and a voided operation
+    /// stays voided; purchases and instrument changes could be applied twice.
+    pub fn idempotent(&self) -> bool {
+        matches!(
+            self,
+            Operation::Authorize
+                | Operation::Status
+                | Operation::Cancel
+                | Operation::CommerceInfo
+                | Operation::TransactionHistory
+        )
+    }
+}
+
+/// Whether a failed call to Plexo may be sent again. A connection that never
+/// opened cannot have reached Plexo, so any operation retries it. Timeouts and
+/// gateway errors are ambiguous (Plexo may have processed the request), so only
+/// idempotent operations retry those.
+pub fn retryable(operation: Operation, error: &PlexoServiceError) -> bool {
+    match error {
+        PlexoServiceError::ConnectionError(_) => true,
+        _ if !operation.idempotent() => false,
+        PlexoServiceError::Timeout
+        | PlexoServiceError::HttpRequestError(_)
+        | PlexoServiceError::UnexpectedResponse { .. } => true,
+        PlexoServiceError::UpstreamStatus { status } => matches!(status, 502..=504),
+        _ => false,
+    }
+}
+
+/// Whether Plexo received the request and answered it. Every other failure
+/// (no connection, a timeout, load shedding, maintenance, or a local error
+/// before sending) leaves Plexo without a judgement on the request.
+pub fn plexo_answered(error: &PlexoServiceError) -> bool {
+    matches!(
+        error,
+        PlexoServiceError::PlexoResultError { .. }
+            | PlexoServiceError::UpstreamStatus { .. }
+            | PlexoServiceError::UnexpectedResponse { .. }
+    )
+}
+
+/// Whether Plexo may have acted on a request that failed with `error`: it
+/// timed out, the connection broke mid-request, or Plexo answered. Only the
+/// other failures (no connection, load shedding, maintenance, or a local error
+/// before sending) provably left the request unprocessed.
+pub fn may_have_reached_plexo(error: &PlexoServiceError) -> bool {
+    plexo_answered(error)
+        || matches!(
+            error,
+            PlexoServiceError::Timeout | PlexoServiceError::HttpRequestError(_)
+        )
+}
+
+/// A purchase sent on top of an authorization, and what became of that
+/// authorization
+#[derive(Debug)]
+pub struct CompensatedPurchase {
+    pub purchase: Result<Value, PlexoServiceError>,
+    pub authorization: AuthorizationVoidResult,
+}
+
+/// What `purchase_or_void` did with the authorization behind a purchase
+#[derive(Debug)]
+pub enum AuthorizationVoidResult {
+    /// The purchase was not declined, so the authorization stays in use
+    NotNeeded,
+    /// Plexo declined the purchase and a cancel was sent
+    Attempted(Result<Value, PlexoServiceError>),
+    /// The purchase failed without a decline, so Plexo may have charged it (a
+    /// timeout or an unreadable answer) or never seen it (a local error); or
+    /// it was declined with no `CancelContract` configured. The authorization
+    /// is left open for the caller to settle.
+    LeftOpen,
+}
+
+/// Per-call options supplied by the API caller
+#[derive(Debug, Clone, Default)]
+pub struct RequestOptions {
+    /// Overrides the client-wide timeout for this call
+    pub timeout: Option<Duration>,
+    /// Skip the recent ClientReferenceId check and re-send a purchase
+    pub allow_duplicate: bool,
+    /// Correlation id of the API call, kept with the payload in the recent requests buffer
+    pub correlation_id: Option<String>,
+    /// Ask Plexo even when a recent status result is cached
+    pub no_cache: bool,
+}
+
+impl RequestOptions {
+    /// Set a timeout override in milliseconds, clamped to `MAX_TIMEOUT_MS`
+    pub fn with_timeout_ms(mut self, timeout_ms: u64) -> Self {
+        self.timeout = Some(Duration::from_millis(timeout_ms.clamp(1, MAX_TIMEOUT_MS)));
+        self
+    }
+
+    pub fn with_correlation_id(mut self, correlation_id: impl Into<String>) -> Self {
+        self.correlation_id = Some(correlation_id.into());
+        self
+    }
+}
+
+/// Build the default client used for every call to Plexo, signing with the
+/// global crypto service (which must be initialized first).
+///
+/// `PLEXO_BASE_URL` points it at another gateway, e.g. production or a mock.
+/// Mutual TLS is off by default. It is enabled by providing a client identity
+/// either as a base64 PKCS12 bundle (`PLEXO_MTLS_PFX_BASE64` and
+/// `PLEXO_MTLS_PFX_PASSWORD`) or as PEM files (`PLEXO_MTLS_CERT_PEM_PATH` and
+/// `PLEXO_MTLS_KEY_PEM_PATH`, the key in PKCS8 format).
+///
+/// `OPERATION_DEADLINE_MS` bounds each operation end to end (signing and the
+/// HTTP call); exceeding it fails with `Timeout`. Unset means no deadline
+/// beyond the HTTP timeout.
+///
+/// `MAX_CONCURRENT_PLEXO` caps simultaneous calls to Plexo; calls beyond the
+/// cap are rejected as `Overloaded` instead of queueing. Unset means no cap.
+///
+/// `INVOICE_NUMBER_FORMAT` selects how `FinancialInclusion.InvoiceNumber` is
+/// sent: `number` (default), `string` or `string:<width>` for zero padding.
+///
+/// `COMMERCE_INFO_TTL_SECS` sets how long commerce configuration is cached
+/// (default 300, 0 disables the cache).
+///
+/// `PLEXO_MAINTENANCE_PATTERN` is the text that marks a Plexo answer as a
+/// maintenance window (default `maintenance`); such answers fail with
+/// `Maintenance`, asking callers to come back after
+/// `PLEXO_MAINTENANCE_RETRY_AFTER_SECS` (default 600).
+///
+/// `PLEXO_EXTRA_HEADERS` (`name1=value1;name2=value2`) adds static headers to
+/// every request, e.g. a tenant header Plexo asks for in one environment.
+///
+/// Requests carry `User-Agent: plexo-back/<version>`, with `PLEXO_CLIENT_ID`
+/// appended as `(<id>)` when set, so Plexo support can tell which integration
+/// called. `PLEXO_USER_AGENT` replaces the whole value.
+///
+/// Strings in `ClientInformation`, `WebFormSettings` and `OptionalMetadata`
+/// are converted to Unicode NFC before signing, so visually identical text
+/// always canonicalizes the same; `NORMALIZE_UNICODE=false` sends them as received.
+///
+/// `PLEXO_POOL_MAX_IDLE` (default 32) bounds the idle connections kept to
+/// Plexo, `PLEXO_IDLE_TIMEOUT_MS` (default 90000) how long they are kept and
+/// `PLEXO_CONNECT_TIMEOUT_MS` (default 3000) how long opening one may take.
+/// Each must be positive.
+///
+/// `MAX_PAYMENT_AMOUNT` caps every amount of a payment; a value that is not a
+/// positive decimal fails here. So does a `MAX_PAYMENT_ITEMS` (default 100)
+/// or `MAX_OPTIONAL_METADATA_BYTES` (default 2048) that is not a positive
+/// number.
+///
+/// `PLEXO_MAX_RETRIES` re-sends failed calls up to that many times (default 0),
+/// waiting `PLEXO_RETRY_BACKOFF_MS` (default 200) times the attempt number in
+/// between. Which failures are retried depends on the operation, see `retryable`.
+pub fn init() -> Result<(), PlexoServiceError> {
+    let _ = MAINTENANCE_SIGNAL.set(MaintenanceSignal::from_env()?);
+    validation::init()?;
+
+    if let Some(raw) = std::env::var("INVOICE_NUMBER_FORMAT")
+        .ok()
+        .filter(|raw| !raw.trim().is_empty())
+    {
+        let format = InvoiceNumberFormat::parse(&raw).ok_or_else(|| {
+            PlexoServiceError::ConfigurationError(
+                "INVOICE_NUMBER_FORMAT must be number, string or string:<width>".to_string(),
+            )
         })?;
+        let _ = INVOICE_NUMBER_FORMAT.set(format);
+    }
 
-    let parsed_response = response.json::<Value>().await?;
+    let normalize = !matches!(
+        std::env::var("NORMALIZE_UNICODE").as_deref(),
+        Ok("false") | Ok("0")
+    );
+    if !normalize {
+        warn!("NORMALIZE_UNICODE is off, free-text fields are signed as received");
+    }
+    let _ = NORMALIZE_UNICODE.set(normalize);
 
-    info!("Received authorization response from Plexo");
+    let client = PlexoClient::from_env()?;
+    // A second init keeps the first client; it is configured from the same env
+    let _ = DEFAULT_CLIENT.set(Arc::new(client));
 
-    println!("signed payload response: {:#?}", parsed_response);
+    Ok(())
+}
 
-    Ok(parsed_response)
+/// The client built by `init()`
+pub fn default_client() -> Result<Arc<PlexoClient>, PlexoServiceError> {
+    DEFAULT_CLIENT.get().cloned().ok_or_else(|| {
+        PlexoServiceError::ConfigurationError("Plexo client not initialized".to_string())
+    })
 }
 
-pub async fn send_payment_request(
-    payment_request: PaymentRequest,
-) -> Result<Value, PlexoServiceError> {
-    // Convert request to Value and remove null values before signing
-    let mut request_value = json!(payment_request);
-    clean_nulls(&mut request_value);
-    println!("payment request: {:#?}", request_value);
+// A positive number from `name`, `default` when unset
+fn positive_env(name: &str, default: u64) -> Result<u64, PlexoServiceError> {
+    match std::env::var(name) {
+        Ok(value) => match value.trim().parse::<u64>() {
+            Ok(number) if number > 0 => Ok(number),
+            _ => Err(PlexoServiceError::ConfigurationError(format!(
+                "{} must be a positive number",
+                name
+            ))),
+        },
+        Err(_) => Ok(default),
+    }
+}
 
-    // Sign the payload
-    let crypto_service = get_crypto_service()?;
-    let signed_payload = crypto_service.create_signed_payload(&request_value)?;
+fn build_http_client() -> Result<Client, PlexoServiceError> {
+    let timeout_ms = match std::env::var("PLEXO_TIMEOUT_MS") {
+        Ok(value) => value.parse::<u64>().map_err(|_| {
+            PlexoServiceError::ConfigurationError("PLEXO_TIMEOUT_MS must be a number".to_string())
+        })?,
+        Err(_) => DEFAULT_TIMEOUT_MS,
+    };
+
+    let pool_max_idle = positive_env("PLEXO_POOL_MAX_IDLE", DEFAULT_POOL_MAX_IDLE)?;
+    let connect_timeout_ms = positive_env("PLEXO_CONNECT_TIMEOUT_MS", DEFAULT_CONNECT_TIMEOUT_MS)?;
+    let idle_timeout_ms = positive_env("PLEXO_IDLE_TIMEOUT_MS", DEFAULT_IDLE_TIMEOUT_MS)?;
+
+    let min_tls_version = tls_min_version()?;
+    info!("Requiring TLS 1.2 or newer for Plexo requests");
+    let user_agent = user_agent()?;
+    info!("Identifying to Plexo as {:?}", user_agent);
+    let mut builder = Client::builder()
+        .timeout(Duration::from_millis(timeout_ms))
+        .connect_timeout(Duration::from_millis(connect_timeout_ms))
+        .pool_max_idle_per_host(pool_max_idle as usize)
+        .pool_idle_timeout(Duration::from_millis(idle_timeout_ms))
+        .user_agent(user_agent)
+        // Plexo or a proxy in front of it may compress; bodies are inflated
+        // before parsing so callers always see plain JSON
+        .gzip(true)
+        .deflate(true)
+        .brotli(true)
+        // Handshakes below this version fail instead of downgrading
+        .min_tls_version(min_tls_version);
+
+    if let Some(identity) = load_client_identity()? {
+        info!("Mutual TLS enabled for Plexo requests");
+        builder = builder.identity(identity);
+    }
+
+    let extra_headers =
+        parse_extra_headers(&std::env::var("PLEXO_EXTRA_HEADERS").unwrap_or_default())?;
+    if !extra_headers.is_empty() {
+        // Values may be credentials, so only the names are logged
+        let names: Vec<&str> = extra_headers.keys().map(HeaderName::as_str).collect();
+        info!("Sending extra headers to Plexo: {}", names.join(", "));
+        builder = builder.default_headers(extra_headers);
+    }
+
+    builder.build().map_err(|e| {
+        PlexoServiceError::ConfigurationError(format!(
+            "Failed to build the Plexo HTTP client: {}",
+            e
+        ))
+    })
+}
+
+/// `PLEXO_USER_AGENT` verbatim, or `plexo-back/<version>` followed by
+/// `PLEXO_CLIENT_ID` in parentheses when that is set
+fn user_agent() -> Result<HeaderValue, PlexoServiceError> {
+    let non_empty = |name: &str| {
+        std::env::var(name)
+            .ok()
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    };
+    let user_agent = match non_empty("PLEXO_USER_AGENT") {
+        Some(user_agent) => user_agent,
+        None => {
+            let product = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+            match non_empty("PLEXO_CLIENT_ID") {
+                Some(client_id) => format!("{} ({})", product, client_id),
+                None => product.to_string(),
+            }
+        }
+    };
+    HeaderValue::from_str(&user_agent).map_err(|_| {
+        PlexoServiceError::ConfigurationError(
+            "PLEXO_USER_AGENT and PLEXO_CLIENT_ID must be valid header text".to_string(),
+        )
+    })
+}
+
+/// Parse `PLEXO_EXTRA_HEADERS` as `name1=value1;name2=value2`, rejecting
+/// anything that is not a legal header so a typo fails at startup
+fn parse_extra_headers(raw: &str) -> Result<HeaderMap, PlexoServiceError> {
+    let mut headers = HeaderMap::new();
+    for entry in raw
+        .split(';')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+    {
+        let invalid = |reason: &str| {
+            PlexoServiceError::ConfigurationError(format!(
+                "PLEXO_EXTRA_HEADERS entry {:?} {}",
+                entry.split('=').next().unwrap_or_default(),
+                reason
+            ))
+        };
+        let (name, value) = entry
+            .split_once('=')
+            .ok_or_else(|| invalid("must be name=value"))?;
+        let name = HeaderName::from_bytes(name.trim().as_bytes())
+            .map_err(|_| invalid("is not a valid header name"))?;
+        let value = HeaderValue::from_str(value.trim())
+            .map_err(|_| invalid("has an invalid header value"))?;
+        headers.insert(name, value);
+    }
+    Ok(headers)
+}
+
+/// `PLEXO_TLS_MIN_VERSION=1.2`, also the default when unset. The native-tls
+/// backend this client uses cannot enforce a 1.3 minimum, so 1.3 is rejected
+/// here with its own message rather than as an opaque client builder error.
+fn tls_min_version() -> Result<tls::Version, PlexoServiceError> {
+    let raw = std::env::var("PLEXO_TLS_MIN_VERSION").unwrap_or_default();
+    match raw.trim() {
+        "" | "1.2" => Ok(tls::Version::TLS_1_2),
+        "1.3" => Err(PlexoServiceError::ConfigurationError(
+            "PLEXO_TLS_MIN_VERSION=1.3 is not supported by the native-tls backend, use 1.2"
+                .to_string(),
+        )),
+        _ => Err(PlexoServiceError::ConfigurationError(
+            "PLEXO_TLS_MIN_VERSION must be 1.2".to_string(),
+        )),
+    }
+}
+
+fn load_client_identity() -> Result<Option<Identity>, PlexoServiceError> {
+    if let Ok(pfx_base64) = std::env::var("PLEXO_MTLS_PFX_BASE64") {
+        let password = std::env::var("PLEXO_MTLS_PFX_PASSWORD").unwrap_or_default();
+        let der = BASE64.decode(pfx_base64.trim()).map_err(|e| {
+            PlexoServiceError::ConfigurationError(format!(
+                "Failed to decode PLEXO_MTLS_PFX_BASE64: {}",
+                e
+            ))
+        })?;
+        let identity = Identity::from_pkcs12_der(&der, &password)?;
+        return Ok(Some(identity));
+    }
+
+    match (
+        std::env::var("PLEXO_MTLS_CERT_PEM_PATH"),
+        std::env::var("PLEXO_MTLS_KEY_PEM_PATH"),
+    ) {
+        (Ok(cert_path), Ok(key_path)) => {
+            let cert = std::fs::read(&cert_path).map_err(|e| {
+                PlexoServiceError::ConfigurationError(format!(
+                    "Failed to read client certificate {}: {}",
+                    cert_path, e
+                ))
+            })?;
+            let key = std::fs::read(&key_path).map_err(|e| {
+                PlexoServiceError::ConfigurationError(format!(
+                    "Failed to read client key {}: {}",
+                    key_path, e
+                ))
+            })?;
+            Ok(Some(Identity::from_pkcs8_pem(&cert, &key)?))
+        }
+        (Err(_), Err(_)) => Ok(None),
+        _ => Err(PlexoServiceError::ConfigurationError(
+            "PLEXO_MTLS_CERT_PEM_PATH and PLEXO_MTLS_KEY_PEM_PATH must be set together".to_string(),
+        )),
+    }
+}
+
+/// Signs and sends operations to one Plexo gateway. Every dependency is held
+/// here, so tests and embedders can point it at any gateway with any key.
+pub struct PlexoClient {
+    base_url: String,
+    http: Client,
+    crypto: Arc<ArcSwap<CryptoService>>,
+    concurrency: Option<Arc<Semaphore>>,
+    deadline: Option<Duration>,
+    // Commerce configuration changes rarely; entries are reused until they expire
+    commerce_info_ttl: Duration,
+    commerce_info_cache: DashMap<(String, i32), (CommerceInfo, Instant)>,
+    max_retries: u32,
+    retry_backoff: Duration,
+    cancel_contract: Option<Arc<dyn CancelContract>>,
+}
+
+/// How an authorization is cancelled at Plexo. No implementation ships with
+/// the service: Plexo's cancel request is not documented here, and a guessed
+/// one would be signed and sent unverified. Embedders that have the contract
+/// install it with `PlexoClient::with_cancel_contract`.
+pub trait CancelContract: Send + Sync {
+    /// Path of the cancel operation, relative to the gateway's base URL
+    fn path(&self) -> &str;
+    /// The unsigned cancel request for `authorization`, sent on behalf of `client`
+    fn request(&self, client: &str, authorization: &StatusQuery) -> Value;
+}
+
+impl PlexoClient {
+    pub fn new(base_url: &str, http: Client, crypto: Arc<CryptoService>) -> Self {
+        Self::with_crypto_handle(base_url, http, Arc::new(ArcSwap::new(crypto)))
+    }
+
+    /// Sign through a shared slot, so certificate rotations apply to this client
+    pub fn with_crypto_handle(
+        base_url: &str,
+        http: Client,
+        crypto: Arc<ArcSwap<CryptoService>>,
+    ) -> Self {
+        Self {
+            base_url: base_url.trim().trim_end_matches('/').to_string(),
+            http,
+            crypto,
+            concurrency: None,
+            deadline: None,
+            commerce_info_ttl: Duration::from_secs(DEFAULT_COMMERCE_INFO_TTL_SECS),
+            commerce_info_cache: DashMap::new(),
+            max_retries: 0,
+            retry_backoff: Duration::from_millis(DEFAULT_RETRY_BACKOFF_MS),
+            cancel_contract: None,
+        }
+    }
+
+    /// Bound each operation as a whole, signing included, to `deadline`
+    pub fn with_deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Reuse commerce configuration for `ttl`; zero always asks Plexo
+    pub fn with_commerce_info_ttl(mut self, ttl: Duration) -> Self {
+        self.commerce_info_ttl = ttl;
+        self
+    }
+
+    /// Re-send calls that fail in a `retryable` way up to `max_retries` times,
+    /// waiting `backoff` times the attempt number before each one
+    pub fn with_retries(mut self, max_retries: u32, backoff: Duration) -> Self {
+        self.max_retries = max_retries;
+        self.retry_backoff = backoff;
+        self
+    }
+
+    /// Allow at most `limit` calls to Plexo at once
+    pub fn with_concurrency_limit(mut self, limit: usize) -> Self {
+        self.concurrency = Some(Arc::new(Semaphore::new(limit)));
+        self
+    }
+
+    /// Cancel authorizations left by declined purchases with `contract`, see
+    /// `purchase_or_void`
+    pub fn with_cancel_contract(mut self, contract: Arc<dyn CancelContract>) -> Self {
+        self.cancel_contract = Some(contract);
+        self
+    }
+
+    /// Configure from the environment as described on `init()`
+    pub fn from_env() -> Result<Self, PlexoServiceError> {
+        let base_url = std::env::var("PLEXO_BASE_URL")
+            .ok()
+            .filter(|url| !url.trim().is_empty())
+            .unwrap_or_else(|| DEFAULT_PLEXO_BASE_URL.to_string());
+
+        let mut client =
+            Self::with_crypto_handle(&base_url, build_http_client()?, crypto_handle()?);
+
+        if let Ok(value) = std::env::var("OPERATION_DEADLINE_MS") {
+            let deadline_ms = value.trim().parse::<u64>().map_err(|_| {
+                PlexoServiceError::ConfigurationError(
+                    "OPERATION_DEADLINE_MS must be a number".to_string(),
+                )
+            })?;
+            client = client.with_deadline(Duration::from_millis(deadline_ms));
+        }
+
+        if let Ok(value) = std::env::var("COMMERCE_INFO_TTL_SECS") {
+            let ttl_secs = value.trim().parse::<u64>().map_err(|_| {
+                PlexoServiceError::ConfigurationError(
+                    "COMMERCE_INFO_TTL_SECS must be a number".to_string(),
+                )
+            })?;
+            client = client.with_commerce_info_ttl(Duration::from_secs(ttl_secs));
+        }
+
+        if let Ok(value) = std::env::var("PLEXO_MAX_RETRIES") {
+            let max_retries = value.trim().parse::<u32>().map_err(|_| {
+                PlexoServiceError::ConfigurationError(
+                    "PLEXO_MAX_RETRIES must be a number".to_string(),
+                )
+            })?;
+            let backoff_ms = match std::env::var("PLEXO_RETRY_BACKOFF_MS") {
+                Ok(value) => value.trim().parse::<u64>().map_err(|_| {
+                    PlexoServiceError::ConfigurationError(
+                        "PLEXO_RETRY_BACKOFF_MS must be a number".to_string(),
+                    )
+                })?,
+                Err(_) => DEFAULT_RETRY_BACKOFF_MS,
+            };
+            client = client.with_retries(max_retries, Duration::from_millis(backoff_ms));
+        }
+
+        match std::env::var("MAX_CONCURRENT_PLEXO") {
+            Ok(value) => match value.trim().parse::<usize>() {
+                Ok(limit) if limit > 0 => Ok(client.with_concurrency_limit(limit)),
+                _ => Err(PlexoServiceError::ConfigurationError(
+                    "MAX_CONCURRENT_PLEXO must be a positive number".to_string(),
+                )),
+            },
+            Err(_) => Ok(client),
+        }
+    }
+
+    /// Clean and sign `request` exactly as it would be sent, with a fixed
+    /// `UTCUnixTimeExpiration`, without calling Plexo. For batches prepared
+    /// ahead of time and sent later.
+    pub fn presign<T: Serialize>(
+        &self,
+        request: &T,
+        expiration: i64,
+    ) -> Result<SignedRequest, PlexoServiceError> {
+        let mut request_value = json!(request);
+        clean_payload(&mut request_value)?;
+        Ok(self
+            .crypto
+            .load()
+            .create_signed_payload_expiring_at(&request_value, expiration)?)
+    }
+
+    pub async fn authorize(
+        &self,
+        mut auth_request: AuthorizationRequest,
+        options: RequestOptions,
+    ) -> Result<Value, PlexoServiceError> {
+        validation::validate_authorization_request(&auth_request)?;
+
+        let extending = auth_request.Request.ExtendableInstrumentToken.is_some();
+        if extending {
+            // Callers only need to send the token, the extend flag is implied by it
+            auth_request.Request.Action |= AuthorizationAction::EXTEND_INSTRUMENT;
+        }
 
-    info!("Sending payment request to Plexo");
+        let mut response = self
+            .send_signed_request(
+                PLEXO_AUTH_PATH,
+                json!(auth_request),
+                Operation::Authorize,
+                &options,
+            )
+            .await?;
 
-    // Send the request to Plexo
-    let client = Client::builder().timeout(Duration::from_secs(10)).build()?;
-    let response = client
-        .post(PLEXO_PURCHASE_URL)
-        .json(&signed_payload)
-        .send()
+        if extending {
+            let available = extended_credit_available(&response);
+            if let Some(object) = response.as_object_mut() {
+                object.insert("ExtendedCreditAvailable".to_string(), json!(available));
+            }
+        }
+
+        Ok(response)
+    }
+
+    pub async fn purchase(
+        &self,
+        payment_request: PaymentRequest,
+        options: RequestOptions,
+    ) -> Result<Value, PlexoServiceError> {
+        validation::validate_payment_request(&payment_request)?;
+
+        // Plexo rejects repeated references, so catch obvious duplicates before the round trip.
+        // The reference is reserved now so concurrent duplicates are caught too.
+        let reference = payment_request.Request.ClientReferenceId.clone();
+        let references = reference_cache::recent_references();
+        if let Some(references) = references {
+            if options.allow_duplicate {
+                references.force_insert(&reference);
+            } else if !references.insert(&reference) {
+                return Err(PlexoServiceError::DuplicateReference(reference));
+            }
+        }
+
+        let result = self
+            .send_signed_request(
+                PLEXO_PURCHASE_PATH,
+                json!(payment_request),
+                Operation::Purchase,
+                &options,
+            )
+            .await;
+
+        // A purchase Plexo never judged is no duplicate, so its retry must go through.
+        // After a timeout Plexo's own reference check still stops a real repeat.
+        if let (Some(references), Err(e)) = (references, &result) {
+            if !plexo_answered(e) {
+                references.remove(&reference);
+            }
+        }
+        result
+    }
+
+    pub async fn status(
+        &self,
+        status_request: StatusRequest,
+        options: RequestOptions,
+    ) -> Result<Value, PlexoServiceError> {
+        // Parsed requests are checked already; this covers queries built in code
+        let query = &status_request.request;
+        validation::validate_status_query(query, "Request.MetaReference")?;
+        let cache_key = format!(
+            "{}:{}:{}",
+            status_request.client,
+            query.reference_type(),
+            query.reference()
+        );
+
+        let ttl = status_cache::ttl();
+        if ttl.is_some() && !options.no_cache {
+            if let Some(cached) = status_cache::cache().get(&cache_key).await {
+                debug!("Answering status lookup from cache");
+                metrics::record_outcome(Operation::Status.as_str(), "cached");
+                return Ok(cached);
+            }
+        }
+
+        let response = self
+            .send_signed_request(
+                PLEXO_STATUS_PATH,
+                json!(status_request),
+                Operation::Status,
+                &options,
+            )
+            .await?;
+
+        if let Some(ttl) = ttl.filter(|_| status_cache::cacheable(&response)) {
+            status_cache::cache()
+                .put(&cache_key, response.clone(), ttl)
+                .await;
+        }
+        Ok(response)
+    }
+
+    /// Send `payment_request` and, when Plexo declines it, cancel
+    /// `authorization` so it is not left open. Callers opt in by using this
+    /// instead of `purchase`. Only an explicit decline is compensated: after
+    /// any other failure the purchase may have been charged, or can be fixed
+    /// and sent again, so the authorization is kept. Without a
+    /// `CancelContract` nothing can be cancelled and it is always kept.
+    pub async fn purchase_or_void(
+        &self,
+        payment_request: PaymentRequest,
+        authorization: StatusQuery,
+        options: RequestOptions,
+    ) -> CompensatedPurchase {
+        let client = payment_request.Client.clone();
+        let purchase = self.purchase(payment_request, options.clone()).await;
+
+        let outcome = match &purchase {
+            Ok(response) => Outcome::from_plexo(response),
+            Err(e) => Outcome::from_error(e),
+        };
+        let authorization = match (&purchase, outcome, &self.cancel_contract) {
+            (_, Outcome::Declined, Some(contract)) => {
+                warn!("Purchase declined, voiding its authorization");
+                let result = self
+                    .send_signed_request(
+                        contract.path(),
+                        contract.request(&client, &authorization),
+                        Operation::Cancel,
+                        &options,
+                    )
+                    .await;
+                if let Err(e) = &result {
+                    warn!("Could not void authorization: {}", e);
+                }
+                AuthorizationVoidResult::Attempted(result)
+            }
+            (_, Outcome::Declined, None) => {
+                warn!("Purchase declined, but no cancel contract is configured to void its authorization");
+                AuthorizationVoidResult::LeftOpen
+            }
+            (Err(_), _, _) => {
+                warn!("Purchase failed without a decline, its authorization is left open");
+                AuthorizationVoidResult::LeftOpen
+            }
+            (Ok(_), _, _) => AuthorizationVoidResult::NotNeeded,
+        };
+        CompensatedPurchase {
+            purchase,
+            authorization,
+        }
+    }
+
+    pub async fn register_instrument(
+        &self,
+        register_request: RegisterInstrumentRequest,
+        options: RequestOptions,
+    ) -> Result<RegisterInstrumentResponse, PlexoServiceError> {
+        validation::validate_register_instrument_request(&register_request)?;
+
+        let response = self
+            .send_signed_request(
+                PLEXO_INSTRUMENT_CREATE_PATH,
+                json!(register_request),
+                Operation::RegisterInstrument,
+                &options,
+            )
+            .await?;
+
+        let parsed = PlexoResponse::<RegisteredInstrument>::from_value(&response)?;
+        let instrument = parsed.into_result()?;
+
+        Ok(RegisterInstrumentResponse {
+            instrument_token: instrument.InstrumentToken,
+            issuer: instrument.Issuer,
+            raw: response,
+        })
+    }
+
+    /// Commerce configuration, from the cache while it is fresh
+    pub async fn commerce_info(
+        &self,
+        commerce_request: CommerceInfoRequest,
+        options: RequestOptions,
+    ) -> Result<CommerceInfo, PlexoServiceError> {
+        let key = (
+            commerce_request.Client.clone(),
+            commerce_request.Request.CommerceId,
+        );
+        let now = Instant::now();
+        self.commerce_info_cache
+            .remove_if(&key, |_, (_, expires_at)| *expires_at <= now);
+        if let Some(entry) = self.commerce_info_cache.get(&key) {
+            debug!("Commerce {} configuration served from cache", key.1);
+            return Ok(entry.value().0.clone());
+        }
+
+        let response = self
+            .send_signed_request(
+                PLEXO_COMMERCE_CONFIG_PATH,
+                json!(commerce_request),
+                Operation::CommerceInfo,
+                &options,
+            )
+            .await?;
+
+        let parsed = PlexoResponse::<CommerceConfig>::from_value(&response)?;
+        let config = parsed.into_result()?;
+        let info = CommerceInfo {
+            commerce_id: config.CommerceId,
+            name: config.Name,
+            active: config.IsActive,
+            currencies: config.Currencies,
+            issuers: config.Issuers,
+            raw: response,
+        };
+
+        if !self.commerce_info_ttl.is_zero() {
+            // Sweep expired entries on write so the map can't grow without bound
+            self.commerce_info_cache
+                .retain(|_, (_, expires_at)| *expires_at > now);
+            self.commerce_info_cache
+                .insert(key, (info.clone(), now + self.commerce_info_ttl));
+        }
+
+        Ok(info)
+    }
+
+    /// One page of every transaction tied to a client reference. Paging is
+    /// passed through to Plexo as given.
+    pub async fn transaction_history(
+        &self,
+        history_request: TransactionHistoryRequest,
+        options: RequestOptions,
+    ) -> Result<TransactionHistory, PlexoServiceError> {
+        validation::validate_transaction_history_request(&history_request)?;
+
+        let client_reference_id = history_request.Request.ClientReferenceId.clone();
+        let response = self
+            .send_signed_request(
+                PLEXO_TRANSACTION_HISTORY_PATH,
+                json!(history_request),
+                Operation::TransactionHistory,
+                &options,
+            )
+            .await?;
+
+        let parsed = PlexoResponse::<TransactionPage>::from_value(&response)?;
+        let page = parsed.into_result()?;
+        Ok(TransactionHistory {
+            client_reference_id,
+            transactions: page.Transactions.into_iter().map(Into::into).collect(),
+            page_number: page.PageNumber,
+            page_size: page.PageSize,
+            total_count: page.TotalCount,
+            raw: response,
+        })
+    }
+
+    pub async fn delete_instrument(
+        &self,
+        delete_request: DeleteInstrumentRequest,
+        options: RequestOptions,
+    ) -> Result<DeleteInstrumentResponse, PlexoServiceError> {
+        let instrument_token = delete_request.Request.InstrumentToken.clone();
+        let response = self
+            .send_signed_request(
+                PLEXO_INSTRUMENT_DELETE_PATH,
+                json!(delete_request),
+                Operation::DeleteInstrument,
+                &options,
+            )
+            .await?;
+
+        let parsed = PlexoResponse::<Value>::from_value(&response)?;
+        match parsed.ensure_ok() {
+            Ok(()) => Ok(DeleteInstrumentResponse {
+                instrument_token,
+                deleted: true,
+                raw: response,
+            }),
+            Err(PlexoServiceError::PlexoResultError { code, .. })
+                if code == PLEXO_RESULT_INSTRUMENT_NOT_FOUND =>
+            {
+                Err(PlexoServiceError::InstrumentNotFound)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    // Clean, sign and send a request to Plexo, returning the raw JSON response
+    async fn send_signed_request(
+        &self,
+        path: &str,
+        request_value: Value,
+        operation: Operation,
+        options: &RequestOptions,
+    ) -> Result<Value, PlexoServiceError> {
+        if let Err(e) = admission::admit(operation, in_flight_requests()) {
+            metrics::record_outcome(operation.as_str(), "shed");
+            return Err(e);
+        }
+
+        // Held until the call finishes so the slot is freed on every path
+        let _permit = match self.acquire_slot().await {
+            Ok(permit) => permit,
+            Err(e) => {
+                metrics::record_outcome(operation.as_str(), "overloaded");
+                return Err(e);
+            }
+        };
+
+        let guard = InFlightGuard::new();
+        let call = self.send_with_retries(path, request_value, operation, options);
+        let result = match self.deadline {
+            Some(deadline) => tokio::time::timeout(deadline, call)
+                .await
+                .unwrap_or(Err(PlexoServiceError::Timeout)),
+            None => call.await,
+        };
+        guard.finish();
+
+        metrics::record_outcome(operation.as_str(), outcome_label(&result));
+        result
+    }
+
+    // Retries stay inside the operation deadline and re-sign, so each attempt
+    // carries a fresh expiration
+    async fn send_with_retries(
+        &self,
+        path: &str,
+        request_value: Value,
+        operation: Operation,
+        options: &RequestOptions,
+    ) -> Result<Value, PlexoServiceError> {
+        let mut attempt = 0;
+        loop {
+            let result = self
+                .sign_and_send(path, request_value.clone(), operation, options)
+                .await;
+            match result {
+                Err(e) if attempt < self.max_retries && retryable(operation, &e) => {
+                    attempt += 1;
+                    warn!(
+                        "Plexo {} failed ({}), retry {} of {}",
+                        operation.as_str(),
+                        e,
+                        attempt,
+                        self.max_retries
+                    );
+                    tokio::time::sleep(self.retry_backoff * attempt).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn acquire_slot(&self) -> Result<Option<OwnedSemaphorePermit>, PlexoServiceError> {
+        let Some(semaphore) = &self.concurrency else {
+            return Ok(None);
+        };
+
+        match tokio::time::timeout(
+            Duration::from_millis(CONCURRENCY_WAIT_MS),
+            semaphore.clone().acquire_owned(),
+        )
         .await
-        .map_err(|e| {
-            if e.is_timeout() {
-                PlexoServiceError::Timeout
-            } else {
-                PlexoServiceError::HttpRequestError(e)
+        {
+            Ok(Ok(permit)) => Ok(Some(permit)),
+            _ => Err(PlexoServiceError::Overloaded),
+        }
+    }
+
+    async fn sign_and_send(
+        &self,
+        path: &str,
+        request_value: Value,
+        operation: Operation,
+        options: &RequestOptions,
+    ) -> Result<Value, PlexoServiceError> {
+        let operation = operation.as_str();
+
+        // Signing may block on an HSM, so it runs off the async workers where
+        // the operation deadline can still fire while it is in progress
+        let crypto = self.crypto.load_full();
+        let signed_payload =
+            tokio::task::spawn_blocking(move || prepare_signed_payload(request_value, &crypto))
+                .await
+                .map_err(|e| {
+                    PlexoServiceError::ConfigurationError(format!("Signing task failed: {}", e))
+                })??;
+        debug!(
+            "{} request: {}",
+            operation,
+            redaction::redact_value(&signed_payload.Object.Object)
+        );
+        if let Some(recent) = recent_requests::recent_requests() {
+            recent.record(
+                options.correlation_id.as_deref(),
+                operation,
+                &signed_payload,
+            );
+        }
+
+        info!("Sending {} request to Plexo", operation);
+
+        // Send the request to Plexo
+        let url = format!("{}{}", self.base_url, path);
+        let mut request = self.http.post(url).json(&signed_payload);
+        if let Some(timeout) = options.timeout {
+            request = request.timeout(timeout);
+        }
+
+        let started = Instant::now();
+        let response = request.send().await;
+        let elapsed = started.elapsed();
+        metrics::observe_plexo_duration(operation, elapsed.as_secs_f64());
+        admission::record_latency(elapsed);
+
+        let response = response.map_err(classify_request_error)?;
+
+        info!(
+            "Plexo {} responded with HTTP {} in {} ms",
+            operation,
+            response.status().as_u16(),
+            elapsed.as_millis()
+        );
+
+        // Plexo's Date header is the reference our expirations must agree with
+        if let Some(date) = response
+            .headers()
+            .get(reqwest::header::DATE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| chrono::DateTime::parse_from_rfc2822(value).ok())
+        {
+            self.crypto
+                .load()
+                .observe_reference_time(date.with_timezone(&chrono::Utc));
+        }
+
+        // Non-2xx answers carry the status in the error instead of failing to parse
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            if maintenance_signal().matches(&body) {
+                warn!(
+                    "Plexo {} answered HTTP {} for maintenance",
+                    operation, status
+                );
+                return Err(maintenance_signal().error());
+            }
+            return Err(PlexoServiceError::UpstreamStatus {
+                status: status.as_u16(),
+            });
+        }
+        // Proxies in front of Plexo can answer 200 with an empty body or an HTML page
+        let body = response.text().await.map_err(classify_request_error)?;
+        let parsed_response = serde_json::from_str::<Value>(&body).map_err(|_| {
+            PlexoServiceError::UnexpectedResponse {
+                status: status.as_u16(),
+                snippet: body_snippet(&body),
             }
         })?;
 
-    let parsed_response = response.json::<Value>().await?;
+        debug!(
+            "{} response: {}",
+            operation,
+            redaction::redact_value(&parsed_response)
+        );
+
+        if in_maintenance(&parsed_response) {
+            warn!("Plexo {} is in a maintenance window", operation);
+            return Err(maintenance_signal().error());
+        }
+
+        Ok(parsed_response)
+    }
+}
+
+// Plexo can also announce maintenance as a failed result with HTTP 200
+fn in_maintenance(response: &Value) -> bool {
+    let failed = response
+        .get("ResultCode")
+        .and_then(Value::as_i64)
+        .is_some_and(|code| code != 0);
+    failed
+        && response
+            .get("ErrorMessage")
+            .and_then(Value::as_str)
+            .is_some_and(|message| maintenance_signal().matches(message))
+}
 
-    info!("Received payment response from Plexo");
+// Plexo reports whether the extended credit line can be used on the session it returns
+fn extended_credit_available(response: &Value) -> bool {
+    response
+        .pointer("/Response/ExtendedCreditAvailable")
+        .and_then(Value::as_bool)
+        .unwrap_or(false)
+}
 
-    println!("payment request response: {:#?}", parsed_response);
+pub async fn send_authorization_request(
+    auth_request: AuthorizationRequest,
+    options: RequestOptions,
+) -> Result<Value, PlexoServiceError> {
+    default_client()?.authorize(auth_request, options).await
+}
 
-    Ok(parsed_response)
+pub async fn send_payment_request(
+    payment_request: PaymentRequest,
+    options: RequestOptions,
+) -> Result<Value, PlexoServiceError> {
+    default_client()?.purchase(payment_request, options).await
 }
 
 pub async fn send_status_request(
     status_request: StatusRequest,
+    options: RequestOptions,
 ) -> Result<Value, PlexoServiceError> {
-    // Convert request to Value and remove null values before signing
-    let mut request_value = json!(status_request);
-    clean_nulls(&mut request_value);
-    println!("status request: {:#?}", request_value);
+    default_client()?.status(status_request, options).await
+}
 
-    // Sign the payload
-    let crypto_service = get_crypto_service()?;
-    let signed_payload = crypto_service.create_signed_payload(&request_value)?;
+pub async fn send_register_instrument_request(
+    register_request: RegisterInstrumentRequest,
+    options: RequestOptions,
+) -> Result<RegisterInstrumentResponse, PlexoServiceError> {
+    default_client()?
+        .register_instrument(register_request, options)
+        .await
+}
 
-    info!("Sending payment request to Plexo");
+pub async fn send_commerce_info_request(
+    commerce_request: CommerceInfoRequest,
+    options: RequestOptions,
+) -> Result<CommerceInfo, PlexoServiceError> {
+    default_client()?
+        .commerce_info(commerce_request, options)
+        .await
+}
+
+pub async fn send_transaction_history_request(
+    history_request: TransactionHistoryRequest,
+    options: RequestOptions,
+) -> Result<TransactionHistory, PlexoServiceError> {
+    default_client()?
+        .transaction_history(history_request, options)
+        .await
+}
 
-    // Send the request to Plexo
-    let client = Client::builder().timeout(Duration::from_secs(10)).build()?;
-    let response = client
-        .post(PLEXO_STATUS_URL)
-        .json(&signed_payload)
-        .send()
+pub async fn send_delete_instrument_request(
+    delete_request: DeleteInstrumentRequest,
+    options: RequestOptions,
+) -> Result<DeleteInstrumentResponse, PlexoServiceError> {
+    default_client()?
+        .delete_instrument(delete_request, options)
         .await
-        .map_err(|e| {
-            if e.is_timeout() {
-                PlexoServiceError::Timeout
-            } else {
-                PlexoServiceError::HttpRequestError(e)
+}
+
+/// Clean and sign a request exactly as it would be sent, without calling Plexo
+pub fn build_signed_request<T: Serialize>(request: &T) -> Result<SignedRequest, PlexoServiceError> {
+    prepare_signed_payload(json!(request), &*get_crypto_service()?)
+}
+
+/// Clean and canonicalize a payload without signing it, listing every field
+/// the cleaning step dropped or reformatted
+pub fn canonical_preview(request_value: &Value) -> Result<CanonicalPreview, PlexoServiceError> {
+    let mut cleaned = request_value.clone();
+    clean_payload(&mut cleaned)?;
+
+    let mut modified_fields = Vec::new();
+    collect_modifications(request_value, &cleaned, String::new(), &mut modified_fields);
+
+    Ok(CanonicalPreview {
+        canonical: canonize_json(&cleaned)?,
+        canonical_unsorted: canonize_json_with(&cleaned, CanonOptions { sort_keys: false })?,
+        modified_fields,
+    })
+}
+
+// Walk the original and cleaned payloads side by side; cleaning only ever
+// drops nulls or rewrites amounts and the invoice number, so those are the
+// only differences
+fn collect_modifications(
+    original: &Value,
+    cleaned: &Value,
+    path: String,
+    modified: &mut Vec<ModifiedField>,
+) {
+    match (original, cleaned) {
+        (Value::Object(original_map), Value::Object(cleaned_map)) => {
+            for (key, original_value) in original_map {
+                let field_path = format!("{}/{}", path, key.replace('~', "~0").replace('/', "~1"));
+                match cleaned_map.get(key) {
+                    Some(cleaned_value) => {
+                        collect_modifications(original_value, cleaned_value, field_path, modified)
+                    }
+                    None => modified.push(ModifiedField {
+                        path: field_path,
+                        change: "null_removed".to_string(),
+                        original: original_value.clone(),
+                        canonical: None,
+                    }),
+                }
             }
-        })?;
+        }
+        (Value::Array(original_items), Value::Array(cleaned_items)) => {
+            let mut remaining = cleaned_items.iter();
+            for (index, original_item) in original_items.iter().enumerate() {
+                let item_path = format!("{}/{}", path, index);
+                if original_item.is_null() {
+                    modified.push(ModifiedField {
+                        path: item_path,
+                        change: "null_removed".to_string(),
+                        original: Value::Null,
+                        canonical: None,
+                    });
+                } else if let Some(cleaned_item) = remaining.next() {
+                    collect_modifications(original_item, cleaned_item, item_path, modified);
+                }
+            }
+        }
+        (Value::String(_), Value::String(_)) if original != cleaned => {
+            modified.push(ModifiedField {
+                path,
+                change: "unicode_normalized".to_string(),
+                original: original.clone(),
+                canonical: Some(cleaned.clone()),
+            })
+        }
+        _ if original != cleaned => modified.push(ModifiedField {
+            path,
+            change: "number_reformatted".to_string(),
+            original: original.clone(),
+            canonical: Some(cleaned.clone()),
+        }),
+        _ => {}
+    }
+}
+
+// Amounts are scaled to the currency's minor units when the request has one
+fn request_currency_id(request_value: &Value) -> Option<i32> {
+    request_value
+        .pointer("/Request/CurrencyId")
+        .and_then(Value::as_i64)
+        .map(|id| id as i32)
+}
 
-    let parsed_response = response.json::<Value>().await?;
+fn prepare_signed_payload(
+    mut request_value: Value,
+    crypto_service: &CryptoService,
+) -> Result<SignedRequest, PlexoServiceError> {
+    // Remove null values and fix number formats before signing
+    clean_payload(&mut request_value)?;
 
-    info!("Received status response from Plexo");
+    // Sign the payload
+    Ok(crypto_service.create_signed_payload(&request_value)?)
+}
+
+// First characters of an unparseable body, enough to recognize a proxy page
+fn body_snippet(body: &str) -> String {
+    const MAX_SNIPPET_CHARS: usize = 200;
+
+    let body = body.trim();
+    match body.char_indices().nth(MAX_SNIPPET_CHARS) {
+        Some((end, _)) => format!("{}...", &body[..end]),
+        None => body.to_string(),
+    }
+}
 
-    println!("status request response: {:#?}", parsed_response);
+// Split transport failures by cause; they mean very different things operationally
+fn classify_request_error(e: reqwest::Error) -> PlexoServiceError {
+    if e.is_timeout() {
+        PlexoServiceError::Timeout
+    } else if e.is_connect() {
+        PlexoServiceError::ConnectionError(e)
+    } else if let Some(status) = e.status() {
+        PlexoServiceError::UpstreamStatus {
+            status: status.as_u16(),
+        }
+    } else {
+        PlexoServiceError::HttpRequestError(e)
+    }
+}
 
-    Ok(parsed_response)
+// Metric label describing how a Plexo call ended
+fn outcome_label(result: &Result<Value, PlexoServiceError>) -> &'static str {
+    match result {
+        Ok(_) => "success",
+        Err(PlexoServiceError::Timeout) => "timeout",
+        Err(PlexoServiceError::SigningError(_)) => "signing_error",
+        Err(PlexoServiceError::ConnectionError(_)) => "connect_error",
+        Err(PlexoServiceError::UpstreamStatus { .. }) => "upstream_status",
+        Err(PlexoServiceError::HttpRequestError(_)) => "http_error",
+        Err(PlexoServiceError::SerializationError(_))
+        | Err(PlexoServiceError::UnexpectedResponse { .. }) => "invalid_response",
+        Err(PlexoServiceError::Maintenance { .. }) => "maintenance",
+        Err(_) => "error",
+    }
 }
 
 // Helper function to recursively remove null values from a JSON Value
 // and properly format LosslessNumber fields
-fn clean_nulls(value: &mut Value) {
+// Everything sent to Plexo goes through here, so the signed canonical form
+// always matches what is on the wire
+fn clean_payload(request_value: &mut Value) -> Result<(), ValidationErrors> {
+    let decimal_places = request_currency_id(request_value)
+        .map(decimal_places_for_currency)
+        .unwrap_or(DEFAULT_DECIMAL_PLACES);
+    let mut errors = Vec::new();
+    clean_nulls(request_value, decimal_places, "", &mut errors);
+    if !errors.is_empty() {
+        return Err(ValidationErrors(errors));
+    }
+    format_invoice_number(request_value);
+    if NORMALIZE_UNICODE.get().copied().unwrap_or(true) {
+        normalize_free_text(request_value, false);
+    }
+    Ok(())
+}
+
+// A composed "é" and an "e" followed by a combining accent look the same but
+// canonicalize to different bytes, so Plexo may verify a form we didn't sign
+fn normalize_free_text(value: &mut Value, in_free_text: bool) {
+    match value {
+        Value::String(text) if in_free_text && is_nfc_quick(text.chars()) != IsNormalized::Yes => {
+            *text = text.nfc().collect();
+        }
+        Value::Object(map) => {
+            for (key, field) in map.iter_mut() {
+                normalize_free_text(
+                    field,
+                    in_free_text || FREE_TEXT_KEYS.contains(&key.as_str()),
+                );
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                normalize_free_text(item, in_free_text);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn format_invoice_number(request_value: &mut Value) {
+    let format = INVOICE_NUMBER_FORMAT.get().copied().unwrap_or_default();
+    if let Some(invoice_number) =
+        request_value.pointer_mut("/Request/FinancialInclusion/InvoiceNumber")
+    {
+        if let Some(number) = invoice_number.as_i64() {
+            *invoice_number = format.format(number);
+        }
+    }
+}
+
+fn clean_nulls(
+    value: &mut Value,
+    decimal_places: u32,
+    path: &str,
+    errors: &mut Vec<ValidationError>,
+) {
     match value {
         Value::Object(map) => {
             // Collect keys to remove (can't modify while iterating)
@@ -161,19 +1589,14 @@ fn clean_nulls(value: &mut Value) {
 
             // Recursively process remaining values and handle special formatting
             for (key, v) in map.iter_mut() {
+                let field = child_path(path, key);
                 // Check if this field should be treated as a LosslessNumber
                 if is_lossless_number_field(key) {
-                    if let Value::String(s) = v {
-                        // Convert string to properly formatted number
-                        let lossless = LosslessNumber::new(s.clone());
-                        let formatted = lossless.format_for_json();
-                        // Try to parse as number for JSON
-                        if let Ok(num) = formatted.parse::<f64>() {
-                            *v = json!(num);
-                        }
+                    if let Err(e) = format_amount(v, decimal_places, &field) {
+                        errors.push(e);
                     }
                 }
-                clean_nulls(v);
+                clean_nulls(v, decimal_places, &field, errors);
             }
         }
         Value::Array(arr) => {
@@ -181,14 +1604,59 @@ fn clean_nulls(value: &mut Value) {
             arr.retain(|item| !item.is_null());
 
             // Recursively process remaining items
-            for item in arr.iter_mut() {
-                clean_nulls(item);
+            for (index, item) in arr.iter_mut().enumerate() {
+                clean_nulls(
+                    item,
+                    decimal_places,
+                    &child_path(path, &index.to_string()),
+                    errors,
+                );
             }
         }
         _ => {} // Nothing to do for primitive values
     }
 }
 
+fn child_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+// Amounts arrive as strings from typed requests, but bare JSON numbers get the
+// same treatment so 10 is sent as 10.00. The result is an exact JSON number at
+// the currency's scale; an amount that would need rounding is refused.
+fn format_amount(
+    value: &mut Value,
+    decimal_places: u32,
+    field: &str,
+) -> Result<(), ValidationError> {
+    let raw = match value {
+        Value::String(s) => s.clone(),
+        Value::Number(n) => n.to_string(),
+        _ => return Ok(()),
+    };
+    let formatted = LosslessNumber::new(raw)
+        .format_with_decimals(decimal_places)
+        .map_err(|e| amount_error(field, e))?;
+    // With arbitrary_precision the number keeps these exact digits on the wire
+    let number = formatted
+        .parse::<serde_json::Number>()
+        .map_err(|_| amount_error(field, AmountFormatError::Invalid(formatted.clone())))?;
+    *value = Value::Number(number);
+    Ok(())
+}
+
+fn amount_error(field: &str, error: AmountFormatError) -> ValidationError {
+    let code = match error {
+        AmountFormatError::Invalid(_) => "invalid_format",
+        AmountFormatError::TooManyDecimals { .. } => "too_many_decimals",
+    };
+    ValidationError::new(field, code, error.to_string())
+}
+
 // Helper function to determine if a field should be treated as a LosslessNumber
 fn is_lossless_number_field(field_name: &str) -> bool {
     matches!(
@@ -0,0 +1,87 @@
+use lazy_static::lazy_static;
+use log::info;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+const ALLOWLIST_PREFIX: &str = "RESPONSE_FIELDS_";
+
+lazy_static! {
+    // RESPONSE_FIELDS_<OPERATION> (e.g. RESPONSE_FIELDS_PURCHASE) lists the
+    // dotted paths returned for that operation; unset returns everything
+    static ref ALLOWLISTS: HashMap<String, FieldTree> = std::env::vars()
+        .filter_map(|(name, fields)| {
+            let operation = name.strip_prefix(ALLOWLIST_PREFIX)?.to_ascii_lowercase();
+            info!("Responses for {} are limited to: {}", operation, fields);
+            Some((operation, FieldTree::parse(&fields)))
+        })
+        .collect();
+}
+
+/// Allowed paths as a tree. A leaf keeps its whole subtree; arrays are
+/// transparent, so `Response.Items.Id` keeps `Id` in every item.
+#[derive(Debug, Default)]
+pub struct FieldTree {
+    keep_all: bool,
+    children: HashMap<String, FieldTree>,
+}
+
+impl FieldTree {
+    /// Parse a comma-separated list of dotted paths, e.g. `ResultCode,Response.Id`
+    pub fn parse(fields: &str) -> Self {
+        let mut tree = Self::default();
+        for path in fields
+            .split(',')
+            .map(str::trim)
+            .filter(|path| !path.is_empty())
+        {
+            let node = path.split('.').fold(&mut tree, |node, key| {
+                node.children.entry(key.to_string()).or_default()
+            });
+            node.keep_all = true;
+        }
+        tree
+    }
+
+    /// Copy of `value` with only the allowed fields; None when nothing is allowed
+    pub fn project(&self, value: &Value) -> Option<Value> {
+        if self.keep_all {
+            return Some(value.clone());
+        }
+
+        match value {
+            Value::Object(map) => {
+                let projected: Map<String, Value> = map
+                    .iter()
+                    .filter_map(|(key, value)| {
+                        let child = self.children.get(key)?;
+                        Some((key.clone(), child.project(value)?))
+                    })
+                    .collect();
+                Some(Value::Object(projected))
+            }
+            Value::Array(items) => Some(Value::Array(
+                items.iter().filter_map(|item| self.project(item)).collect(),
+            )),
+            // A path that goes deeper than the data does not allow the scalar itself
+            _ => None,
+        }
+    }
+}
+
+/// Apply the allowlist configured for `operation`, if any. `always` names
+/// top-level fields kept regardless, e.g. the result code callers rely on.
+pub fn project(operation: &str, value: Value, always: &[&str]) -> Value {
+    let Some(tree) = ALLOWLISTS.get(operation) else {
+        return value;
+    };
+
+    let mut projected = tree.project(&value).unwrap_or(Value::Null);
+    if let (Value::Object(projected), Value::Object(original)) = (&mut projected, &value) {
+        for key in always {
+            if let Some(field) = original.get(*key) {
+                projected.insert(key.to_string(), field.clone());
+            }
+        }
+    }
+    projected
+}
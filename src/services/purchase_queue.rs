@@ -0,0 +1,181 @@
+use crate::models::requests::PaymentRequest;
+use crate::services::plexo_service::RequestOptions;
+use async_trait::async_trait;
+use dashmap::DashMap;
+use lazy_static::lazy_static;
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::sync::{mpsc, Mutex};
+use utoipa::ToSchema;
+
+const DEFAULT_CAPACITY: usize = 1_000;
+const DEFAULT_WORKERS: usize = 4;
+const DEFAULT_JOB_TTL_SECS: u64 = 60 * 60;
+
+lazy_static! {
+    // Purchases waiting for a worker, from ASYNC_PURCHASE_QUEUE_CAPACITY
+    static ref CAPACITY: usize = env_number("ASYNC_PURCHASE_QUEUE_CAPACITY", DEFAULT_CAPACITY as u64) as usize;
+    // Purchases sent to Plexo at once, from ASYNC_PURCHASE_WORKERS
+    static ref WORKERS: usize = env_number("ASYNC_PURCHASE_WORKERS", DEFAULT_WORKERS as u64) as usize;
+    // How long a finished job can still be polled, from ASYNC_JOB_TTL_SECS
+    static ref JOB_TTL: Duration =
+        Duration::from_secs(env_number("ASYNC_JOB_TTL_SECS", DEFAULT_JOB_TTL_SECS));
+}
+
+static PURCHASE_QUEUE: OnceLock<Arc<dyn PurchaseQueue>> = OnceLock::new();
+
+fn env_number(name: &str, default: u64) -> u64 {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .filter(|value| *value > 0)
+        .unwrap_or(default)
+}
+
+/// A purchase accepted by `POST /api/purchase/async`, waiting to be sent
+#[derive(Debug)]
+pub struct PurchaseJob {
+    pub id: String,
+    pub correlation_id: String,
+    pub request: PaymentRequest,
+    pub options: RequestOptions,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    Queued,
+    Processing,
+    /// Plexo answered or the call failed for good; see `http_status` and `response`
+    Completed,
+}
+
+/// What polling a job returns
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct JobStatus {
+    pub job_id: String,
+    pub state: JobState,
+    pub correlation_id: String,
+    /// Status `POST /api/purchase` would have answered, once completed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub http_status: Option<u16>,
+    /// Body `POST /api/purchase` would have returned, once completed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub response: Option<Value>,
+}
+
+impl JobStatus {
+    pub fn queued(job: &PurchaseJob) -> Self {
+        Self {
+            job_id: job.id.clone(),
+            state: JobState::Queued,
+            correlation_id: job.correlation_id.clone(),
+            http_status: None,
+            response: None,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum QueueError {
+    #[error("The purchase queue is full")]
+    Full,
+    #[error("Purchase queue error: {0}")]
+    Backend(String),
+}
+
+/// Where asynchronous purchases wait and where their progress is kept. The
+/// default is an in-memory bounded channel; implement this over a durable
+/// queue to keep accepted purchases across restarts.
+#[async_trait]
+pub trait PurchaseQueue: Send + Sync {
+    /// Accept a job as queued, failing when there is no room for it
+    async fn enqueue(&self, job: PurchaseJob) -> Result<(), QueueError>;
+    /// Wait for the next job; None once the queue is closed
+    async fn next(&self) -> Option<PurchaseJob>;
+    async fn update(&self, status: JobStatus);
+    async fn status(&self, job_id: &str) -> Option<JobStatus>;
+}
+
+/// Process-local queue, lost on restart along with its jobs
+pub struct InMemoryPurchaseQueue {
+    sender: mpsc::Sender<PurchaseJob>,
+    receiver: Mutex<mpsc::Receiver<PurchaseJob>>,
+    // Each status with when it last changed
+    statuses: DashMap<String, (JobStatus, Instant)>,
+    ttl: Duration,
+}
+
+impl InMemoryPurchaseQueue {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        let (sender, receiver) = mpsc::channel(capacity.max(1));
+        Self {
+            sender,
+            receiver: Mutex::new(receiver),
+            statuses: DashMap::new(),
+            ttl,
+        }
+    }
+}
+
+#[async_trait]
+impl PurchaseQueue for InMemoryPurchaseQueue {
+    async fn enqueue(&self, job: PurchaseJob) -> Result<(), QueueError> {
+        let now = Instant::now();
+        // Sweep finished jobs on write so the map can't grow without bound
+        self.statuses.retain(|_, (status, updated_at)| {
+            status.state != JobState::Completed || now.duration_since(*updated_at) < self.ttl
+        });
+
+        // Recorded first, so a worker's update can't be overwritten by this one
+        let id = job.id.clone();
+        self.statuses
+            .insert(id.clone(), (JobStatus::queued(&job), now));
+        self.sender.try_send(job).map_err(|e| {
+            self.statuses.remove(&id);
+            match e {
+                mpsc::error::TrySendError::Full(_) => QueueError::Full,
+                mpsc::error::TrySendError::Closed(_) => {
+                    QueueError::Backend("queue closed".to_string())
+                }
+            }
+        })
+    }
+
+    async fn next(&self) -> Option<PurchaseJob> {
+        self.receiver.lock().await.recv().await
+    }
+
+    async fn update(&self, status: JobStatus) {
+        self.statuses
+            .insert(status.job_id.clone(), (status, Instant::now()));
+    }
+
+    async fn status(&self, job_id: &str) -> Option<JobStatus> {
+        self.statuses
+            .get(job_id)
+            .map(|entry| entry.value().0.clone())
+    }
+}
+
+/// Install a custom queue. Must be called before the workers start; returns
+/// false when a queue is already in place.
+pub fn set_queue(queue: Arc<dyn PurchaseQueue>) -> bool {
+    PURCHASE_QUEUE.set(queue).is_ok()
+}
+
+/// The configured queue, falling back to the in-memory one
+pub fn queue() -> Arc<dyn PurchaseQueue> {
+    PURCHASE_QUEUE
+        .get_or_init(|| Arc::new(InMemoryPurchaseQueue::new(*CAPACITY, *JOB_TTL)))
+        .clone()
+}
+
+/// Number of workers draining the queue
+pub fn workers() -> usize {
+    *WORKERS
+}
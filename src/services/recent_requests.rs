@@ -0,0 +1,80 @@
+use crate::models::responses::SignedRequest;
+use crate::services::crypto::canonize_json;
+use crate::services::redaction;
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use utoipa::ToSchema;
+
+lazy_static! {
+    // Enabled by setting RECENT_REQUESTS_CAPACITY to the number of payloads to keep
+    static ref RECENT_REQUESTS: Option<RecentRequests> = std::env::var("RECENT_REQUESTS_CAPACITY")
+        .ok()
+        .and_then(|value| value.trim().parse::<usize>().ok())
+        .filter(|capacity| *capacity > 0)
+        .map(RecentRequests::new);
+}
+
+/// A payload as it was signed for Plexo, with sensitive fields redacted
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct RecentRequest {
+    pub correlation_id: Option<String>,
+    pub operation: String,
+    pub signed_at: DateTime<Utc>,
+    /// Canonical string of the signed object after redaction, so it matches
+    /// what was signed everywhere except the masked fields
+    pub canonical: String,
+}
+
+/// Ring buffer of the last signed payloads, oldest evicted first
+pub struct RecentRequests {
+    capacity: usize,
+    entries: Mutex<VecDeque<RecentRequest>>,
+}
+
+impl RecentRequests {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            capacity,
+            entries: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    /// Redact and remember the object a request signed, in its envelope layout
+    pub fn record(&self, correlation_id: Option<&str>, operation: &str, signed: &SignedRequest) {
+        let value = signed.Object.to_value(signed.format);
+        let canonical = canonize_json(&redaction::redact_value(&value)).ok();
+        let Some(canonical) = canonical else {
+            return;
+        };
+
+        self.push(RecentRequest {
+            correlation_id: correlation_id.map(str::to_string),
+            operation: operation.to_string(),
+            signed_at: Utc::now(),
+            canonical,
+        });
+    }
+
+    pub fn push(&self, entry: RecentRequest) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        while entries.len() >= self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+
+    /// Buffered entries, newest first
+    pub fn snapshot(&self) -> Vec<RecentRequest> {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.iter().rev().cloned().collect()
+    }
+}
+
+/// The process-wide buffer, or None when it is disabled
+pub fn recent_requests() -> Option<&'static RecentRequests> {
+    RECENT_REQUESTS.as_ref()
+}
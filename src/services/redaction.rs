@@ -0,0 +1,133 @@
+use lazy_static::lazy_static;
+use log::warn;
+use serde_json::Value;
+
+const REDACTED: &str = "[REDACTED]";
+const SUPPRESSED: &str = "[SUPPRESSED: unrecognized fields]";
+
+lazy_static! {
+    // REDACTION_STRICT=true drops sensitive objects carrying fields this list doesn't know
+    static ref STRICT: bool = std::env::var("REDACTION_STRICT")
+        .map(|value| value.eq_ignore_ascii_case("true") || value == "1")
+        .unwrap_or(false);
+}
+
+// Fields that may carry card, instrument or personal data
+const SENSITIVE_KEYS: &[&str] = &[
+    "InstrumentToken",
+    "ExtendableInstrumentToken",
+    "InstrumentData",
+    "OptionalFields",
+    "OptionalInstrumentFields",
+    "Identification",
+    "Name",
+    "Address",
+    "Email",
+    "Cellphone",
+    "Signature",
+];
+
+// Objects holding card or personal data, with every field we know they carry.
+// A field missing here may be new data nobody has classified yet.
+const SENSITIVE_PARENTS: &[(&str, &[&str])] = &[
+    (
+        "PaymentInstrumentInput",
+        &[
+            "InstrumentToken",
+            "UseExtendedClientCreditIfAvailable",
+            "OptionalFields",
+            "InstrumentData",
+        ],
+    ),
+    (
+        "ClientInformation",
+        &[
+            "Name",
+            "Address",
+            "Email",
+            "Cellphone",
+            "Identification",
+            "IdentificationType",
+        ],
+    ),
+];
+
+/// Return a copy of `value` with sensitive fields masked, suitable for logs.
+/// With `REDACTION_STRICT` set, see `redact_value_with`.
+pub fn redact_value(value: &Value) -> Value {
+    redact_value_with(value, *STRICT)
+}
+
+/// Like `redact_value`; when `strict`, a `PaymentInstrumentInput` or
+/// `ClientInformation` object with a field outside the known list is replaced
+/// whole instead of logged, since the field may not be covered by redaction
+pub fn redact_value_with(value: &Value, strict: bool) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(key, v)| {
+                    let redacted = if is_sensitive_key(key) && !v.is_null() {
+                        Value::String(REDACTED.to_string())
+                    } else if strict && has_unrecognized_fields(key, v) {
+                        Value::String(SUPPRESSED.to_string())
+                    } else {
+                        redact_value_with(v, strict)
+                    };
+                    (key.clone(), redacted)
+                })
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(
+            items
+                .iter()
+                .map(|item| redact_value_with(item, strict))
+                .collect(),
+        ),
+        _ => value.clone(),
+    }
+}
+
+fn has_unrecognized_fields(parent: &str, value: &Value) -> bool {
+    let Some((_, known)) = SENSITIVE_PARENTS.iter().find(|(name, _)| *name == parent) else {
+        return false;
+    };
+    let Value::Object(map) = value else {
+        return false;
+    };
+
+    let unknown: Vec<&str> = map
+        .keys()
+        .map(String::as_str)
+        .filter(|key| !known.contains(key))
+        .collect();
+    if unknown.is_empty() {
+        return false;
+    }
+    // Only the names are logged; they say what to add to the lists above
+    warn!(
+        "Suppressed {} from logs, unrecognized fields: {}",
+        parent,
+        unknown.join(", ")
+    );
+    true
+}
+
+/// Describe a JSON error by category and position only, never the offending input
+pub fn describe_json_error(e: &serde_json::Error) -> String {
+    let category = match e.classify() {
+        serde_json::error::Category::Io => "I/O error",
+        serde_json::error::Category::Syntax => "syntax error",
+        serde_json::error::Category::Data => "invalid data",
+        serde_json::error::Category::Eof => "unexpected end of input",
+    };
+
+    // Errors from an already parsed `Value` have no position
+    if e.line() == 0 {
+        return category.to_string();
+    }
+    format!("{} at line {} column {}", category, e.line(), e.column())
+}
+
+fn is_sensitive_key(key: &str) -> bool {
+    SENSITIVE_KEYS.contains(&key)
+}
@@ -0,0 +1,105 @@
+use lazy_static::lazy_static;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const DEFAULT_CAPACITY: usize = 10_000;
+
+lazy_static! {
+    // Enabled by setting DUPLICATE_REFERENCE_TTL_SECS; DUPLICATE_REFERENCE_CAPACITY bounds it
+    static ref RECENT_REFERENCES: Option<RecentReferences> = std::env::var("DUPLICATE_REFERENCE_TTL_SECS")
+        .ok()
+        .and_then(|ttl| ttl.parse::<u64>().ok())
+        .map(|ttl| {
+            let capacity = std::env::var("DUPLICATE_REFERENCE_CAPACITY")
+                .ok()
+                .and_then(|value| value.parse::<usize>().ok())
+                .unwrap_or(DEFAULT_CAPACITY);
+            RecentReferences::new(Duration::from_secs(ttl), capacity)
+        });
+}
+
+/// Bounded, time-limited set of recently submitted client references
+pub struct RecentReferences {
+    ttl: Duration,
+    capacity: usize,
+    state: Mutex<ReferenceState>,
+}
+
+#[derive(Default)]
+struct ReferenceState {
+    seen: HashMap<String, Instant>,
+    order: VecDeque<(String, Instant)>,
+}
+
+impl RecentReferences {
+    pub fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            ttl,
+            capacity: capacity.max(1),
+            state: Mutex::new(ReferenceState::default()),
+        }
+    }
+
+    /// Record `reference`, returning false if it was already seen within the TTL
+    pub fn insert(&self, reference: &str) -> bool {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        self.evict_expired(&mut state, now);
+
+        if state.seen.contains_key(reference) {
+            return false;
+        }
+
+        self.record(&mut state, reference, now);
+        true
+    }
+
+    /// Record `reference` unconditionally, refreshing its TTL
+    pub fn force_insert(&self, reference: &str) {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        self.evict_expired(&mut state, now);
+        self.record(&mut state, reference, now);
+    }
+
+    /// Forget `reference`, e.g. when the request carrying it never reached Plexo
+    pub fn remove(&self, reference: &str) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        // The order queue entry is skipped on eviction once the map no longer matches
+        state.seen.remove(reference);
+    }
+
+    fn record(&self, state: &mut ReferenceState, reference: &str, now: Instant) {
+        state.seen.insert(reference.to_string(), now);
+        state.order.push_back((reference.to_string(), now));
+
+        // Drop the oldest references once the cache is full
+        while state.seen.len() > self.capacity {
+            let Some((oldest, inserted)) = state.order.pop_front() else {
+                break;
+            };
+            if state.seen.get(&oldest) == Some(&inserted) {
+                state.seen.remove(&oldest);
+            }
+        }
+    }
+
+    fn evict_expired(&self, state: &mut ReferenceState, now: Instant) {
+        while let Some((reference, inserted)) = state.order.front() {
+            if now.duration_since(*inserted) < self.ttl {
+                break;
+            }
+            // Only remove the map entry if it was not refreshed later
+            if state.seen.get(reference) == Some(inserted) {
+                state.seen.remove(reference);
+            }
+            state.order.pop_front();
+        }
+    }
+}
+
+/// The process-wide cache, or None when duplicate detection is disabled
+pub fn recent_references() -> Option<&'static RecentReferences> {
+    RECENT_REFERENCES.as_ref()
+}
@@ -0,0 +1,80 @@
+use crate::services::crypto;
+use actix_web::{
+    body::{self, BoxBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::{self, HeaderName, HeaderValue},
+    middleware::Next,
+    web, Error,
+};
+use lazy_static::lazy_static;
+use log::warn;
+use serde_json::Value;
+
+pub const RESPONSE_SIGNATURE_HEADER: &str = "x-response-signature";
+
+lazy_static! {
+    // Sign JSON responses when RESPONSE_SIGNING=true
+    static ref ENABLED: bool = std::env::var("RESPONSE_SIGNING")
+        .is_ok_and(|value| value.trim().eq_ignore_ascii_case("true"));
+}
+
+pub fn enabled() -> bool {
+    *ENABLED
+}
+
+/// Attach `X-Response-Signature` to JSON responses: the base64 RSA-SHA512
+/// signature, with the signing certificate, over the body canonicalized the
+/// way Plexo payloads are. Streams and non-JSON bodies pass through unsigned,
+/// and so does a body that could not be signed.
+pub async fn sign_response(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let response = next.call(req).await?.map_into_boxed_body();
+    if !is_json(&response) {
+        return Ok(response);
+    }
+
+    let (request, response) = response.into_parts();
+    let (mut response, body) = response.into_parts();
+    let bytes = body::to_bytes(body)
+        .await
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to buffer response"))?;
+
+    // Signing may block on an HSM
+    let signed = bytes.clone();
+    let signature = web::block(move || {
+        let body: Value = serde_json::from_slice(&signed).map_err(crypto::CryptoError::from)?;
+        crypto::get_crypto_service()?.sign_response(&body)
+    })
+    .await;
+
+    match signature {
+        Ok(Ok(signature)) => {
+            if let Ok(value) = HeaderValue::from_str(&signature) {
+                response
+                    .headers_mut()
+                    .insert(HeaderName::from_static(RESPONSE_SIGNATURE_HEADER), value);
+            }
+        }
+        Ok(Err(e)) => warn!("Sending response unsigned: {}", e),
+        Err(e) => warn!("Sending response unsigned, signing task failed: {}", e),
+    }
+
+    Ok(ServiceResponse::new(
+        request,
+        response.set_body(BoxBody::new(bytes)),
+    ))
+}
+
+// NDJSON batches stream line by line and are never buffered for signing
+fn is_json(response: &ServiceResponse<BoxBody>) -> bool {
+    response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|content_type| {
+            let essence = content_type.split(';').next().unwrap_or_default().trim();
+            essence.ends_with("json") && !essence.ends_with("ndjson")
+        })
+}
@@ -0,0 +1,111 @@
+//! Secrets kept encrypted at rest in the environment.
+//!
+//! `<NAME>_ENC` holds `base64(nonce || ciphertext || tag)` sealed with
+//! AES-256-GCM under a key from `ENC_KEY` (base64 of 32 bytes) or from the file
+//! named by `ENC_KEY_FILE`. When `<NAME>_ENC` is unset the plaintext `<NAME>`
+//! is used, which keeps local setups simple.
+
+use crate::services::crypto::CryptoError;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use openssl::rand::rand_bytes;
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+/// Read secret `name`, decrypting `<name>_ENC` when it is set.
+/// Returns None when neither form is set.
+pub fn read_secret(name: &str) -> Result<Option<String>, CryptoError> {
+    let encrypted_name = format!("{}_ENC", name);
+    match std::env::var(&encrypted_name) {
+        Ok(ciphertext) => {
+            let key = encryption_key()?;
+            decrypt_secret(&ciphertext, &key).map(Some).map_err(|e| {
+                CryptoError::InitializationError(format!("{} is unusable: {}", encrypted_name, e))
+            })
+        }
+        Err(_) => Ok(std::env::var(name).ok()),
+    }
+}
+
+/// The key from `ENC_KEY`, or from the file named by `ENC_KEY_FILE`
+pub fn encryption_key() -> Result<Vec<u8>, CryptoError> {
+    let encoded = match std::env::var("ENC_KEY") {
+        Ok(key) => key,
+        Err(_) => {
+            let path = std::env::var("ENC_KEY_FILE").map_err(|_| {
+                CryptoError::InitializationError(
+                    "ENC_KEY or ENC_KEY_FILE is required to decrypt secrets".to_string(),
+                )
+            })?;
+            std::fs::read_to_string(&path).map_err(|e| {
+                CryptoError::InitializationError(format!("Failed to read {}: {}", path, e))
+            })?
+        }
+    };
+
+    let key = BASE64.decode(encoded.trim()).map_err(|e| {
+        CryptoError::InitializationError(format!("Encryption key is not base64: {}", e))
+    })?;
+    if key.len() != KEY_LEN {
+        return Err(CryptoError::InitializationError(format!(
+            "Encryption key must be {} bytes, got {}",
+            KEY_LEN,
+            key.len()
+        )));
+    }
+    Ok(key)
+}
+
+/// Seal `plaintext` under `key` with a fresh random nonce
+pub fn encrypt_secret(plaintext: &str, key: &[u8]) -> Result<String, CryptoError> {
+    let mut nonce = [0u8; NONCE_LEN];
+    rand_bytes(&mut nonce)?;
+
+    let mut tag = [0u8; TAG_LEN];
+    let ciphertext = encrypt_aead(
+        Cipher::aes_256_gcm(),
+        key,
+        Some(&nonce),
+        &[],
+        plaintext.as_bytes(),
+        &mut tag,
+    )?;
+
+    let mut sealed = Vec::with_capacity(NONCE_LEN + ciphertext.len() + TAG_LEN);
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&ciphertext);
+    sealed.extend_from_slice(&tag);
+    Ok(BASE64.encode(sealed))
+}
+
+/// Open a value produced by `encrypt_secret`. A wrong key or a tampered
+/// value fails the tag check.
+pub fn decrypt_secret(sealed_base64: &str, key: &[u8]) -> Result<String, CryptoError> {
+    let sealed = BASE64
+        .decode(sealed_base64.trim())
+        .map_err(|e| CryptoError::SecretError(format!("Ciphertext is not base64: {}", e)))?;
+    if sealed.len() < NONCE_LEN + TAG_LEN {
+        return Err(CryptoError::SecretError(
+            "Ciphertext is too short".to_string(),
+        ));
+    }
+
+    let (nonce, rest) = sealed.split_at(NONCE_LEN);
+    let (ciphertext, tag) = rest.split_at(rest.len() - TAG_LEN);
+    let plaintext = decrypt_aead(
+        Cipher::aes_256_gcm(),
+        key,
+        Some(nonce),
+        &[],
+        ciphertext,
+        tag,
+    )
+    .map_err(|_| {
+        CryptoError::SecretError("Authentication failed, wrong key or corrupted value".to_string())
+    })?;
+
+    String::from_utf8(plaintext)
+        .map_err(|_| CryptoError::SecretError("Decrypted secret is not UTF-8".to_string()))
+}
@@ -0,0 +1,243 @@
+use crate::services::crypto::CryptoError;
+use foreign_types::ForeignType;
+use log::info;
+use openssl::hash::MessageDigest;
+use openssl::pkey::{PKey, Private};
+use openssl::sign::Signer;
+use openssl::x509::X509;
+use std::ffi::{c_char, c_int, c_void, CString};
+use std::ptr;
+
+/// Produces RSA-SHA512 signatures over canonized payloads
+pub trait PayloadSigner: Send + Sync {
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>, CryptoError>;
+    fn fingerprint(&self) -> &str;
+}
+
+/// Signer backed by a private key held in process memory (loaded from a PFX)
+pub struct PfxSigner {
+    private_key: PKey<Private>,
+    fingerprint: String,
+}
+
+impl PfxSigner {
+    pub fn new(private_key: PKey<Private>, fingerprint: String) -> Self {
+        Self {
+            private_key,
+            fingerprint,
+        }
+    }
+}
+
+impl PayloadSigner for PfxSigner {
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        sign_with_key(&self.private_key, data)
+    }
+
+    fn fingerprint(&self) -> &str {
+        &self.fingerprint
+    }
+}
+
+/// Signer for contract tests that only care about request shape. The
+/// "signature" is the SHA-512 digest of the data, so it is deterministic but
+/// worthless to Plexo. Only compiled with the `test-utils` feature.
+#[cfg(feature = "test-utils")]
+pub struct NoopSigner;
+
+#[cfg(feature = "test-utils")]
+impl NoopSigner {
+    pub const FINGERPRINT: &'static str = "0000000000000000000000000000000000000000";
+}
+
+#[cfg(feature = "test-utils")]
+impl PayloadSigner for NoopSigner {
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        Ok(openssl::sha::sha512(data).to_vec())
+    }
+
+    fn fingerprint(&self) -> &str {
+        Self::FINGERPRINT
+    }
+}
+
+// Opaque OpenSSL ENGINE handle; the ENGINE API is not wrapped by the openssl crate
+#[allow(clippy::upper_case_acronyms)]
+enum ENGINE {}
+
+extern "C" {
+    fn ENGINE_by_id(id: *const c_char) -> *mut ENGINE;
+    fn ENGINE_ctrl_cmd_string(
+        e: *mut ENGINE,
+        cmd_name: *const c_char,
+        arg: *const c_char,
+        cmd_optional: c_int,
+    ) -> c_int;
+    fn ENGINE_init(e: *mut ENGINE) -> c_int;
+    fn ENGINE_finish(e: *mut ENGINE) -> c_int;
+    fn ENGINE_free(e: *mut ENGINE) -> c_int;
+    fn ENGINE_load_private_key(
+        e: *mut ENGINE,
+        key_id: *const c_char,
+        ui_method: *mut c_void,
+        callback_data: *mut c_void,
+    ) -> *mut openssl_sys::EVP_PKEY;
+}
+
+/// Signer whose private key stays inside an HSM, accessed through OpenSSL's
+/// PKCS#11 engine. The key handle is engine-backed, so signing operations are
+/// performed by the token and the key material never enters process memory.
+pub struct Pkcs11Signer {
+    engine: *mut ENGINE,
+    private_key: PKey<Private>,
+    certificate: X509,
+    fingerprint: String,
+}
+
+// The engine is initialized once and only used through thread-safe EVP calls
+unsafe impl Send for Pkcs11Signer {}
+unsafe impl Sync for Pkcs11Signer {}
+
+impl Pkcs11Signer {
+    /// Load the signer from `PKCS11_MODULE` and `PKCS11_KEY_LABEL`.
+    ///
+    /// `PKCS11_PIN` is passed to the token when set, and `PKCS11_CERT_PATH`
+    /// points to the PEM certificate matching the key, used for the fingerprint.
+    pub fn from_env() -> Result<Self, CryptoError> {
+        let module = required_env("PKCS11_MODULE")?;
+        let key_label = required_env("PKCS11_KEY_LABEL")?;
+        let cert_path = required_env("PKCS11_CERT_PATH")?;
+        let pin = std::env::var("PKCS11_PIN").ok();
+
+        let cert = X509::from_pem(&std::fs::read(&cert_path)?)?;
+        let fingerprint = super::crypto::format_fingerprint(&cert.digest(MessageDigest::sha1())?);
+
+        let signer = Self::load(&module, &key_label, pin.as_deref(), cert, fingerprint)?;
+        info!(
+            "PKCS#11 signer initialized. Fingerprint: {}",
+            super::crypto::display_fingerprint(&signer.fingerprint)
+        );
+        Ok(signer)
+    }
+
+    fn load(
+        module: &str,
+        key_label: &str,
+        pin: Option<&str>,
+        certificate: X509,
+        fingerprint: String,
+    ) -> Result<Self, CryptoError> {
+        let engine_id = c_string("pkcs11")?;
+        let engine = unsafe { ENGINE_by_id(engine_id.as_ptr()) };
+        if engine.is_null() {
+            return Err(CryptoError::InitializationError(
+                "OpenSSL pkcs11 engine is not available".to_string(),
+            ));
+        }
+
+        let result = Self::init_engine(engine, module, key_label, pin);
+        match result {
+            Ok(private_key) => Ok(Self {
+                engine,
+                private_key,
+                certificate,
+                fingerprint,
+            }),
+            Err(e) => {
+                unsafe { ENGINE_free(engine) };
+                Err(e)
+            }
+        }
+    }
+
+    /// Public certificate matching the token's private key
+    pub fn certificate(&self) -> &X509 {
+        &self.certificate
+    }
+
+    fn init_engine(
+        engine: *mut ENGINE,
+        module: &str,
+        key_label: &str,
+        pin: Option<&str>,
+    ) -> Result<PKey<Private>, CryptoError> {
+        engine_ctrl(engine, "MODULE_PATH", module)?;
+        if let Some(pin) = pin {
+            engine_ctrl(engine, "PIN", pin)?;
+        }
+
+        if unsafe { ENGINE_init(engine) } != 1 {
+            return Err(CryptoError::InitializationError(
+                "Failed to initialize pkcs11 engine".to_string(),
+            ));
+        }
+
+        let key_id = c_string(&format!("pkcs11:object={};type=private", key_label))?;
+        let pkey = unsafe {
+            ENGINE_load_private_key(engine, key_id.as_ptr(), ptr::null_mut(), ptr::null_mut())
+        };
+        if pkey.is_null() {
+            unsafe { ENGINE_finish(engine) };
+            return Err(CryptoError::InitializationError(format!(
+                "Private key '{}' not found on PKCS#11 token",
+                key_label
+            )));
+        }
+
+        Ok(unsafe { PKey::from_ptr(pkey) })
+    }
+}
+
+impl PayloadSigner for Pkcs11Signer {
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        sign_with_key(&self.private_key, data)
+    }
+
+    fn fingerprint(&self) -> &str {
+        &self.fingerprint
+    }
+}
+
+impl Drop for Pkcs11Signer {
+    fn drop(&mut self) {
+        unsafe {
+            ENGINE_finish(self.engine);
+            ENGINE_free(self.engine);
+        }
+    }
+}
+
+fn sign_with_key(private_key: &PKey<Private>, data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    // Create a signer using RSA-SHA512
+    let mut signer = Signer::new(MessageDigest::sha512(), private_key)
+        .map_err(|e| CryptoError::SigningError(format!("Failed to create signer: {}", e)))?;
+
+    signer
+        .sign_oneshot_to_vec(data)
+        .map_err(|e| CryptoError::SigningError(format!("Failed to sign payload: {}", e)))
+}
+
+fn engine_ctrl(engine: *mut ENGINE, command: &str, argument: &str) -> Result<(), CryptoError> {
+    let command_c = c_string(command)?;
+    let argument_c = c_string(argument)?;
+    let ok = unsafe { ENGINE_ctrl_cmd_string(engine, command_c.as_ptr(), argument_c.as_ptr(), 0) };
+    if ok != 1 {
+        return Err(CryptoError::InitializationError(format!(
+            "pkcs11 engine rejected {}",
+            command
+        )));
+    }
+    Ok(())
+}
+
+fn c_string(value: &str) -> Result<CString, CryptoError> {
+    CString::new(value).map_err(|_| {
+        CryptoError::InitializationError(format!("Invalid engine argument: {}", value))
+    })
+}
+
+fn required_env(name: &str) -> Result<String, CryptoError> {
+    std::env::var(name).map_err(|_| {
+        CryptoError::InitializationError(format!("{} environment variable is required", name))
+    })
+}
@@ -0,0 +1,96 @@
+use crate::models::responses::is_in_progress_status;
+use async_trait::async_trait;
+use dashmap::DashMap;
+use lazy_static::lazy_static;
+use serde_json::Value;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+const DEFAULT_TTL_MS: u64 = 2_000;
+// Expired queries are swept once every this many writes, not on each one
+const SWEEP_EVERY_WRITES: usize = 1024;
+
+lazy_static! {
+    // How long a terminal status is answered from cache, from STATUS_CACHE_TTL_MS; 0 disables it
+    static ref STATUS_CACHE_TTL: Duration = Duration::from_millis(
+        std::env::var("STATUS_CACHE_TTL_MS")
+            .ok()
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .unwrap_or(DEFAULT_TTL_MS),
+    );
+}
+
+static STATUS_CACHE: OnceLock<Arc<dyn StatusCache>> = OnceLock::new();
+
+/// Where recent status results are kept per query. The default keeps them in
+/// memory; implement this over a shared cache so replicas answer each other's
+/// repeated polls.
+#[async_trait]
+pub trait StatusCache: Send + Sync {
+    async fn get(&self, key: &str) -> Option<Value>;
+    async fn put(&self, key: &str, response: Value, ttl: Duration);
+}
+
+/// Process-local cache, lost on restart
+#[derive(Default)]
+pub struct InMemoryStatusCache {
+    entries: DashMap<String, (Value, Instant)>,
+    writes: AtomicUsize,
+}
+
+impl InMemoryStatusCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl StatusCache for InMemoryStatusCache {
+    async fn get(&self, key: &str) -> Option<Value> {
+        let now = Instant::now();
+        self.entries
+            .remove_if(key, |_, (_, expires_at)| *expires_at <= now);
+        self.entries.get(key).map(|entry| entry.value().0.clone())
+    }
+
+    async fn put(&self, key: &str, response: Value, ttl: Duration) {
+        let now = Instant::now();
+        // Sweep expired queries now and then so the map can't grow without bound
+        if self
+            .writes
+            .fetch_add(1, Ordering::Relaxed)
+            .is_multiple_of(SWEEP_EVERY_WRITES)
+        {
+            self.entries.retain(|_, (_, expires_at)| *expires_at > now);
+        }
+        self.entries.insert(key.to_string(), (response, now + ttl));
+    }
+}
+
+/// Install a custom cache. Must be called before the first request; returns
+/// false when a cache is already in place.
+pub fn set_cache(cache: Arc<dyn StatusCache>) -> bool {
+    STATUS_CACHE.set(cache).is_ok()
+}
+
+/// The configured cache, falling back to the in-memory one
+pub fn cache() -> Arc<dyn StatusCache> {
+    STATUS_CACHE
+        .get_or_init(|| Arc::new(InMemoryStatusCache::new()))
+        .clone()
+}
+
+/// How long results are kept, None when caching is disabled
+pub fn ttl() -> Option<Duration> {
+    Some(*STATUS_CACHE_TTL).filter(|ttl| !ttl.is_zero())
+}
+
+/// Whether a status result can be served again: Plexo accepted the lookup and
+/// the transaction is in a final state
+pub fn cacheable(response: &Value) -> bool {
+    let succeeded = response.get("ResultCode").and_then(Value::as_i64) == Some(0);
+    let status = response.pointer("/Response/Status").and_then(Value::as_str);
+    // Caching a transaction that can still change would hide the change
+    succeeded && status.is_some_and(|status| !is_in_progress_status(status))
+}
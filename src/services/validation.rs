@@ -0,0 +1,880 @@
+use crate::models::common::{AmountFormatError, Currency, LosslessNumber};
+use crate::models::requests::{
+    AuthorizationAction, AuthorizationRequest, AuthorizationRequestData, AuthorizationType,
+    InstrumentData, PaymentRequest, PaymentRequestData, ReferenceRequest,
+    RegisterInstrumentRequest, StatusQuery, TransactionHistoryRequest, WebFormSettings,
+    REFERENCE_TYPE_CLIENT_REFERENCE, REFERENCE_TYPE_TRANSACTION_ID,
+};
+use crate::services::plexo_service::PlexoServiceError;
+use lazy_static::lazy_static;
+use log::{info, warn};
+use reqwest::Url;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Once, OnceLock};
+use thiserror::Error;
+use utoipa::ToSchema;
+
+/// A single rejected field. `field` is the dotted path of the offending value
+/// in the request body (e.g. `Request.FinancialInclusion.BilledAmount`) and
+/// `code` a stable identifier frontends can map to their own messages.
+#[derive(Error, Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[error("{message}")]
+pub struct ValidationError {
+    pub field: String,
+    pub code: String,
+    pub message: String,
+}
+
+impl ValidationError {
+    pub fn new(field: impl Into<String>, code: &str, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            code: code.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+/// Every rejected field of a request, in the order the fields were checked,
+/// so a frontend can flag them all at once
+#[derive(Error, Debug, Clone, Default)]
+#[error("{}", .0.iter().map(|e| e.message.as_str()).collect::<Vec<_>>().join("; "))]
+pub struct ValidationErrors(pub Vec<ValidationError>);
+
+impl ValidationErrors {
+    fn check(&mut self, result: Result<(), ValidationError>) {
+        if let Err(e) = result {
+            self.0.push(e);
+        }
+    }
+
+    fn check_all(&mut self, result: Result<(), ValidationErrors>) {
+        if let Err(errors) = result {
+            self.0.extend(errors.0);
+        }
+    }
+
+    fn into_result(self) -> Result<(), ValidationErrors> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl From<ValidationError> for ValidationErrors {
+    fn from(error: ValidationError) -> Self {
+        Self(vec![error])
+    }
+}
+
+// Plexo rejects larger metadata after the full round trip, so fail fast here
+const DEFAULT_MAX_OPTIONAL_METADATA_BYTES: usize = 2048;
+// Every item lands in the canonical string that gets signed, so bound them
+const DEFAULT_MAX_PAYMENT_ITEMS: usize = 100;
+// Plexo issuer ids accepted in `LimitIssuers`, with the issuer they stand for.
+// Ids Plexo adds after this table was written can be accepted through
+// KNOWN_ISSUER_CODES until the table is updated.
+const KNOWN_ISSUERS: &[(&str, &str)] = &[
+    ("1", "American Express"),
+    ("2", "Anda"),
+    ("3", "Cabal"),
+    ("4", "Club del Este"),
+    ("5", "Creditel"),
+    ("6", "Diners"),
+    ("7", "Lider"),
+    ("8", "Mastercard"),
+    ("9", "OCA"),
+    ("10", "Passcard"),
+    ("11", "Visa"),
+];
+
+// Plexo bank ids accepted in `LimitBanks`, which are the Banco Central del
+// Uruguay institution codes. KNOWN_BANK_CODES adds to them the same way.
+const KNOWN_BANKS: &[(&str, &str)] = &[
+    ("1", "BROU"),
+    ("91", "BHU"),
+    ("110", "Bandes"),
+    ("113", "Itaú"),
+    ("128", "Scotiabank"),
+    ("137", "Santander"),
+    ("153", "BBVA"),
+    ("157", "HSBC"),
+    ("162", "Heritage"),
+    ("205", "Citibank"),
+    ("246", "Banco de la Nación Argentina"),
+];
+// Largest history page callers may ask Plexo for
+pub const MAX_HISTORY_PAGE_SIZE: u32 = 100;
+
+// Largest amount any single field of a payment may carry, set by `init()`
+static MAX_PAYMENT_AMOUNT: OnceLock<Option<Decimal>> = OnceLock::new();
+
+// Items accepted in one payment, set by `init()`
+static MAX_PAYMENT_ITEMS: OnceLock<usize> = OnceLock::new();
+
+// Size cap for OptionalMetadata, set by `init()`
+static MAX_OPTIONAL_METADATA_BYTES: OnceLock<usize> = OnceLock::new();
+
+static REDIRECT_ALLOWLIST_WARNING: Once = Once::new();
+
+lazy_static! {
+    // Issuer -> InstrumentData keys that issuer requires, from ISSUER_REQUIRED_FIELDS
+    static ref ISSUER_REQUIRED_FIELDS: HashMap<String, Vec<String>> =
+        parse_issuer_required_fields(&std::env::var("ISSUER_REQUIRED_FIELDS").unwrap_or_default());
+
+    // Hosts Plexo may send the customer back to, from REDIRECT_HOST_ALLOWLIST.
+    // Empty means any host is accepted.
+    static ref REDIRECT_HOST_ALLOWLIST: Vec<String> =
+        parse_redirect_host_allowlist(&std::env::var("REDIRECT_HOST_ALLOWLIST").unwrap_or_default());
+
+    // Whether item amounts must add up to BilledAmount, from REQUIRE_ITEMS_TOTAL_MATCH
+    static ref REQUIRE_ITEMS_TOTAL_MATCH: bool = std::env::var("REQUIRE_ITEMS_TOTAL_MATCH")
+        .is_ok_and(|value| value.trim().eq_ignore_ascii_case("true"));
+
+    // Whether the amounts of a payment must agree with each other (taxed within
+    // billed, VAT within taxed, items adding up), from VALIDATE_AMOUNT_CONSISTENCY
+    static ref VALIDATE_AMOUNT_CONSISTENCY: bool = std::env::var("VALIDATE_AMOUNT_CONSISTENCY")
+        .is_ok_and(|value| value.trim().eq_ignore_ascii_case("true"));
+
+    // OPTIONAL_METADATA_FORMAT=text accepts any string; the default requires JSON
+    static ref OPTIONAL_METADATA_REQUIRES_JSON: bool = !std::env::var("OPTIONAL_METADATA_FORMAT")
+        .is_ok_and(|format| format.trim().eq_ignore_ascii_case("text"));
+
+    // KNOWN_ISSUERS plus any ids listed in KNOWN_ISSUER_CODES
+    static ref KNOWN_ISSUER_CODES: HashSet<String> =
+        known_codes(KNOWN_ISSUERS, &std::env::var("KNOWN_ISSUER_CODES").unwrap_or_default());
+
+    // KNOWN_BANKS plus any ids listed in KNOWN_BANK_CODES
+    static ref KNOWN_BANK_CODES: HashSet<String> =
+        known_codes(KNOWN_BANKS, &std::env::var("KNOWN_BANK_CODES").unwrap_or_default());
+}
+
+/// Read `MAX_PAYMENT_AMOUNT`, `MAX_PAYMENT_ITEMS` and
+/// `MAX_OPTIONAL_METADATA_BYTES`, called from
+/// `plexo_service::init()`. An invalid value fails startup rather than
+/// silently lifting or replacing the limit.
+pub fn init() -> Result<(), PlexoServiceError> {
+    let max = parse_max_payment_amount(std::env::var("MAX_PAYMENT_AMOUNT").ok().as_deref())?;
+    if let Some(max) = max {
+        info!("Rejecting payment amounts above {}", max);
+    }
+    let _ = MAX_PAYMENT_AMOUNT.set(max);
+
+    let max_items = parse_limit(
+        "MAX_PAYMENT_ITEMS",
+        std::env::var("MAX_PAYMENT_ITEMS").ok().as_deref(),
+        DEFAULT_MAX_PAYMENT_ITEMS,
+    )?;
+    let _ = MAX_PAYMENT_ITEMS.set(max_items);
+
+    let max_metadata_bytes = parse_limit(
+        "MAX_OPTIONAL_METADATA_BYTES",
+        std::env::var("MAX_OPTIONAL_METADATA_BYTES").ok().as_deref(),
+        DEFAULT_MAX_OPTIONAL_METADATA_BYTES,
+    )?;
+    let _ = MAX_OPTIONAL_METADATA_BYTES.set(max_metadata_bytes);
+    Ok(())
+}
+
+pub fn validate_authorization_request(
+    request: &AuthorizationRequest,
+) -> Result<(), ValidationErrors> {
+    let mut errors = ValidationErrors::default();
+    errors.check(validate_redirect_uri(&request.Request.RedirectUri));
+    errors.check(validate_authorization_action(&request.Request));
+    errors.check_all(validate_limit_codes(&request.Request));
+    errors.check(validate_optional_metadata(
+        request.Request.OptionalMetadata.as_deref(),
+    ));
+    if let Some(settings) = &request.Request.WebFormSettings {
+        errors.check_all(validate_web_form_settings(settings));
+    }
+    errors.into_result()
+}
+
+// Plexo truncates longer button labels on narrow screens
+const MAX_BUTTON_LABEL_CHARS: usize = 40;
+
+/// Check the typed `WebFormSettings` fields; passed-through fields are Plexo's to judge
+pub fn validate_web_form_settings(settings: &WebFormSettings) -> Result<(), ValidationErrors> {
+    const PATH: &str = "Request.WebFormSettings";
+
+    let mut errors = ValidationErrors::default();
+    if let Some(theme) = &settings.Theme {
+        if !matches!(theme.as_str(), "light" | "dark") {
+            errors.check(Err(ValidationError::new(
+                format!("{}.Theme", PATH),
+                "unknown_value",
+                format!("Theme must be light or dark, got {}", theme),
+            )));
+        }
+    }
+
+    if let Some(language) = &settings.Language {
+        if language.len() != 2 || !language.chars().all(|c| c.is_ascii_lowercase()) {
+            errors.check(Err(ValidationError::new(
+                format!("{}.Language", PATH),
+                "invalid_format",
+                "Language must be a lowercase ISO 639-1 code such as es or en",
+            )));
+        }
+    }
+
+    if let Some(labels) = &settings.ButtonLabels {
+        for (name, label) in [("Submit", &labels.Submit), ("Cancel", &labels.Cancel)] {
+            if label
+                .as_ref()
+                .is_some_and(|label| label.chars().count() > MAX_BUTTON_LABEL_CHARS)
+            {
+                errors.check(Err(ValidationError::new(
+                    format!("{}.ButtonLabels.{}", PATH, name),
+                    "too_long",
+                    format!(
+                        "Button labels are limited to {} characters",
+                        MAX_BUTTON_LABEL_CHARS
+                    ),
+                )));
+            }
+        }
+    }
+
+    if let Some(styling) = &settings.Styling {
+        for (name, color) in [
+            ("PrimaryColor", &styling.PrimaryColor),
+            ("BackgroundColor", &styling.BackgroundColor),
+        ] {
+            if color.as_deref().is_some_and(|color| !is_hex_color(color)) {
+                errors.check(Err(ValidationError::new(
+                    format!("{}.Styling.{}", PATH, name),
+                    "invalid_format",
+                    format!("{} must be a hex color like #1a2b3c", name),
+                )));
+            }
+        }
+
+        if let Some(logo_url) = &styling.LogoUrl {
+            if !Url::parse(logo_url).is_ok_and(|url| url.scheme() == "https") {
+                errors.check(Err(ValidationError::new(
+                    format!("{}.Styling.LogoUrl", PATH),
+                    "invalid_format",
+                    "LogoUrl must be an absolute https URL",
+                )));
+            }
+        }
+    }
+
+    errors.into_result()
+}
+
+fn is_hex_color(color: &str) -> bool {
+    color
+        .strip_prefix('#')
+        .is_some_and(|hex| matches!(hex.len(), 3 | 6) && hex.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// Check `OptionalMetadata` against `MAX_OPTIONAL_METADATA_BYTES` and, unless
+/// `OPTIONAL_METADATA_FORMAT=text`, require it to be a JSON document
+pub fn validate_optional_metadata(metadata: Option<&str>) -> Result<(), ValidationError> {
+    const FIELD: &str = "Request.OptionalMetadata";
+
+    let Some(metadata) = metadata else {
+        return Ok(());
+    };
+
+    let max = MAX_OPTIONAL_METADATA_BYTES
+        .get()
+        .copied()
+        .unwrap_or(DEFAULT_MAX_OPTIONAL_METADATA_BYTES);
+    if metadata.len() > max {
+        return Err(ValidationError::new(
+            FIELD,
+            "too_long",
+            format!(
+                "OptionalMetadata is {} bytes, the maximum is {}",
+                metadata.len(),
+                max
+            ),
+        ));
+    }
+
+    if *OPTIONAL_METADATA_REQUIRES_JSON {
+        if let Err(e) = serde_json::from_str::<serde_json::Value>(metadata) {
+            return Err(ValidationError::new(
+                FIELD,
+                "invalid_format",
+                format!("OptionalMetadata must be valid JSON: {}", e),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Resolve a status lookup to exactly one `StatusQuery`, rejecting empty
+/// references and requests that name more than one
+pub fn validate_status_request(request: &ReferenceRequest) -> Result<StatusQuery, ValidationError> {
+    let mut queries = Vec::new();
+    if let Some(id) = &request.transaction_id {
+        queries.push((
+            "Request.TransactionId",
+            StatusQuery::ByTransactionId(id.clone()),
+        ));
+    }
+    if let Some(reference) = &request.client_reference_id {
+        queries.push((
+            "Request.ClientReferenceId",
+            StatusQuery::ByClientReference(reference.clone()),
+        ));
+    }
+    if let Some(reference) = &request.meta_reference {
+        let query = match request.reference_type {
+            None | Some(REFERENCE_TYPE_CLIENT_REFERENCE) => {
+                StatusQuery::ByClientReference(reference.clone())
+            }
+            Some(REFERENCE_TYPE_TRANSACTION_ID) => StatusQuery::ByTransactionId(reference.clone()),
+            Some(other) => {
+                return Err(ValidationError::new(
+                    "Request.Type",
+                    "unknown_value",
+                    format!("Unknown status reference Type: {}", other),
+                ))
+            }
+        };
+        queries.push(("Request.MetaReference", query));
+    }
+
+    let mut queries = queries.into_iter();
+    let Some((field, query)) = queries.next() else {
+        return Err(ValidationError::new(
+            "Request",
+            "required",
+            "Provide one of TransactionId, ClientReferenceId or MetaReference",
+        ));
+    };
+    if let Some((other_field, _)) = queries.next() {
+        return Err(ValidationError::new(
+            other_field,
+            "invalid_combination",
+            format!(
+                "Only one reference may be given, found {} and {}",
+                field, other_field
+            ),
+        ));
+    }
+
+    validate_status_query(&query, field)?;
+    Ok(query)
+}
+
+/// A lookup needs a non-empty reference; `field` is where it was given
+pub fn validate_status_query(query: &StatusQuery, field: &str) -> Result<(), ValidationError> {
+    if query.reference().trim().is_empty() {
+        return Err(ValidationError::new(
+            field,
+            "required",
+            "The status reference must not be empty",
+        ));
+    }
+    Ok(())
+}
+
+/// Reject `LimitIssuers`/`LimitBanks` entries Plexo doesn't know, which it
+/// would otherwise ignore silently and show every instrument
+pub fn validate_limit_codes(request: &AuthorizationRequestData) -> Result<(), ValidationErrors> {
+    let lists = [
+        (
+            "LimitIssuers",
+            "issuer",
+            &request.LimitIssuers,
+            &*KNOWN_ISSUER_CODES,
+        ),
+        (
+            "LimitBanks",
+            "bank",
+            &request.LimitBanks,
+            &*KNOWN_BANK_CODES,
+        ),
+    ];
+    let mut errors = ValidationErrors::default();
+    for (field, kind, codes, known_codes) in lists {
+        let Some(codes) = codes else {
+            continue;
+        };
+        for (index, code) in codes
+            .iter()
+            .enumerate()
+            .filter(|(_, code)| !known_codes.contains(code.trim()))
+        {
+            errors.check(Err(ValidationError::new(
+                format!("Request.{}.{}", field, index),
+                "unknown_value",
+                format!("Unknown {} code: {}", kind, code),
+            )));
+        }
+    }
+
+    errors.into_result()
+}
+
+/// Reject `Action`/`Type`/`ExtendableInstrumentToken` combinations Plexo
+/// would refuse or misinterpret, see `AuthorizationRequestData`
+pub fn validate_authorization_action(
+    request: &AuthorizationRequestData,
+) -> Result<(), ValidationError> {
+    // Unknown Type values and Action flags are rejected when the request is parsed
+    let Some(token) = request.ExtendableInstrumentToken.as_deref() else {
+        if request
+            .Action
+            .intersects(AuthorizationAction::EXTEND_INSTRUMENT)
+        {
+            return Err(ValidationError::new(
+                "Request.ExtendableInstrumentToken",
+                "required",
+                "Action requests an instrument extension but no ExtendableInstrumentToken was provided",
+            ));
+        }
+        return Ok(());
+    };
+
+    if token.trim().is_empty() {
+        return Err(ValidationError::new(
+            "Request.ExtendableInstrumentToken",
+            "required",
+            "ExtendableInstrumentToken must not be empty",
+        ));
+    }
+
+    if request.request_type != AuthorizationType::ClientReference {
+        return Err(ValidationError::new(
+            "Request.ExtendableInstrumentToken",
+            "invalid_combination",
+            "ExtendableInstrumentToken requires the client reference authorization Type",
+        ));
+    }
+
+    if request.Action.intersects(
+        AuthorizationAction::REGISTER_INSTRUMENT | AuthorizationAction::DELETE_INSTRUMENT,
+    ) {
+        return Err(ValidationError::new(
+            "Request.ExtendableInstrumentToken",
+            "invalid_combination",
+            "ExtendableInstrumentToken cannot be combined with register or delete actions",
+        ));
+    }
+
+    Ok(())
+}
+
+pub fn validate_payment_request(request: &PaymentRequest) -> Result<(), ValidationErrors> {
+    let mut errors = ValidationErrors::default();
+    match validate_currency(request.Request.CurrencyId) {
+        Ok(currency) => errors.check_all(validate_amount_decimals(request, currency)),
+        Err(e) => errors.check(Err(e)),
+    }
+    errors.check(validate_payment_items(request.Request.Items.len()));
+    errors.check_all(validate_payment_amounts(request));
+    if *VALIDATE_AMOUNT_CONSISTENCY {
+        errors.check_all(validate_amount_consistency(&request.Request));
+    } else if *REQUIRE_ITEMS_TOTAL_MATCH {
+        errors.check(validate_items_total(&request.Request));
+    }
+    if let Some(instrument_data) = &request.Request.PaymentInstrumentInput.InstrumentData {
+        errors.check_all(validate_instrument_data(
+            instrument_data,
+            "Request.PaymentInstrumentInput.InstrumentData",
+        ));
+    }
+    errors.into_result()
+}
+
+/// Plexo requires at least one item; more than `MAX_PAYMENT_ITEMS` is refused
+pub fn validate_payment_items(count: usize) -> Result<(), ValidationError> {
+    const FIELD: &str = "Request.Items";
+    let max = MAX_PAYMENT_ITEMS
+        .get()
+        .copied()
+        .unwrap_or(DEFAULT_MAX_PAYMENT_ITEMS);
+
+    if count == 0 {
+        return Err(ValidationError::new(
+            FIELD,
+            "required",
+            "A payment needs at least one item",
+        ));
+    }
+    if count > max {
+        return Err(ValidationError::new(
+            FIELD,
+            "too_many",
+            format!("A payment may have at most {} items, got {}", max, count),
+        ));
+    }
+    Ok(())
+}
+
+/// A history lookup needs a reference; pages count from 1 and hold at most
+/// `MAX_HISTORY_PAGE_SIZE` transactions
+pub fn validate_transaction_history_request(
+    request: &TransactionHistoryRequest,
+) -> Result<(), ValidationErrors> {
+    let data = &request.Request;
+    let mut errors = ValidationErrors::default();
+    if data.ClientReferenceId.trim().is_empty() {
+        errors.check(Err(ValidationError::new(
+            "Request.ClientReferenceId",
+            "required",
+            "A client reference is required",
+        )));
+    }
+    if data.PageNumber == Some(0) {
+        errors.check(Err(ValidationError::new(
+            "Request.PageNumber",
+            "out_of_range",
+            "Pages are numbered from 1",
+        )));
+    }
+    if let Some(size) = data
+        .PageSize
+        .filter(|size| *size == 0 || *size > MAX_HISTORY_PAGE_SIZE)
+    {
+        errors.check(Err(ValidationError::new(
+            "Request.PageSize",
+            "out_of_range",
+            format!(
+                "Page size must be between 1 and {}, got {}",
+                MAX_HISTORY_PAGE_SIZE, size
+            ),
+        )));
+    }
+    errors.into_result()
+}
+
+pub fn validate_register_instrument_request(
+    request: &RegisterInstrumentRequest,
+) -> Result<(), ValidationErrors> {
+    let mut errors = ValidationErrors::default();
+    errors.check_all(validate_instrument_data(
+        &request.Request.InstrumentData,
+        "Request.InstrumentData",
+    ));
+    errors.check(validate_optional_metadata(
+        request.Request.OptionalMetadata.as_deref(),
+    ));
+    errors.into_result()
+}
+
+/// Check that the fields required by the instrument's issuer are present.
+/// Extra fields are passed through to Plexo untouched. `path` is where the
+/// instrument data sits in the request, used to report the missing fields.
+pub fn validate_instrument_data(
+    instrument_data: &InstrumentData,
+    path: &str,
+) -> Result<(), ValidationErrors> {
+    let Some(issuer) = &instrument_data.Issuer else {
+        return Ok(());
+    };
+    let Some(required_fields) = ISSUER_REQUIRED_FIELDS.get(issuer) else {
+        return Ok(());
+    };
+
+    let mut errors = ValidationErrors::default();
+    for field in required_fields.iter().filter(|field| {
+        instrument_data
+            .additional_data
+            .as_ref()
+            .and_then(|data| data.get(field.as_str()))
+            .is_none_or(|value| value.is_null())
+    }) {
+        errors.check(Err(ValidationError::new(
+            format!("{}.{}", path, field),
+            "required",
+            format!("{} is required by issuer {}", field, issuer),
+        )));
+    }
+    errors.into_result()
+}
+
+/// Reject currency ids Plexo doesn't know, so a typo can't charge in the wrong currency
+pub fn validate_currency(currency_id: i32) -> Result<Currency, ValidationError> {
+    Currency::try_from(currency_id).map_err(|id| {
+        ValidationError::new(
+            "Request.CurrencyId",
+            "unknown_value",
+            format!("Unknown CurrencyId: {}", id),
+        )
+    })
+}
+
+/// Reject amounts above `MAX_PAYMENT_AMOUNT`, compared as exact decimals
+pub fn validate_payment_amounts(request: &PaymentRequest) -> Result<(), ValidationErrors> {
+    let Some(max) = MAX_PAYMENT_AMOUNT.get().copied().flatten() else {
+        return Ok(());
+    };
+
+    let mut errors = ValidationErrors::default();
+    for (field, amount) in payment_amounts(&request.Request) {
+        let Some(value) = amount.to_decimal() else {
+            let message = format!("{} is not a valid decimal: {}", field, amount.0);
+            errors.check(Err(ValidationError::new(field, "invalid_format", message)));
+            continue;
+        };
+        if value.abs() > max {
+            let message = format!(
+                "{} {} exceeds the maximum allowed amount of {}",
+                field, amount.0, max
+            );
+            errors.check(Err(ValidationError::new(field, "out_of_range", message)));
+        }
+    }
+
+    errors.into_result()
+}
+
+/// Reject amounts with more decimal places than the currency has; they
+/// would have to be rounded to be sent, and a charge is never rounded
+pub fn validate_amount_decimals(
+    request: &PaymentRequest,
+    currency: Currency,
+) -> Result<(), ValidationErrors> {
+    let mut errors = ValidationErrors::default();
+    for (field, amount) in payment_amounts(&request.Request) {
+        match amount.format_with_decimals(currency.decimal_places()) {
+            Ok(_) => {}
+            Err(AmountFormatError::TooManyDecimals { decimal_places, .. }) => {
+                let message = format!(
+                    "{} {} has more than the {} decimal places of currency {}",
+                    field, amount.0, decimal_places, request.Request.CurrencyId
+                );
+                errors.check(Err(ValidationError::new(
+                    field,
+                    "too_many_decimals",
+                    message,
+                )));
+            }
+            Err(AmountFormatError::Invalid(_)) => {
+                let message = format!("{} is not a valid decimal: {}", field, amount.0);
+                errors.check(Err(ValidationError::new(field, "invalid_format", message)));
+            }
+        }
+    }
+
+    errors.into_result()
+}
+
+// Every amount of a payment with its path in the request
+fn payment_amounts(data: &PaymentRequestData) -> impl Iterator<Item = (String, &LosslessNumber)> {
+    let inclusion = &data.FinancialInclusion;
+    [
+        (
+            "Request.FinancialInclusion.BilledAmount",
+            Some(&inclusion.BilledAmount),
+        ),
+        (
+            "Request.FinancialInclusion.TaxedAmount",
+            Some(&inclusion.TaxedAmount),
+        ),
+        (
+            "Request.FinancialInclusion.VATAmount",
+            inclusion.VATAmount.as_ref(),
+        ),
+        (
+            "Request.LoyaltyProgramAmount",
+            data.LoyaltyProgramAmount.as_ref(),
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(field, amount)| Some((field.to_string(), amount?)))
+    .chain(
+        data.Items
+            .iter()
+            .enumerate()
+            .map(|(index, item)| (format!("Request.Items.{}.Amount", index), &item.Amount)),
+    )
+}
+
+/// Check that a payment's amounts agree: TaxedAmount no more than BilledAmount,
+/// VATAmount no more than TaxedAmount and the items adding up to BilledAmount,
+/// all compared exactly. Plexo only rejects these after the round trip.
+pub fn validate_amount_consistency(data: &PaymentRequestData) -> Result<(), ValidationErrors> {
+    let inclusion = &data.FinancialInclusion;
+    let taxed = &inclusion.TaxedAmount;
+
+    let mut errors = ValidationErrors::default();
+    errors.check(ensure_not_above(
+        "Request.FinancialInclusion.TaxedAmount",
+        ("TaxedAmount", taxed),
+        ("BilledAmount", &inclusion.BilledAmount),
+    ));
+    if let Some(vat) = &inclusion.VATAmount {
+        errors.check(ensure_not_above(
+            "Request.FinancialInclusion.VATAmount",
+            ("VATAmount", vat),
+            ("TaxedAmount", taxed),
+        ));
+    }
+    errors.check(validate_items_total(data));
+    errors.into_result()
+}
+
+// `amount` must not exceed `limit`; `field` is the request path of `amount`
+fn ensure_not_above(
+    field: &str,
+    amount: (&str, &LosslessNumber),
+    limit: (&str, &LosslessNumber),
+) -> Result<(), ValidationError> {
+    let ((name, amount), (limit_name, limit)) = (amount, limit);
+    match amount.decimal_cmp(limit) {
+        Some(Ordering::Greater) => Err(ValidationError::new(
+            field,
+            "inconsistent",
+            format!("{} {} exceeds {} {}", name, amount.0, limit_name, limit.0),
+        )),
+        Some(_) => Ok(()),
+        None => Err(ValidationError::new(
+            field,
+            "invalid_format",
+            format!("{} and {} must be valid decimals", name, limit_name),
+        )),
+    }
+}
+
+/// Require the item amounts to add up exactly to `BilledAmount`
+pub fn validate_items_total(data: &PaymentRequestData) -> Result<(), ValidationError> {
+    const FIELD: &str = "Request.FinancialInclusion.BilledAmount";
+
+    let billed = &data.FinancialInclusion.BilledAmount;
+    let total = data.sum_items().ok_or_else(|| {
+        ValidationError::new(
+            "Request.Items",
+            "invalid_format",
+            "Item amounts must be valid decimals",
+        )
+    })?;
+    match total.decimal_cmp(billed) {
+        Some(Ordering::Equal) => Ok(()),
+        Some(_) => Err(ValidationError::new(
+            FIELD,
+            "mismatch",
+            format!(
+                "BilledAmount {} does not match the item total {}",
+                billed.0, total.0
+            ),
+        )),
+        None => Err(ValidationError::new(
+            FIELD,
+            "invalid_format",
+            format!("BilledAmount is not a valid decimal: {}", billed.0),
+        )),
+    }
+}
+
+/// Require an absolute https URL, restricted to `REDIRECT_HOST_ALLOWLIST` when set
+pub fn validate_redirect_uri(redirect_uri: &str) -> Result<(), ValidationError> {
+    const FIELD: &str = "Request.RedirectUri";
+
+    let url = Url::parse(redirect_uri).map_err(|e| {
+        ValidationError::new(
+            FIELD,
+            "invalid_format",
+            format!("RedirectUri must be an absolute URL: {}", e),
+        )
+    })?;
+
+    if url.scheme() != "https" {
+        return Err(ValidationError::new(
+            FIELD,
+            "invalid_format",
+            format!("RedirectUri must use https, got {}", url.scheme()),
+        ));
+    }
+
+    let host = url
+        .host_str()
+        .ok_or_else(|| {
+            ValidationError::new(FIELD, "invalid_format", "RedirectUri must include a host")
+        })?
+        .to_ascii_lowercase();
+
+    if REDIRECT_HOST_ALLOWLIST.is_empty() {
+        REDIRECT_ALLOWLIST_WARNING.call_once(|| {
+            warn!("REDIRECT_HOST_ALLOWLIST is not set, any https RedirectUri host is accepted");
+        });
+        return Ok(());
+    }
+
+    if REDIRECT_HOST_ALLOWLIST.contains(&host) {
+        Ok(())
+    } else {
+        Err(ValidationError::new(
+            FIELD,
+            "not_allowed",
+            format!("RedirectUri host {} is not allowed", host),
+        ))
+    }
+}
+
+fn parse_max_payment_amount(raw: Option<&str>) -> Result<Option<Decimal>, PlexoServiceError> {
+    let Some(raw) = raw.map(str::trim).filter(|raw| !raw.is_empty()) else {
+        return Ok(None);
+    };
+    match raw.parse::<Decimal>() {
+        Ok(max) if max > Decimal::ZERO => Ok(Some(max)),
+        _ => Err(PlexoServiceError::ConfigurationError(format!(
+            "MAX_PAYMENT_AMOUNT must be a positive decimal, got {}",
+            raw
+        ))),
+    }
+}
+
+// `name` as a positive count, `default` when unset
+fn parse_limit(name: &str, raw: Option<&str>, default: usize) -> Result<usize, PlexoServiceError> {
+    let Some(raw) = raw.map(str::trim).filter(|raw| !raw.is_empty()) else {
+        return Ok(default);
+    };
+    match raw.parse::<usize>() {
+        Ok(limit) if limit > 0 => Ok(limit),
+        _ => Err(PlexoServiceError::ConfigurationError(format!(
+            "{} must be a positive number, got {}",
+            name, raw
+        ))),
+    }
+}
+
+// The ids in `table` plus those in `extra`, given as `1,4,11`
+fn known_codes(table: &[(&str, &str)], extra: &str) -> HashSet<String> {
+    table
+        .iter()
+        .map(|(code, _)| code.to_string())
+        .chain(extra.split(',').map(|code| code.trim().to_string()))
+        .filter(|code| !code.is_empty())
+        .collect()
+}
+
+// Parse `shop.example.com,pay.example.com`
+fn parse_redirect_host_allowlist(raw: &str) -> Vec<String> {
+    raw.split(',')
+        .map(|host| host.trim().to_ascii_lowercase())
+        .filter(|host| !host.is_empty())
+        .collect()
+}
+
+// Parse `issuer:field1,field2;issuer2:field3`
+fn parse_issuer_required_fields(raw: &str) -> HashMap<String, Vec<String>> {
+    raw.split(';')
+        .filter_map(|entry| {
+            let (issuer, fields) = entry.split_once(':')?;
+            let fields: Vec<String> = fields
+                .split(',')
+                .map(|field| field.trim().to_string())
+                .filter(|field| !field.is_empty())
+                .collect();
+            Some((issuer.trim().to_string(), fields))
+        })
+        .filter(|(issuer, _)| !issuer.is_empty())
+        .collect()
+}
@@ -0,0 +1,158 @@
+use crate::services::metrics;
+use crate::services::redaction;
+use lazy_static::lazy_static;
+use log::{debug, error, warn};
+use reqwest::Client;
+use serde::Serialize;
+use serde_json::Value;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::time::Duration;
+
+const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+// Backoff between attempts doubles from here, capped at MAX_BACKOFF
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Body POSTed to `RESULT_WEBHOOK_URL` once an operation has a final result
+#[derive(Debug, Clone, Serialize)]
+pub struct ResultNotification {
+    pub timestamp: String,
+    pub correlation_id: String,
+    pub operation: String,
+    pub client_reference: Option<String>,
+    pub http_status: u16,
+    pub success: bool,
+    pub result_code: Option<i64>,
+    pub data: Option<Value>,
+    pub error: Option<String>,
+}
+
+struct WebhookConfig {
+    url: String,
+    max_attempts: u32,
+    dead_letter_path: Option<String>,
+    client: Client,
+}
+
+lazy_static! {
+    // RESULT_WEBHOOK_URL enables forwarding; RESULT_WEBHOOK_MAX_ATTEMPTS bounds
+    // retries and WEBHOOK_DEAD_LETTER_PATH keeps what could not be delivered
+    static ref WEBHOOK: Option<WebhookConfig> = load_config();
+}
+
+fn load_config() -> Option<WebhookConfig> {
+    let url = std::env::var("RESULT_WEBHOOK_URL")
+        .ok()
+        .map(|url| url.trim().to_string())
+        .filter(|url| !url.is_empty())?;
+
+    let max_attempts = std::env::var("RESULT_WEBHOOK_MAX_ATTEMPTS")
+        .ok()
+        .and_then(|value| value.parse::<u32>().ok())
+        .filter(|attempts| *attempts > 0)
+        .unwrap_or(DEFAULT_MAX_ATTEMPTS);
+
+    let client = match Client::builder().timeout(DELIVERY_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(e) => {
+            error!(
+                "Failed to build webhook client, result forwarding disabled: {}",
+                e
+            );
+            return None;
+        }
+    };
+
+    Some(WebhookConfig {
+        url,
+        max_attempts,
+        dead_letter_path: std::env::var("WEBHOOK_DEAD_LETTER_PATH")
+            .ok()
+            .filter(|path| !path.is_empty()),
+        client,
+    })
+}
+
+/// Forward a result in the background. Returns immediately and never fails;
+/// the API response must not depend on the receiver being up.
+pub fn notify(notification: ResultNotification) {
+    let Some(config) = WEBHOOK.as_ref() else {
+        return;
+    };
+
+    tokio::spawn(async move {
+        if let Err(e) = deliver(config, &notification).await {
+            metrics::record_webhook_dead_letter();
+            dead_letter(config, &notification, &e);
+        }
+    });
+}
+
+async fn deliver(config: &WebhookConfig, notification: &ResultNotification) -> Result<(), String> {
+    let mut backoff = INITIAL_BACKOFF;
+    let mut last_error = String::new();
+
+    for attempt in 1..=config.max_attempts {
+        let result = config
+            .client
+            .post(&config.url)
+            .header("x-correlation-id", &notification.correlation_id)
+            .json(notification)
+            .send()
+            .await;
+
+        match result {
+            Ok(response) if response.status().is_success() => {
+                debug!(
+                    "Delivered {} result {} on attempt {}",
+                    notification.operation, notification.correlation_id, attempt
+                );
+                return Ok(());
+            }
+            Ok(response) => last_error = format!("receiver answered HTTP {}", response.status()),
+            Err(e) => last_error = e.to_string(),
+        }
+
+        if attempt < config.max_attempts {
+            warn!(
+                "Webhook delivery of {} failed (attempt {}/{}): {}",
+                notification.correlation_id, attempt, config.max_attempts, last_error
+            );
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+
+    Err(last_error)
+}
+
+// Keep undeliverable results for replay, with personal data redacted
+fn dead_letter(config: &WebhookConfig, notification: &ResultNotification, reason: &str) {
+    let mut redacted = notification.clone();
+    redacted.data = redacted.data.as_ref().map(redaction::redact_value);
+    let line = serde_json::to_string(&redacted).unwrap_or_default();
+
+    error!(
+        "Giving up on webhook delivery of {} after {} attempts: {}",
+        notification.correlation_id, config.max_attempts, reason
+    );
+
+    let Some(path) = &config.dead_letter_path else {
+        error!("Undelivered result: {}", line);
+        return;
+    };
+
+    let written = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| writeln!(file, "{}", line));
+    if let Err(e) = written {
+        error!(
+            "Failed to write dead letter to {}: {}. Undelivered result: {}",
+            path, e, line
+        );
+    }
+}
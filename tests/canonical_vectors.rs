@@ -0,0 +1,64 @@
+//! Canonicalization against the golden vectors in
+//! `tests/data/canonical_vectors.json`, so a change that alters what gets
+//! signed fails `cargo test` before Plexo rejects it.
+//!
+//! Vectors marked `"clean": true` go through the payload cleaning that runs
+//! before signing (null removal, amount formatting); the rest are passed to
+//! `canonize_json` as they are.
+//!
+//! Strings containing quotes, backslashes or control characters have no
+//! vector: `canonize_json` writes them unescaped, which is ambiguous, and
+//! that output has not been confirmed against Plexo's canonicalizer.
+
+use plexo_back::services::crypto::canonize_json;
+use plexo_back::services::plexo_service::canonical_preview;
+use serde::Deserialize;
+use serde_json::Value;
+
+const VECTORS: &str = include_str!("data/canonical_vectors.json");
+
+#[derive(Deserialize)]
+struct Vector {
+    name: String,
+    input: Value,
+    canonical: String,
+    #[serde(default)]
+    clean: bool,
+}
+
+#[test]
+fn canonical_vectors() {
+    let vectors: Vec<Vector> = serde_json::from_str(VECTORS).expect("vectors parse");
+
+    let failures: Vec<String> = vectors
+        .iter()
+        .filter_map(|vector| {
+            let actual = if vector.clean {
+                canonical_preview(&vector.input)
+                    .map(|preview| preview.canonical)
+                    .map_err(|e| e.to_string())
+            } else {
+                canonize_json(&vector.input).map_err(|e| e.to_string())
+            };
+            match actual {
+                Ok(actual) if actual == vector.canonical => None,
+                // Quoted, so control characters and trailing spaces show up
+                Ok(actual) => Some(format!(
+                    "{}\n  expected: {}\n  actual:   {}",
+                    vector.name,
+                    Value::from(vector.canonical.as_str()),
+                    Value::from(actual)
+                )),
+                Err(e) => Some(format!("{}\n  error: {}", vector.name, e)),
+            }
+        })
+        .collect();
+
+    assert!(
+        failures.is_empty(),
+        "{} of {} vectors failed:\n{}",
+        failures.len(),
+        vectors.len(),
+        failures.join("\n")
+    );
+}
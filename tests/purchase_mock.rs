@@ -0,0 +1,100 @@
+//! `/api/purchase` through the full app against the mock gateway, one test
+//! per scenario the mock picks from the reference: approved, declined and a
+//! gateway too slow to answer.
+
+use actix_web::http::StatusCode;
+use actix_web::test;
+use plexo_back::api::app::{build_app, AppConfig};
+use plexo_back::services::middleware::ServiceAuthConfig;
+use plexo_back::services::plexo_service::PlexoClient;
+use plexo_back::services::{crypto, mock_plexo};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+const SERVICE_KEY: &str = "purchase-test-key";
+
+// Purchase `reference` through an app wired to a fresh mock gateway and
+// return the HTTP status with the decoded envelope
+async fn purchase(reference: &str, timeout_ms: Option<u64>) -> (StatusCode, Value) {
+    let (mock, handle) = mock_plexo::start("127.0.0.1:0".parse().unwrap()).unwrap();
+    std::env::set_var("SIGNER", "noop");
+    crypto::init().unwrap();
+    let client = PlexoClient::new(
+        &format!("http://{}", mock),
+        reqwest::Client::new(),
+        crypto::get_crypto_service().unwrap(),
+    );
+    let config = AppConfig::new(
+        Arc::new(client),
+        ServiceAuthConfig::new(SERVICE_KEY.to_string(), "purchase-test"),
+    );
+    let app = test::init_service(build_app(config)).await;
+
+    let mut request = test::TestRequest::post()
+        .uri("/api/purchase")
+        .insert_header(("x-service-key", SERVICE_KEY))
+        .set_json(purchase_body(reference));
+    if let Some(timeout_ms) = timeout_ms {
+        request = request.insert_header(("x-timeout-ms", timeout_ms.to_string()));
+    }
+    let response = test::call_service(&app, request.to_request()).await;
+    let status = response.status();
+    let body = test::read_body_json(response).await;
+
+    handle.stop(false).await;
+    (status, body)
+}
+
+fn purchase_body(reference: &str) -> Value {
+    json!({
+        "Client": "test",
+        "Request": {
+            "ClientReferenceId": reference,
+            "CurrencyId": 858,
+            "FinancialInclusion": { "BilledAmount": 100, "TaxedAmount": 80, "Type": 1 },
+            "Installments": 1,
+            "Items": [{ "Amount": 100, "ClientItemReferenceId": "item-1" }],
+            "PaymentInstrumentInput": {
+                "InstrumentToken": "token-1",
+                "UseExtendedClientCreditIfAvailable": false,
+            },
+        },
+    })
+}
+
+#[actix_web::test]
+async fn approved_purchase() {
+    let (status, body) = purchase("order-approved", None).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["success"], true);
+    assert_eq!(body["outcome"], "Approved");
+    assert_eq!(body["data"]["result_code"], 0);
+    assert_eq!(
+        body["data"]["response"]["ClientReferenceId"],
+        "order-approved"
+    );
+}
+
+#[actix_web::test]
+async fn declined_purchase() {
+    let (status, body) = purchase("order-decline", None).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["outcome"], "Declined");
+    assert_eq!(
+        body["data"]["result_code"],
+        mock_plexo::MOCK_DECLINED_RESULT_CODE
+    );
+    assert_eq!(body["data"]["error_message"], "Declined by mock issuer");
+}
+
+#[actix_web::test]
+async fn timed_out_purchase() {
+    let (status, body) = purchase("order-timeout", Some(500)).await;
+
+    assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+    assert_eq!(body["success"], false);
+    assert_eq!(body["outcome"], "Error");
+    assert_eq!(body["error_code"], "PLEXO_TIMEOUT");
+}
@@ -0,0 +1,56 @@
+//! `/api/status` through the full app, as built by `build_app`, against the
+//! mock gateway with stub signing.
+
+use actix_web::http::StatusCode;
+use actix_web::test;
+use plexo_back::api::app::{build_app, AppConfig};
+use plexo_back::services::middleware::ServiceAuthConfig;
+use plexo_back::services::plexo_service::PlexoClient;
+use plexo_back::services::{crypto, mock_plexo};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+const SERVICE_KEY: &str = "status-test-key";
+
+#[actix_web::test]
+async fn status_reports_the_transaction_from_plexo() {
+    let (mock, handle) = mock_plexo::start("127.0.0.1:0".parse().unwrap()).unwrap();
+    std::env::set_var("PLEXO_BASE_URL", format!("http://{}", mock));
+    std::env::set_var("SIGNER", "noop");
+    crypto::init().unwrap();
+    let client = Arc::new(PlexoClient::from_env().unwrap());
+    let config = AppConfig::new(
+        client,
+        ServiceAuthConfig::new(SERVICE_KEY.to_string(), "status-test"),
+    );
+    let app = test::init_service(build_app(config)).await;
+
+    let request = test::TestRequest::post()
+        .uri("/api/status")
+        .insert_header(("x-service-key", SERVICE_KEY))
+        .set_json(json!({
+            "Client": "test",
+            "Request": { "ClientReferenceId": "order-1" },
+        }))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(body["success"], true);
+    assert_eq!(body["outcome"], "Approved");
+    assert_eq!(body["data"]["response"]["Status"], "Approved");
+    assert_eq!(body["data"]["response"]["MetaReference"], "order-1");
+
+    let unauthenticated = test::TestRequest::post()
+        .uri("/api/status")
+        .set_json(json!({
+            "Client": "test",
+            "Request": { "ClientReferenceId": "order-1" },
+        }))
+        .to_request();
+    let response = test::call_service(&app, unauthenticated).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    handle.stop(true).await;
+}